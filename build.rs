@@ -0,0 +1,32 @@
+use std::process::Command;
+
+
+/// Run git with the given arguments, returning its output if successful.
+fn git(args: &[&str]) -> Option<String> {
+	let output = Command::new("git")
+		.args(args)
+		.output()
+		.ok()
+		.filter(|output| output.status.success())?;
+
+	Some(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
+
+/// Embed the git revision of the build, for the manifest of a run. Builds with changes not
+/// committed are marked as dirty.
+fn main() {
+	let revision = match git(&["rev-parse", "HEAD"]) {
+		Some(revision) => match git(&["status", "--porcelain", "--untracked-files=no"]) {
+			Some(status) if status.is_empty() => revision,
+			_ => format!("{}-dirty", revision),
+		},
+		None => "unknown".to_owned(),
+	};
+
+	println!("cargo:rustc-env=GIT_REVISION={}", revision);
+
+	for path in &[".git/HEAD", ".git/refs", ".git/index", "src", "Cargo.toml"] {
+		println!("cargo:rerun-if-changed={}", path);
+	}
+}
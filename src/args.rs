@@ -1,3 +1,9 @@
+use std::{
+	collections::HashSet,
+	path::{Path, PathBuf},
+	time,
+};
+
 use clap::{
 	clap_app,
 	crate_authors,
@@ -6,117 +12,763 @@ use clap::{
 	value_t
 };
 
-use crate::data;
+use dci::ItemSet as _;
+
+use anyhow::Context;
 
+use icpsr_36404_analysis::{
+	classifier,
+	data::{
+		self,
+		sample::{self, Sampling},
+		weight::Weighting,
+		Grouping,
+	},
+	grid::Dimension,
+	stability,
+	table::{self, Table},
+	Constraints,
+	Filter,
+	ItemSet,
+};
 
+use crate::{
+	config::Config,
+	serve,
+};
+
+
+/// The subcommands that read the original dataset do so from stdin, unless an input file
+/// is given.
 #[derive(Debug, Clone, PartialEq)]
 pub enum Command {
 	Help(Box<str>),
 	Version(Box<str>),
 	Run {
-		min_sup_ratio: f64,
-		options: Options,
+		mining: Mining,
+		options: Filter,
+		grouping: Grouping,
+		sampling: Option<Sampling>,
+		weighting: Option<Weighting>,
+		/// The study schema, or none for ICPSR 36404.
+		schema: Option<PathBuf>,
+		input: Option<PathBuf>,
 	},
 	Save {
-		options: Options,
+		options: Filter,
+		grouping: Grouping,
+		sampling: Option<Sampling>,
+		input: Option<PathBuf>,
+		/// Output the aligned layout instead of MessagePack.
+		aligned: bool,
 	},
 	Load {
-		min_sup_ratio: f64,
+		mining: Mining,
+		/// The saved matrix, or none for stdin.
+		input: Option<PathBuf>,
+	},
+	Cache {
+		input: Option<PathBuf>,
+	},
+	Serve {
+		/// The saved matrix, or none for stdin.
+		input: Option<PathBuf>,
+		settings: serve::Settings,
 	},
 	Distribution {
-		options: Options,
+		options: Filter,
+		grouping: Grouping,
+		sampling: Option<Sampling>,
+		weighting: Option<Weighting>,
+		table: Table,
+		/// The study schema, or none for ICPSR 36404.
+		schema: Option<PathBuf>,
+		input: Option<PathBuf>,
+	},
+	Classify {
+		parameters: classifier::Parameters,
+		folds: usize,
+		options: Filter,
+		grouping: Grouping,
+		sampling: Option<Sampling>,
+		input: Option<PathBuf>,
+	},
+	Stability {
+		mining: Mining,
+		parameters: stability::Parameters,
+		options: Filter,
+		grouping: Grouping,
+		input: Option<PathBuf>,
+	},
+	Export {
+		/// The output path, without extension.
+		path: PathBuf,
+		options: Filter,
+		grouping: Grouping,
+		sampling: Option<Sampling>,
+		input: Option<PathBuf>,
+	},
+	Report {
+		mining: Mining,
+		min_confidence: f64,
+		options: Filter,
+		grouping: Grouping,
+		sampling: Option<Sampling>,
+		input: Option<PathBuf>,
+	},
+	Grid {
+		mining: Mining,
+		/// The attributes whose values are combined into the cells.
+		dimensions: Vec<Dimension>,
+		options: Filter,
+		grouping: Grouping,
+		/// The directory of the results.
+		output: PathBuf,
+		input: Option<PathBuf>,
+	},
+	Query {
+		itemset: ItemSet,
+		/// Output the ids of the covered records.
+		ids: bool,
+		/// Output the distribution of the covered records.
+		distribution: bool,
+		options: Filter,
+		grouping: Grouping,
+		sampling: Option<Sampling>,
+		input: Option<PathBuf>,
+	},
+	Repl {
+		options: Filter,
+		grouping: Grouping,
+		sampling: Option<Sampling>,
+		/// The input is required, as the commands are read from stdin.
+		input: PathBuf,
+	},
+	Diff {
+		/// The result files to compare.
+		before: PathBuf,
+		after: PathBuf,
+	},
+	Codebook {
+		setup: PathBuf,
+		/// Check the dataset for codes absent from the codebook.
+		data: bool,
+		/// Output a schema instead of the report.
+		schema: bool,
+	},
+	/// The commands of the profiles of a configuration file, by profile, run in turn.
+	Batch {
+		commands: Vec<(Box<str>, Command)>,
 	},
 }
 
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub struct Options {
-	pub recidivists: bool,
-	pub sex: Option<data::Sex>,
-	pub admission_type: Option<data::AdmissionType>,
-	pub race: Option<data::Race>,
+impl Command {
+	/// The input file, or none if read from stdin.
+	pub fn input(&self) -> Option<&Path> {
+		match self {
+			Command::Run { input, .. }
+			| Command::Save { input, .. }
+			| Command::Load { input, .. }
+			| Command::Cache { input }
+			| Command::Serve { input, .. }
+			| Command::Distribution { input, .. }
+			| Command::Classify { input, .. }
+			| Command::Stability { input, .. }
+			| Command::Export { input, .. }
+			| Command::Report { input, .. }
+			| Command::Grid { input, .. }
+			| Command::Query { input, .. } => input.as_deref(),
+			Command::Repl { input, .. } => Some(input),
+			Command::Help(_)
+			| Command::Version(_)
+			| Command::Diff { .. }
+			| Command::Codebook { .. }
+			| Command::Batch { .. } => None,
+		}
+	}
 }
 
 
-pub fn parse(args: impl Iterator<Item = String>) -> anyhow::Result<Command> {
-	let mut app = clap_app!(
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mining {
+	pub min_sup_ratio: f64,
+	pub compress: bool,
+	pub constraints: Constraints,
+	pub export_lattice: Option<PathBuf>,
+	pub table: Table,
+}
+
+
+/// How to label the output.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Localization {
+	/// The locale, or none for the raw labels.
+	pub lang: Option<Box<str>>,
+	/// The label catalog file, or none for the built-in catalog.
+	pub labels: Option<PathBuf>,
+}
+
+
+/// The global args, as defined in `app`.
+const GLOBAL_ARGS: &[&str] = &["lang", "labels", "config", "profile", "manifest"];
+
+
+/// The args of each subcommand, as defined in `app`, to apply the options of a profile only
+/// to the subcommands that define them.
+const SUBCOMMAND_ARGS: &[(&str, &[&str])] = &[
+	("distribution", &["input", "schema", "format", "columns", "limit", "caption", "recidivists", "external", "sample", "stratify_by", "seed", "weights", "weight_by", "marginals", "sex", "admission_type", "race"]),
+	("run", &["input", "min_sup", "schema", "compress", "must_contain", "must_not_contain", "max_length", "export_lattice", "format", "columns", "limit", "caption", "recidivists", "external", "sample", "stratify_by", "seed", "weights", "weight_by", "marginals", "sex", "admission_type", "race"]),
+	("save", &["input", "aligned", "recidivists", "external", "sample", "stratify_by", "seed", "sex", "admission_type", "race"]),
+	("cache", &["input"]),
+	("export", &["input", "path", "recidivists", "external", "sample", "stratify_by", "seed", "sex", "admission_type", "race"]),
+	("classify", &["input", "target", "min_sup", "min_confidence", "folds", "max_length", "recidivists", "external", "sample", "stratify_by", "seed", "sex", "admission_type", "race"]),
+	("stability", &["input", "min_sup", "resamples", "subsample", "seed", "must_contain", "must_not_contain", "max_length", "format", "limit", "caption", "recidivists", "external", "sex", "admission_type", "race"]),
+	("report", &["input", "min_sup", "min_confidence", "must_contain", "must_not_contain", "max_length", "limit", "recidivists", "external", "sample", "stratify_by", "seed", "sex", "admission_type", "race"]),
+	("grid", &["input", "min_sup", "by", "output", "must_contain", "must_not_contain", "max_length", "format", "columns", "limit", "recidivists", "external", "sex", "admission_type", "race"]),
+	("query", &["input", "items", "ids", "distribution", "recidivists", "external", "sample", "stratify_by", "seed", "sex", "admission_type", "race"]),
+	("repl", &["input", "recidivists", "external", "sample", "stratify_by", "seed", "sex", "admission_type", "race"]),
+	("serve", &["input", "address", "timeout", "cache_size", "workers", "allow_origin"]),
+	("diff", &["before", "after"]),
+	("codebook", &["setup", "data", "schema"]),
+	("load", &["input", "min_sup", "compress", "must_contain", "must_not_contain", "max_length", "export_lattice", "format", "columns", "limit", "caption"]),
+];
+
+
+fn app() -> clap::App<'static, 'static> {
+	clap_app!(
 		analyzer =>
 			(version: crate_version!())
 			(author: crate_authors!())
 			(about: crate_description!())
 
+			(@arg lang: --lang +takes_value +global "the locale for the output labels, e.g. en or pt")
+			(@arg labels: --labels +takes_value +global requires[lang] "the label catalog file (TOML or JSON)")
+			(@arg config: --config +takes_value +global "read the analysis profiles from the given file (TOML)")
+			(@arg profile: --profile +takes_value +global requires[config] "add the options of the given profile to those given, which take precedence (by default, run runs every profile in turn)")
+			(@arg manifest: --manifest +takes_value +global "write a manifest of the run to the given file (JSON): the version, the command, the hashes of the input and output, the record counts after each filter, and the timings")
+
 			(@subcommand distribution =>
 				(about: "load the original dataset from stdin and display the data distribution")
+				(@arg input: --input +takes_value "read the original dataset from the given file (.tsv, .sav, .dta or .cache), instead of stdin")
+				(@arg schema: --schema +takes_value conflicts_with[recidivists external sample stratify_by seed weights weight_by marginals sex admission_type race] "describe the dataset with the given schema file (TOML), instead of ICPSR 36404")
+				(@arg format: --format +takes_value possible_value[text latex markdown] default_value("text") "the output format")
+				(@arg columns: --columns +takes_value +use_delimiter possible_value[support percentage] default_value("support,percentage") "the table columns")
+				(@arg limit: --limit +takes_value "the maximum number of values of each attribute")
+				(@arg caption: --caption +takes_value "the table caption, or the first line of the text")
 				(@arg recidivists: --recidivists "whether to include only recidivists")
+				(@arg external: --external requires[recidivists] "group the records of each inmate by sorting them on disk, instead of in memory")
+				(@arg sample: --sample +takes_value "mine a random sample of the accepted records, given by a percentage (e.g. 5%) or a number of records")
+				(@arg stratify_by: --("stratify-by") +takes_value requires[sample] possible_value[sex admission_type offense_type sentence race age time_served release_type state] "sample each value of the given attribute in proportion to its number of records")
+				(@arg seed: --seed +takes_value requires[sample] "the seed of the random sample")
+				(@arg weights: --weights +takes_value conflicts_with[weight_by] "weight the records by the given column of the dataset")
+				(@arg weight_by: --("weight-by") +takes_value +use_delimiter requires[marginals] possible_value[sex admission_type offense_type sentence race age time_served release_type state] "weight the records by raking the given attributes to the marginal totals")
+				(@arg marginals: --marginals +takes_value requires[weight_by] "the marginal totals of the attributes to weight by (TOML)")
 				(@arg sex: --sex +takes_value possible_value[male female] "include only the given sex")
 				(@arg admission_type: --("admission-type") +takes_value possible_value[parole new other] "include only the given admission type")
 				(@arg race: --race +takes_value possible_value[black white hispanic other] "include only the given race"))
 
 			(@subcommand run =>
 				(about: "runs the entire pipeline")
-				(@arg min_sup: +required "the minimum support ratio ([0, 1.0])")
+				(@arg input: --input +takes_value "read the original dataset from the given file (.tsv, .sav, .dta or .cache), instead of stdin")
+				(@arg min_sup: "the minimum support ratio ([0, 1.0])")
+				(@arg schema: --schema +takes_value conflicts_with[compress must_contain must_not_contain max_length export_lattice recidivists external sample stratify_by seed weights weight_by marginals sex admission_type race] "describe the dataset with the given schema file (TOML), instead of ICPSR 36404")
+				(@arg compress: --compress "select a characteristic pattern set through compression (text format only)")
+				(@arg must_contain: --("must-contain") +takes_value +multiple number_of_values(1) "include only itemsets with the given item")
+				(@arg must_not_contain: --("must-not-contain") +takes_value +multiple number_of_values(1) "exclude the given item from all itemsets")
+				(@arg max_length: --("max-length") +takes_value "the maximum itemset length")
+				(@arg export_lattice: --("export-lattice") +takes_value "write the closed itemset lattice to <path>.dot and <path>.json")
+				(@arg format: --format +takes_value possible_value[text latex markdown json] default_value("text") "the output format. json outputs a result file, to be compared by diff")
+				(@arg columns: --columns +takes_value +use_delimiter possible_value[support percentage lift estimate] default_value("support,percentage") "the table columns")
+				(@arg limit: --limit +takes_value "the maximum number of table rows")
+				(@arg caption: --caption +takes_value "the table caption")
 				(@arg recidivists: --recidivists "whether to include only recidivists")
+				(@arg external: --external requires[recidivists] "group the records of each inmate by sorting them on disk, instead of in memory")
+				(@arg sample: --sample +takes_value "mine a random sample of the accepted records, given by a percentage (e.g. 5%) or a number of records")
+				(@arg stratify_by: --("stratify-by") +takes_value requires[sample] possible_value[sex admission_type offense_type sentence race age time_served release_type state] "sample each value of the given attribute in proportion to its number of records")
+				(@arg seed: --seed +takes_value requires[sample] "the seed of the random sample")
+				(@arg weights: --weights +takes_value conflicts_with[weight_by compress export_lattice] "weight the records by the given column of the dataset")
+				(@arg weight_by: --("weight-by") +takes_value +use_delimiter requires[marginals] conflicts_with[compress export_lattice] possible_value[sex admission_type offense_type sentence race age time_served release_type state] "weight the records by raking the given attributes to the marginal totals")
+				(@arg marginals: --marginals +takes_value requires[weight_by] "the marginal totals of the attributes to weight by (TOML)")
 				(@arg sex: --sex +takes_value possible_value[male female] "include only the given sex")
 				(@arg admission_type: --("admission-type") +takes_value possible_value[parole new other] "include only the given admission type")
 				(@arg race: --race +takes_value possible_value[black white hispanic other] "include only the given race"))
 
 			(@subcommand save =>
 				(about: "load the original dataset from stdin and output the serialized matrix to stdout")
+				(@arg input: --input +takes_value "read the original dataset from the given file (.tsv, .sav, .dta or .cache), instead of stdin")
+				(@arg aligned: --aligned "output the aligned binary layout, which is memory-mapped by load --input, instead of MessagePack")
+				(@arg recidivists: --recidivists "whether to include only recidivists")
+				(@arg external: --external requires[recidivists] "group the records of each inmate by sorting them on disk, instead of in memory")
+				(@arg sample: --sample +takes_value "mine a random sample of the accepted records, given by a percentage (e.g. 5%) or a number of records")
+				(@arg stratify_by: --("stratify-by") +takes_value requires[sample] possible_value[sex admission_type offense_type sentence race age time_served release_type state] "sample each value of the given attribute in proportion to its number of records")
+				(@arg seed: --seed +takes_value requires[sample] "the seed of the random sample")
+				(@arg sex: --sex +takes_value possible_value[male female] "include only the given sex")
+				(@arg admission_type: --("admission-type") +takes_value possible_value[parole new other] "include only the given admission type")
+				(@arg race: --race +takes_value possible_value[black white hispanic other] "include only the given race"))
+
+			(@subcommand cache =>
+				(about: "load the original dataset from stdin and output the record cache to stdout, to be used as input with the .cache extension")
+				(@arg input: --input +takes_value "read the original dataset from the given file (.tsv, .sav or .dta), instead of stdin"))
+
+			(@subcommand export =>
+				(about: "load the original dataset from stdin and write the filtered records to <path>.parquet and <path>.arrow")
+				(@arg input: --input +takes_value "read the original dataset from the given file (.tsv, .sav, .dta or .cache), instead of stdin")
+				(@arg path: +required "the output path, without extension")
+				(@arg recidivists: --recidivists "whether to include only recidivists")
+				(@arg external: --external requires[recidivists] "group the records of each inmate by sorting them on disk, instead of in memory")
+				(@arg sample: --sample +takes_value "mine a random sample of the accepted records, given by a percentage (e.g. 5%) or a number of records")
+				(@arg stratify_by: --("stratify-by") +takes_value requires[sample] possible_value[sex admission_type offense_type sentence race age time_served release_type state] "sample each value of the given attribute in proportion to its number of records")
+				(@arg seed: --seed +takes_value requires[sample] "the seed of the random sample")
+				(@arg sex: --sex +takes_value possible_value[male female] "include only the given sex")
+				(@arg admission_type: --("admission-type") +takes_value possible_value[parole new other] "include only the given admission type")
+				(@arg race: --race +takes_value possible_value[black white hispanic other] "include only the given race"))
+
+			(@subcommand classify =>
+				(about: "load the original dataset from stdin, build a rule-based classifier and evaluate it")
+				(@arg input: --input +takes_value "read the original dataset from the given file (.tsv, .sav, .dta or .cache), instead of stdin")
+				(@arg target: +required possible_value[sex admission_type offense_type sentence race age time_served release_type] "the attribute to be predicted")
+				(@arg min_sup: "the minimum support ratio ([0, 1.0])")
+				(@arg min_confidence: --("min-confidence") +takes_value default_value("0.5") "the minimum rule confidence ([0, 1.0])")
+				(@arg folds: --folds +takes_value default_value("10") "the number of cross-validation folds")
+				(@arg max_length: --("max-length") +takes_value "the maximum rule antecedent length")
 				(@arg recidivists: --recidivists "whether to include only recidivists")
+				(@arg external: --external requires[recidivists] "group the records of each inmate by sorting them on disk, instead of in memory")
+				(@arg sample: --sample +takes_value "mine a random sample of the accepted records, given by a percentage (e.g. 5%) or a number of records")
+				(@arg stratify_by: --("stratify-by") +takes_value requires[sample] possible_value[sex admission_type offense_type sentence race age time_served release_type state] "sample each value of the given attribute in proportion to its number of records")
+				(@arg seed: --seed +takes_value requires[sample] "the seed of the random sample")
 				(@arg sex: --sex +takes_value possible_value[male female] "include only the given sex")
 				(@arg admission_type: --("admission-type") +takes_value possible_value[parole new other] "include only the given admission type")
 				(@arg race: --race +takes_value possible_value[black white hispanic other] "include only the given race"))
 
+			(@subcommand stability =>
+				(about: "load the original dataset from stdin and assess the stability of the closed itemsets by mining resamples of the transactions")
+				(@arg input: --input +takes_value "read the original dataset from the given file (.tsv, .sav, .dta or .cache), instead of stdin")
+				(@arg min_sup: "the minimum support ratio ([0, 1.0])")
+				(@arg resamples: --resamples +takes_value default_value("100") "the number of resamples")
+				(@arg subsample: --subsample +takes_value "draw the given ratio of the transactions ((0, 1.0]) without replacement, instead of bootstrap resamples")
+				(@arg seed: --seed +takes_value "the seed of the resamples")
+				(@arg must_contain: --("must-contain") +takes_value +multiple number_of_values(1) "include only itemsets with the given item")
+				(@arg must_not_contain: --("must-not-contain") +takes_value +multiple number_of_values(1) "exclude the given item from all itemsets")
+				(@arg max_length: --("max-length") +takes_value "the maximum itemset length")
+				(@arg format: --format +takes_value possible_value[text latex markdown] default_value("text") "the output format")
+				(@arg limit: --limit +takes_value "the maximum number of table rows")
+				(@arg caption: --caption +takes_value "the table caption")
+				(@arg recidivists: --recidivists "whether to include only recidivists")
+				(@arg external: --external requires[recidivists] "group the records of each inmate by sorting them on disk, instead of in memory")
+				(@arg sex: --sex +takes_value possible_value[male female] "include only the given sex")
+				(@arg admission_type: --("admission-type") +takes_value possible_value[parole new other] "include only the given admission type")
+				(@arg race: --race +takes_value possible_value[black white hispanic other] "include only the given race"))
+
+			(@subcommand report =>
+				(about: "load the original dataset from stdin, mine it and output a report in HTML, with the distribution, the top itemsets and rules, and histograms of their supports")
+				(@arg input: --input +takes_value "read the original dataset from the given file (.tsv, .sav, .dta or .cache), instead of stdin")
+				(@arg min_sup: "the minimum support ratio ([0, 1.0])")
+				(@arg min_confidence: --("min-confidence") +takes_value default_value("0.8") "the minimum rule confidence ([0, 1.0])")
+				(@arg must_contain: --("must-contain") +takes_value +multiple number_of_values(1) "include only itemsets with the given item")
+				(@arg must_not_contain: --("must-not-contain") +takes_value +multiple number_of_values(1) "exclude the given item from all itemsets")
+				(@arg max_length: --("max-length") +takes_value "the maximum itemset length")
+				(@arg limit: --limit +takes_value default_value("100") "the maximum number of itemsets and rules in the tables")
+				(@arg recidivists: --recidivists "whether to include only recidivists")
+				(@arg external: --external requires[recidivists] "group the records of each inmate by sorting them on disk, instead of in memory")
+				(@arg sample: --sample +takes_value "mine a random sample of the accepted records, given by a percentage (e.g. 5%) or a number of records")
+				(@arg stratify_by: --("stratify-by") +takes_value requires[sample] possible_value[sex admission_type offense_type sentence race age time_served release_type state] "sample each value of the given attribute in proportion to its number of records")
+				(@arg seed: --seed +takes_value requires[sample] "the seed of the random sample")
+				(@arg sex: --sex +takes_value possible_value[male female] "include only the given sex")
+				(@arg admission_type: --("admission-type") +takes_value possible_value[parole new other] "include only the given admission type")
+				(@arg race: --race +takes_value possible_value[black white hispanic other] "include only the given race"))
+
+			(@subcommand grid =>
+				(about: "load the original dataset from stdin, and mine the records of every combination of the values of the given attributes, in parallel")
+				(@arg input: --input +takes_value "read the original dataset from the given file (.tsv, .sav, .dta or .cache), instead of stdin")
+				(@arg min_sup: "the minimum support ratio of each cell ([0, 1.0])")
+				(@arg by: --by +takes_value +required +use_delimiter possible_value[sex admission_type race] "the attributes whose values are combined into the cells")
+				(@arg output: --output +takes_value +required "the directory of the results, with a subdirectory for each value of each attribute, and an index of the cells (index.json)")
+				(@arg must_contain: --("must-contain") +takes_value +multiple number_of_values(1) "include only itemsets with the given item")
+				(@arg must_not_contain: --("must-not-contain") +takes_value +multiple number_of_values(1) "exclude the given item from all itemsets")
+				(@arg max_length: --("max-length") +takes_value "the maximum itemset length")
+				(@arg format: --format +takes_value possible_value[text latex markdown json] default_value("json") "the format of the results. json outputs result files, to be compared by diff")
+				(@arg columns: --columns +takes_value +use_delimiter possible_value[support percentage lift estimate] default_value("support,percentage") "the table columns")
+				(@arg limit: --limit +takes_value "the maximum number of table rows")
+				(@arg recidivists: --recidivists "whether to include only recidivists")
+				(@arg external: --external requires[recidivists] "group the records of each inmate by sorting them on disk, instead of in memory")
+				(@arg sex: --sex +takes_value possible_value[male female] "include only the given sex, if not combined")
+				(@arg admission_type: --("admission-type") +takes_value possible_value[parole new other] "include only the given admission type, if not combined")
+				(@arg race: --race +takes_value possible_value[black white hispanic other] "include only the given race, if not combined"))
+
+			(@subcommand query =>
+				(about: "load the original dataset from stdin and compute the support of the given itemset")
+				(@arg input: --input +takes_value "read the original dataset from the given file (.tsv, .sav, .dta or .cache), instead of stdin")
+				(@arg items: +required +multiple "the items, given by label (e.g. Race::Black) or by field and value (e.g. race=Black)")
+				(@arg ids: --ids "output the inmate ids of the covered records")
+				(@arg distribution: --distribution "output the distribution of the covered records")
+				(@arg recidivists: --recidivists "whether to include only recidivists")
+				(@arg external: --external requires[recidivists] "group the records of each inmate by sorting them on disk, instead of in memory")
+				(@arg sample: --sample +takes_value "mine a random sample of the accepted records, given by a percentage (e.g. 5%) or a number of records")
+				(@arg stratify_by: --("stratify-by") +takes_value requires[sample] possible_value[sex admission_type offense_type sentence race age time_served release_type state] "sample each value of the given attribute in proportion to its number of records")
+				(@arg seed: --seed +takes_value requires[sample] "the seed of the random sample")
+				(@arg sex: --sex +takes_value possible_value[male female] "include only the given sex")
+				(@arg admission_type: --("admission-type") +takes_value possible_value[parole new other] "include only the given admission type")
+				(@arg race: --race +takes_value possible_value[black white hispanic other] "include only the given race"))
+
+			(@subcommand repl =>
+				(about: "load the original dataset once and run commands over it interactively, e.g. filter, mine and rules")
+				(@arg input: --input +takes_value +required "read the original dataset from the given file (.tsv, .sav, .dta or .cache)")
+				(@arg recidivists: --recidivists "whether to include only recidivists")
+				(@arg external: --external requires[recidivists] "group the records of each inmate by sorting them on disk, instead of in memory")
+				(@arg sample: --sample +takes_value "mine a random sample of the accepted records, given by a percentage (e.g. 5%) or a number of records")
+				(@arg stratify_by: --("stratify-by") +takes_value requires[sample] possible_value[sex admission_type offense_type sentence race age time_served release_type state] "sample each value of the given attribute in proportion to its number of records")
+				(@arg seed: --seed +takes_value requires[sample] "the seed of the random sample")
+				(@arg sex: --sex +takes_value possible_value[male female] "include only the given sex")
+				(@arg admission_type: --("admission-type") +takes_value possible_value[parole new other] "include only the given admission type")
+				(@arg race: --race +takes_value possible_value[black white hispanic other] "include only the given race"))
+
+			(@subcommand serve =>
+				(about: "load the serialized matrix from stdin and answer queries over HTTP in JSON: /distribution, /mine, /query and /rules")
+				(@arg input: --input +takes_value "read the serialized matrix from the given file, instead of stdin. Files in the aligned layout are memory-mapped")
+				(@arg address: --address +takes_value default_value("127.0.0.1:8080") "the address to listen on")
+				(@arg timeout: --timeout +takes_value default_value("60") "the maximum time to compute a response, in seconds")
+				(@arg cache_size: --("cache-size") +takes_value default_value("32") "the maximum number of cached mining results")
+				(@arg workers: --workers +takes_value "the maximum number of responses computed at once, by default the number of cores")
+				(@arg allow_origin: --("allow-origin") +takes_value "allow queries from the given origin in a browser, e.g. http://localhost:3000"))
+
+			(@subcommand diff =>
+				(about: "compare two result files, output by run or load with --format json, and report the added and removed itemsets, and the changes of support and rank")
+				(@arg before: +required "the first result file")
+				(@arg after: +required "the second result file"))
+
+			(@subcommand codebook =>
+				(about: "import the value labels from an ICPSR setup file (SPSS .sps or Stata .do) and validate them")
+				(@arg setup: +required "the setup file")
+				(@arg data: --data "load the original dataset from stdin and report the codes absent from the codebook")
+				(@arg schema: --schema conflicts_with[data] "output a schema file (TOML) with the value labels"))
+
 			(@subcommand load =>
 				(about: "load the serialized matrix from stdin and run the algorithm")
-				(@arg min_sup: +required "the minimum support ratio ([0, 1.0])"))
-	);
-
-	match app.get_matches_from_safe_borrow(args) {
-		Ok(matches) => Ok(
-			match matches.subcommand() {
-				("distribution", Some(matches)) => Command::Distribution {
-					options: parse_options(&matches),
-				},
-				("save", Some(matches)) => Command::Save {
-					options: parse_options(&matches),
-				},
-				("load", Some(matches)) => Command::Load {
-					min_sup_ratio: validate_min_sup(
-						value_t!(matches, "min_sup", f64)?
-					)?,
-				},
-				("run", Some(matches)) => Command::Run {
-					min_sup_ratio: validate_min_sup(
-						value_t!(matches, "min_sup", f64)?
-					)?,
-					options: parse_options(&matches),
-				},
-				_ => {
-					let mut out = Vec::new();
+				(@arg input: --input +takes_value "read the serialized matrix from the given file, instead of stdin. Files in the aligned layout are memory-mapped")
+				(@arg min_sup: "the minimum support ratio ([0, 1.0])")
+				(@arg compress: --compress "select a characteristic pattern set through compression (text format only)")
+				(@arg must_contain: --("must-contain") +takes_value +multiple number_of_values(1) "include only itemsets with the given item")
+				(@arg must_not_contain: --("must-not-contain") +takes_value +multiple number_of_values(1) "exclude the given item from all itemsets")
+				(@arg max_length: --("max-length") +takes_value "the maximum itemset length")
+				(@arg export_lattice: --("export-lattice") +takes_value "write the closed itemset lattice to <path>.dot and <path>.json")
+				(@arg format: --format +takes_value possible_value[text latex markdown json] default_value("text") "the output format. json outputs a result file, to be compared by diff")
+				(@arg columns: --columns +takes_value +use_delimiter possible_value[support percentage lift estimate] default_value("support,percentage") "the table columns")
+				(@arg limit: --limit +takes_value "the maximum number of table rows")
+				(@arg caption: --caption +takes_value "the table caption"))
+	)
+}
 
-					app.write_help(&mut out)?;
 
-					let help = String::from_utf8(out)?;
+/// Parse the command, its localization, and the path of its manifest, if any.
+pub fn parse(args: impl Iterator<Item = String>) -> anyhow::Result<(Command, Localization, Option<PathBuf>)> {
+	let args: Vec<String> = args.collect();
 
-					Command::Help(help.into())
-				},
-			}
-		),
+	match app().get_matches_from_safe(&args) {
+		Ok(matches) => Ok((
+			match global_value(&matches, "config") {
+				Some(path) => parse_profiles(&args, &matches, Path::new(path))?,
+				None => parse_command(&matches)?,
+			},
+			parse_localization(&matches),
+			global_value(&matches, "manifest").map(PathBuf::from),
+		)),
 
 		Err(error) => match error.kind {
-			clap::ErrorKind::HelpDisplayed => Ok(
-				Command::Help(error.message.into_boxed_str())
-			),
-			clap::ErrorKind::VersionDisplayed => Ok(
-				Command::Version(error.message.into_boxed_str())
-			),
+			clap::ErrorKind::HelpDisplayed => Ok((
+				Command::Help(error.message.into_boxed_str()),
+				Localization::default(),
+				None,
+			)),
+			clap::ErrorKind::VersionDisplayed => Ok((
+				Command::Version(error.message.into_boxed_str()),
+				Localization::default(),
+				None,
+			)),
 			_ => Err(error.into())
 		}
 	}
 }
 
 
+/// Parse the command of the chosen profile of the configuration file, or of every profile if
+/// none is chosen for run, adding the options of the profile to the given args.
+fn parse_profiles(args: &[String], matches: &clap::ArgMatches, path: &Path) -> anyhow::Result<Command> {
+	let (subcommand, subcommand_matches) = match matches.subcommand() {
+		(subcommand, Some(subcommand_matches)) => (subcommand, subcommand_matches),
+		_ => return parse_command(matches),
+	};
+
+	let config = Config::load(path)?;
+
+	let profiles = match global_value(matches, "profile") {
+		Some(name) => vec![(name, config.profile(name)?)],
+		None if subcommand == "run" => config.profiles
+			.iter()
+			.map(|(name, profile)| (name.as_str(), profile))
+			.collect(),
+		None => anyhow::bail!("{} requires a profile, given by --profile", subcommand),
+	};
+
+	// The top-level options of a profile apply only to the subcommands that define them, e.g.
+	// the minimum support only to the mining subcommands. Options that no subcommand defines
+	// are kept, so that they are reported as invalid.
+	let defined: HashSet<&str> = GLOBAL_ARGS
+		.iter()
+		.chain(subcommand_args(subcommand))
+		.copied()
+		.collect();
+
+	let known: HashSet<&str> = SUBCOMMAND_ARGS
+		.iter()
+		.flat_map(|(_, args)| args.iter().copied())
+		.collect();
+
+	let applies = |option: &str| defined.contains(option) || !known.contains(option);
+
+	let mut commands = Vec::new();
+
+	for (name, profile) in profiles {
+		let mut profile_args = args.to_vec();
+
+		for (option, value) in profile.options(subcommand, applies) {
+			// The options given in the command line take precedence.
+			if subcommand_matches.occurrences_of(option) == 0 {
+				profile_args.extend(option_args(option, value)?);
+			}
+		}
+
+		let command = app()
+			.get_matches_from_safe(profile_args)
+			.map_err(anyhow::Error::from)
+			.and_then(|matches| parse_command(&matches))
+			.with_context(|| format!("invalid profile: {}", name))?;
+
+		commands.push((name.into(), command));
+	}
+
+	match commands.len() {
+		0 => anyhow::bail!("no profiles in {}", path.display()),
+		1 => Ok(commands.remove(0).1),
+		_ => {
+			if commands.iter().any(|(_, command)| command.input().is_none()) {
+				anyhow::bail!("running every profile requires an input file, as stdin can only be read once");
+			}
+
+			let json = commands
+				.iter()
+				.filter(
+					|(_, command)| matches!(
+						command,
+						Command::Run { mining, .. } if mining.table.format == table::Format::Json
+					)
+				)
+				.count();
+
+			if json > 0 && json < commands.len() {
+				anyhow::bail!("running every profile with the json format requires every profile to use it");
+			}
+
+			Ok(Command::Batch { commands })
+		},
+	}
+}
+
+
+/// The args of the given subcommand, as defined in `app`.
+fn subcommand_args(subcommand: &str) -> &'static [&'static str] {
+	SUBCOMMAND_ARGS
+		.iter()
+		.find(|(name, _)| *name == subcommand)
+		.map_or(&[], |(_, args)| args)
+}
+
+
+/// The args of an option of a profile: a flag if true, none if false, and an occurrence for
+/// each value of an array. The minimum support is the only positional arg. Floats keep their
+/// decimal point, e.g. `1.0`, so that they are not read as integers.
+fn option_args(name: &str, value: &toml::Value) -> anyhow::Result<Vec<String>> {
+	let arg = |value: String| match name {
+		"min_sup" => value,
+		_ => format!("--{}={}", name.replace('_', "-"), value),
+	};
+
+	match value {
+		toml::Value::Boolean(true) => Ok(vec![format!("--{}", name.replace('_', "-"))]),
+		toml::Value::Boolean(false) => Ok(Vec::new()),
+		toml::Value::String(value) => Ok(vec![arg(value.clone())]),
+		toml::Value::Integer(value) => Ok(vec![arg(value.to_string())]),
+		toml::Value::Float(value) => Ok(vec![arg(format!("{:?}", value))]),
+		toml::Value::Array(values) => values
+			.iter()
+			.map(|value| option_args(name, value))
+			.collect::<anyhow::Result<Vec<_>>>()
+			.map(|args| args.concat()),
+		_ => anyhow::bail!("invalid value of {}: {}", name, value),
+	}
+}
+
+
+fn parse_command(matches: &clap::ArgMatches) -> anyhow::Result<Command> {
+	Ok(
+		match matches.subcommand() {
+			("distribution", Some(matches)) => Command::Distribution {
+				options: parse_options(matches),
+				grouping: parse_grouping(matches),
+				sampling: parse_sampling(matches)?,
+				weighting: parse_weighting(matches)?,
+				table: parse_table(matches)?,
+				schema: matches.value_of("schema").map(PathBuf::from),
+				input: matches.value_of("input").map(PathBuf::from),
+			},
+			("save", Some(matches)) => Command::Save {
+				options: parse_options(matches),
+				grouping: parse_grouping(matches),
+				sampling: parse_sampling(matches)?,
+				input: matches.value_of("input").map(PathBuf::from),
+				aligned: matches.is_present("aligned"),
+			},
+			("cache", Some(matches)) => Command::Cache {
+				input: matches.value_of("input").map(PathBuf::from),
+			},
+			("load", Some(matches)) => Command::Load {
+				mining: parse_mining(matches)?,
+				input: matches.value_of("input").map(PathBuf::from),
+			},
+			("classify", Some(matches)) => Command::Classify {
+				parameters: classifier::Parameters {
+					target: parse_target(matches.value_of("target")),
+					min_sup_ratio: parse_min_sup(matches)?,
+					min_confidence: validate_min_conf(
+						value_t!(matches, "min_confidence", f64)?
+					)?,
+					max_length: match matches.value_of("max_length") {
+						Some(_) => Some(value_t!(matches, "max_length", usize)?),
+						None => None,
+					},
+				},
+				folds: validate_folds(
+					value_t!(matches, "folds", usize)?
+				)?,
+				options: parse_options(matches),
+				grouping: parse_grouping(matches),
+				sampling: parse_sampling(matches)?,
+				input: matches.value_of("input").map(PathBuf::from),
+			},
+			("stability", Some(matches)) => Command::Stability {
+				mining: parse_mining(matches)?,
+				parameters: parse_stability(matches)?,
+				options: parse_options(matches),
+				grouping: parse_grouping(matches),
+				input: matches.value_of("input").map(PathBuf::from),
+			},
+			("export", Some(matches)) => Command::Export {
+				path: PathBuf::from(matches.value_of("path").expect("path is required")),
+				options: parse_options(matches),
+				grouping: parse_grouping(matches),
+				sampling: parse_sampling(matches)?,
+				input: matches.value_of("input").map(PathBuf::from),
+			},
+			("report", Some(matches)) => Command::Report {
+				mining: parse_mining(matches)?,
+				min_confidence: validate_min_conf(
+					value_t!(matches, "min_confidence", f64)?
+				)?,
+				options: parse_options(matches),
+				grouping: parse_grouping(matches),
+				sampling: parse_sampling(matches)?,
+				input: matches.value_of("input").map(PathBuf::from),
+			},
+			("grid", Some(matches)) => Command::Grid {
+				mining: parse_mining(matches)?,
+				dimensions: parse_dimensions(matches)?,
+				options: parse_options(matches),
+				grouping: parse_grouping(matches),
+				output: PathBuf::from(matches.value_of("output").expect("output is required")),
+				input: matches.value_of("input").map(PathBuf::from),
+			},
+			("query", Some(matches)) => Command::Query {
+				itemset: ItemSet::parse(
+					&matches
+						.values_of("items")
+						.into_iter()
+						.flatten()
+						.collect::<Vec<_>>()
+						.join(" ")
+				)?,
+				ids: matches.is_present("ids"),
+				distribution: matches.is_present("distribution"),
+				options: parse_options(matches),
+				grouping: parse_grouping(matches),
+				sampling: parse_sampling(matches)?,
+				input: matches.value_of("input").map(PathBuf::from),
+			},
+			("repl", Some(matches)) => Command::Repl {
+				options: parse_options(matches),
+				grouping: parse_grouping(matches),
+				sampling: parse_sampling(matches)?,
+				input: PathBuf::from(matches.value_of("input").expect("input is required")),
+			},
+			("serve", Some(matches)) => Command::Serve {
+				input: matches.value_of("input").map(PathBuf::from),
+				settings: serve::Settings {
+					address: matches.value_of("address").expect("address has a default").into(),
+					timeout: time::Duration::from_secs(value_t!(matches, "timeout", u64)?),
+					cache_size: value_t!(matches, "cache_size", usize)?,
+					workers: match matches.value_of("workers") {
+						Some(_) => match value_t!(matches, "workers", usize)? {
+							0 => anyhow::bail!("--workers must be at least 1"),
+							count => Some(count),
+						},
+						None => None,
+					},
+					allow_origin: matches.value_of("allow_origin").map(Into::into),
+				},
+			},
+			("diff", Some(matches)) => Command::Diff {
+				before: PathBuf::from(matches.value_of("before").expect("before is required")),
+				after: PathBuf::from(matches.value_of("after").expect("after is required")),
+			},
+			("codebook", Some(matches)) => Command::Codebook {
+				setup: PathBuf::from(matches.value_of("setup").expect("setup is required")),
+				data: matches.is_present("data"),
+				schema: matches.is_present("schema"),
+			},
+			("run", Some(matches)) => Command::Run {
+				mining: parse_mining(matches)?,
+				options: parse_options(matches),
+				grouping: parse_grouping(matches),
+				sampling: parse_sampling(matches)?,
+				weighting: parse_weighting(matches)?,
+				schema: matches.value_of("schema").map(PathBuf::from),
+				input: matches.value_of("input").map(PathBuf::from),
+			},
+			_ => {
+				let mut out = Vec::new();
+
+				app().write_help(&mut out)?;
+
+				let help = String::from_utf8(out)?;
+
+				Command::Help(help.into())
+			},
+		}
+	)
+}
+
+
+/// The minimum support is not required by clap, as it may be given by a profile instead.
+fn parse_min_sup(matches: &clap::ArgMatches) -> anyhow::Result<f64> {
+	if !matches.is_present("min_sup") {
+		anyhow::bail!("the minimum support is required, as an argument or by a profile");
+	}
+
+	validate_min_sup(
+		value_t!(matches, "min_sup", f64)?
+	)
+}
+
+
 fn validate_min_sup(min_sup: f64) -> anyhow::Result<f64> {
 	if (0.0 ..= 1.0).contains(&min_sup) {
 		Ok(min_sup)
@@ -129,8 +781,151 @@ fn validate_min_sup(min_sup: f64) -> anyhow::Result<f64> {
 }
 
 
-fn parse_options(matches: &clap::ArgMatches) -> Options {
-	Options {
+fn validate_min_conf(min_conf: f64) -> anyhow::Result<f64> {
+	if (0.0 ..= 1.0).contains(&min_conf) {
+		Ok(min_conf)
+	}
+	else {
+		Err(
+			anyhow::anyhow!("invalid minimum confidence: {}", min_conf)
+		)
+	}
+}
+
+
+fn validate_folds(folds: usize) -> anyhow::Result<usize> {
+	if folds >= 2 {
+		Ok(folds)
+	}
+	else {
+		Err(
+			anyhow::anyhow!("invalid number of folds: {}", folds)
+		)
+	}
+}
+
+
+fn parse_mining(matches: &clap::ArgMatches) -> anyhow::Result<Mining> {
+	let mining = Mining {
+		min_sup_ratio: parse_min_sup(matches)?,
+		compress: matches.is_present("compress"),
+		constraints: parse_constraints(matches)?,
+		export_lattice: matches.value_of("export_lattice").map(PathBuf::from),
+		table: parse_table(matches)?,
+	};
+
+	// The code table is only output as text.
+	if mining.compress && mining.table.format != table::Format::Text {
+		return Err(
+			anyhow::anyhow!("--compress only supports the text format")
+		);
+	}
+
+	Ok(mining)
+}
+
+
+fn parse_constraints(matches: &clap::ArgMatches) -> anyhow::Result<Constraints> {
+	fn parse_items(values: Option<clap::Values>) -> anyhow::Result<ItemSet> {
+		let mut itemset = ItemSet::empty();
+
+		for label in values.into_iter().flatten() {
+			let item = ItemSet
+				::item(label)
+				.ok_or_else(
+					|| anyhow::anyhow!("invalid item: {}", label)
+				)?;
+
+			itemset.add(item);
+		}
+
+		Ok(itemset)
+	}
+
+	let constraints = Constraints {
+		must_contain: parse_items(matches.values_of("must_contain"))?,
+		must_not_contain: parse_items(matches.values_of("must_not_contain"))?,
+		max_length: match matches.value_of("max_length") {
+			Some(_) => Some(value_t!(matches, "max_length", usize)?),
+			None => None,
+		},
+	};
+
+	let mut conflicts = constraints.must_contain.clone();
+	conflicts.remove_all(&constraints.must_not_contain);
+
+	if conflicts != constraints.must_contain {
+		return Err(
+			anyhow::anyhow!("items can't be both required and forbidden")
+		);
+	}
+
+	Ok(constraints)
+}
+
+
+/// The value of a global arg, which may be given either before or after the subcommand.
+fn global_value<'a>(matches: &'a clap::ArgMatches, name: &str) -> Option<&'a str> {
+	matches
+		.value_of(name)
+		.or_else(
+			|| matches
+				.subcommand()
+				.1
+				.and_then(|matches| matches.value_of(name))
+		)
+}
+
+
+fn parse_localization(matches: &clap::ArgMatches) -> Localization {
+	Localization {
+		lang: global_value(matches, "lang").map(Into::into),
+		labels: global_value(matches, "labels").map(PathBuf::from),
+	}
+}
+
+
+fn parse_table(matches: &clap::ArgMatches) -> anyhow::Result<Table> {
+	let format = match matches.value_of("format") {
+		Some("latex")    => table::Format::Latex,
+		Some("markdown") => table::Format::Markdown,
+		Some("json")     => table::Format::Json,
+		_                => table::Format::Text,
+	};
+
+	let columns = matches
+		.values_of("columns")
+		.into_iter()
+		.flatten()
+		.map(
+			|column| match column {
+				"support"    => table::Column::Support,
+				"percentage" => table::Column::Percentage,
+				"lift"       => table::Column::Lift,
+				"estimate"   => table::Column::Estimate,
+				_ => panic!("invalid column arg"),
+			}
+		)
+		.collect();
+
+	let limit = match matches.value_of("limit") {
+		Some(_) => Some(value_t!(matches, "limit", usize)?),
+		None => None,
+	};
+
+	Ok(
+		Table {
+			format,
+			columns,
+			limit,
+			caption: matches.value_of("caption").map(Into::into),
+		}
+	)
+}
+
+
+fn parse_options(matches: &clap::ArgMatches) -> Filter {
+	Filter {
 		recidivists: matches.is_present("recidivists"),
 		sex: parse_sex(matches.value_of("sex")),
 		admission_type: parse_admission_type(matches.value_of("admission_type")),
@@ -139,6 +934,135 @@ fn parse_options(matches: &clap::ArgMatches) -> Options {
 }
 
 
+/// The dimensions of the grid, which must be distinct and not filtered.
+fn parse_dimensions(matches: &clap::ArgMatches) -> anyhow::Result<Vec<Dimension>> {
+	let options = parse_options(matches);
+
+	let mut dimensions = Vec::new();
+
+	for name in matches.values_of("by").into_iter().flatten() {
+		let dimension = match name {
+			"sex"            => Dimension::Sex,
+			"admission_type" => Dimension::AdmissionType,
+			"race"           => Dimension::Race,
+			_ => panic!("invalid dimension arg"),
+		};
+
+		if dimensions.contains(&dimension) {
+			anyhow::bail!("repeated dimension: {}", name);
+		}
+
+		if dimension.is_filtered(&options) {
+			anyhow::bail!("{} can't be both combined and filtered", name);
+		}
+
+		dimensions.push(dimension);
+	}
+
+	Ok(dimensions)
+}
+
+
+fn parse_grouping(matches: &clap::ArgMatches) -> Grouping {
+	if matches.is_present("external") {
+		Grouping::External
+	}
+	else {
+		Grouping::Memory
+	}
+}
+
+
+fn parse_sampling(matches: &clap::ArgMatches) -> anyhow::Result<Option<Sampling>> {
+	let size = match matches.value_of("sample") {
+		Some(value) => value.parse()?,
+		None => return Ok(None),
+	};
+
+	let stratify_by = matches
+		.value_of("stratify_by")
+		.map(str::parse::<sample::Attribute>)
+		.transpose()?;
+
+	let seed = match matches.value_of("seed") {
+		Some(_) => Some(value_t!(matches, "seed", u64)?),
+		None => None,
+	};
+
+	Ok(
+		Some(
+			Sampling {
+				size,
+				stratify_by,
+				seed,
+			}
+		)
+	)
+}
+
+
+fn parse_weighting(matches: &clap::ArgMatches) -> anyhow::Result<Option<Weighting>> {
+	if let Some(column) = matches.value_of("weights") {
+		return Ok(Some(Weighting::Column(column.into())));
+	}
+
+	let attributes = match matches.values_of("weight_by") {
+		Some(values) => values
+			.map(str::parse)
+			.collect::<anyhow::Result<_>>()?,
+		None => return Ok(None),
+	};
+
+	Ok(
+		Some(
+			Weighting::Rake {
+				attributes,
+				marginals: PathBuf::from(matches.value_of("marginals").expect("marginals is required")),
+			}
+		)
+	)
+}
+
+
+fn parse_stability(matches: &clap::ArgMatches) -> anyhow::Result<stability::Parameters> {
+	let resamples = value_t!(matches, "resamples", usize)?;
+
+	if resamples == 0 {
+		return Err(
+			anyhow::anyhow!("invalid number of resamples: {}", resamples)
+		);
+	}
+
+	let resampling = match matches.value_of("subsample") {
+		Some(_) => {
+			let ratio = value_t!(matches, "subsample", f64)?;
+
+			if !(ratio > 0.0 && ratio <= 1.0) {
+				return Err(
+					anyhow::anyhow!("invalid subsample ratio: {}", ratio)
+				);
+			}
+
+			stability::Resampling::Subsample(ratio)
+		},
+		None => stability::Resampling::Bootstrap,
+	};
+
+	let seed = match matches.value_of("seed") {
+		Some(_) => Some(value_t!(matches, "seed", u64)?),
+		None => None,
+	};
+
+	Ok(
+		stability::Parameters {
+			resamples,
+			resampling,
+			seed,
+		}
+	)
+}
+
+
 fn parse_sex(arg: Option<&str>) -> Option<data::Sex> {
 	arg.map(
 		|arg| match arg {
@@ -173,3 +1097,153 @@ fn parse_race(arg: Option<&str>) -> Option<data::Race> {
 		}
 	)
 }
+
+
+fn parse_target(arg: Option<&str>) -> classifier::Target {
+	match arg {
+		Some("sex")            => classifier::Target::Sex,
+		Some("admission_type") => classifier::Target::AdmissionType,
+		Some("offense_type")   => classifier::Target::OffenseType,
+		Some("sentence")       => classifier::Target::Sentence,
+		Some("race")           => classifier::Target::Race,
+		Some("age")            => classifier::Target::Age,
+		Some("time_served")    => classifier::Target::TimeServed,
+		Some("release_type")   => classifier::Target::ReleaseType,
+		_ => panic!("invalid target arg"),
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use std::{fs, process};
+
+	use super::*;
+
+
+	fn parse_with_profile(config: &Path, subcommand: &[&str]) -> anyhow::Result<Command> {
+		let args = ["icpsr-36404-analysis", "--config", config.to_str().unwrap(), "--profile", "women"];
+
+		let args = args
+			.iter()
+			.chain(subcommand)
+			.map(|arg| arg.to_string());
+
+		parse(args).map(|(command, _, _)| command)
+	}
+
+
+	#[test]
+	fn profile_options_apply_only_to_their_subcommands() {
+		let config = std::env::temp_dir().join(format!("icpsr-36404-analysis-{}.toml", process::id()));
+
+		fs::write(
+			&config,
+			"[profiles.women]\n\
+			sex = \"female\"\n\
+			min_sup = 0.3\n\
+			must_contain = [\"Sex::Female\"]\n",
+		)
+		.unwrap();
+
+		let distribution = parse_with_profile(&config, &["distribution"]);
+		let save = parse_with_profile(&config, &["save"]);
+		let run = parse_with_profile(&config, &["run"]);
+
+		fs::remove_file(&config).unwrap();
+
+		match distribution.unwrap() {
+			Command::Distribution { options, .. } => assert_eq!(options.sex, Some(data::Sex::Female)),
+			command => panic!("unexpected command: {:?}", command),
+		}
+
+		match save.unwrap() {
+			Command::Save { options, .. } => assert_eq!(options.sex, Some(data::Sex::Female)),
+			command => panic!("unexpected command: {:?}", command),
+		}
+
+		match run.unwrap() {
+			Command::Run { mining, options, .. } => {
+				assert_eq!(options.sex, Some(data::Sex::Female));
+				assert_eq!(mining.min_sup_ratio, 0.3);
+				assert_eq!(mining.constraints.must_contain.len(), 1);
+			},
+			command => panic!("unexpected command: {:?}", command),
+		}
+	}
+
+
+	#[test]
+	fn profile_floats_keep_their_decimal_point() {
+		assert_eq!(option_args("subsample", &toml::Value::Float(1.0)).unwrap(), ["--subsample=1.0"]);
+		assert_eq!(option_args("min_sup", &toml::Value::Float(1e-5)).unwrap(), ["1e-5"]);
+		assert_eq!(option_args("limit", &toml::Value::Integer(10)).unwrap(), ["--limit=10"]);
+
+		let config = std::env::temp_dir().join(format!("icpsr-36404-analysis-{}-floats.toml", process::id()));
+
+		fs::write(
+			&config,
+			"[profiles.women]\n\
+			sex = \"female\"\n\
+			min_sup = 1.0\n\
+			\n\
+			[profiles.women.distribution]\n\
+			sample = 1.0\n",
+		)
+		.unwrap();
+
+		let run = parse_with_profile(&config, &["run"]);
+		let distribution = parse_with_profile(&config, &["distribution"]);
+
+		fs::remove_file(&config).unwrap();
+
+		match run.unwrap() {
+			Command::Run { mining, .. } => assert_eq!(mining.min_sup_ratio, 1.0),
+			command => panic!("unexpected command: {:?}", command),
+		}
+
+		// A float sample is neither a number of records nor a percentage.
+		assert!(distribution.is_err());
+	}
+
+
+	#[test]
+	fn subcommand_args_match_the_app() {
+		for (subcommand, args) in SUBCOMMAND_ARGS {
+			let help = match app().get_matches_from_safe(["icpsr-36404-analysis", subcommand, "--help"]) {
+				Err(error) if error.kind == clap::ErrorKind::HelpDisplayed => error.message,
+				result => panic!("unexpected result: {:?}", result),
+			};
+
+			// Every arg in the table is in the help, as a positional or an option.
+			for arg in args.iter() {
+				let positional = format!("<{}>", arg);
+				let option = format!("--{}", arg.replace('_', "-"));
+
+				assert!(
+					help.contains(&positional) || help.split_whitespace().any(|word| word == option),
+					"{} {}",
+					subcommand,
+					arg,
+				);
+			}
+
+			// Every option in the help is in the table.
+			for line in help.lines() {
+				let option = match line.split_whitespace().find(|word| word.starts_with("--")) {
+					Some(option) if line.trim_start().starts_with('-') => option.trim_start_matches('-').replace('-', "_"),
+					_ => continue,
+				};
+
+				if option != "help" && option != "version" {
+					assert!(
+						args.contains(&option.as_str()) || GLOBAL_ARGS.contains(&option.as_str()),
+						"{} {}",
+						subcommand,
+						option,
+					);
+				}
+			}
+		}
+	}
+}
@@ -0,0 +1,647 @@
+use std::{
+	cmp,
+	collections::{BTreeMap, HashMap},
+	fmt,
+	time,
+};
+
+use dci::{DataSet, ItemSet as _};
+
+use rand::{
+	rngs::StdRng,
+	seq::SliceRandom,
+	SeedableRng,
+};
+
+use crate::{
+	data::Record,
+	dataset::Dataset,
+	itemset::ItemSet,
+	labels,
+	mining::{self, Constraints},
+};
+
+
+/// The attribute to be predicted by the classifier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Target {
+	Sex,
+	AdmissionType,
+	OffenseType,
+	Sentence,
+	Race,
+	Age,
+	TimeServed,
+	ReleaseType,
+}
+
+
+impl Target {
+	/// The prefix of the labels of the attribute's items.
+	fn prefix(self) -> &'static str {
+		match self {
+			Target::Sex           => "Sex::",
+			Target::AdmissionType => "AdmissionType::",
+			Target::OffenseType   => "OffenseType::",
+			Target::Sentence      => "Sentence::",
+			Target::Race          => "Race::",
+			Target::Age           => "Age::",
+			Target::TimeServed    => "TimeServed::",
+			Target::ReleaseType   => "ReleaseType::",
+		}
+	}
+
+
+	/// The items corresponding to each class of the attribute.
+	pub fn classes(self) -> Box<[usize]> {
+		(0 .. <Record as onehot::OneHot>::ONEHOT_LEN)
+			.filter(
+				|&item| ItemSet::label(item).starts_with(self.prefix())
+			)
+			.collect()
+	}
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Parameters {
+	pub target: Target,
+	pub min_sup_ratio: f64,
+	pub min_confidence: f64,
+	/// The maximum length of the rules' antecedents.
+	pub max_length: Option<usize>,
+}
+
+
+/// A class association rule: `antecedent -> class`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+	pub antecedent: ItemSet,
+	pub class: usize,
+	pub support: dci::Support,
+	pub confidence: f64,
+}
+
+
+impl fmt::Display for Rule {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"{} -> {} (support {}, confidence {:.1}%)",
+			self.antecedent,
+			labels::item(self.class),
+			self.support,
+			100.0 * self.confidence,
+		)
+	}
+}
+
+
+/// The training transactions, grouped by distinct itemsets.
+struct Transactions {
+	/// The itemset, its class, and its multiplicity.
+	entries: Vec<(ItemSet, usize, usize)>,
+}
+
+
+impl Transactions {
+	fn new(records: &[Record], classes: &[usize]) -> Self {
+		let mut distinct: HashMap<ItemSet, usize> = HashMap::new();
+
+		for record in records {
+			*distinct.entry(record.into()).or_insert(0) += 1;
+		}
+
+		let entries = distinct
+			.into_iter()
+			.filter_map(
+				|(itemset, count)| {
+					let class = class(&itemset, classes)?;
+
+					Some((itemset, class, count))
+				}
+			)
+			.collect();
+
+		Self { entries }
+	}
+
+
+	/// The class with most transactions, according to the given counts.
+	fn majority(counts: &HashMap<usize, usize>) -> Option<usize> {
+		counts
+			.iter()
+			.max_by_key(|&(&class, &count)| (count, cmp::Reverse(class)))
+			.map(|(&class, _)| class)
+	}
+
+
+	fn class_counts(&self) -> HashMap<usize, usize> {
+		let mut counts = HashMap::new();
+
+		for &(_, class, count) in &self.entries {
+			*counts.entry(class).or_insert(0) += count;
+		}
+
+		counts
+	}
+}
+
+
+/// The class of the given transaction, if any.
+fn class(itemset: &ItemSet, classes: &[usize]) -> Option<usize> {
+	classes
+		.iter()
+		.copied()
+		.find(|&class| itemset.into_iter().any(|item| item == class))
+}
+
+
+/// An ordered rule list classifier, built as in CBA (Liu et al., 1998). The first rule whose
+/// antecedent is contained in a transaction determines its class. If no rule matches, the
+/// default class is predicted.
+#[derive(Debug, Clone)]
+pub struct Classifier {
+	pub rules: Box<[Rule]>,
+	pub default_class: usize,
+}
+
+
+impl Classifier {
+	/// Mine the class association rules from the given records, and select the rule list
+	/// through the database coverage heuristic (CBA-CB M1).
+	pub fn train(records: &[Record], parameters: &Parameters) -> Self {
+		let classes = parameters.target.classes();
+
+		let rules = mine_rules(records, &classes, parameters);
+
+		let mut transactions = Transactions::new(records, &classes);
+		let mut counts = transactions.class_counts();
+
+		let mut default_class = Transactions::majority(&counts).unwrap_or(classes[0]);
+
+		let mut selected = Vec::new();
+		let mut rules_errors = 0;
+		let mut best_errors = counts.values().sum::<usize>() - counts.get(&default_class).unwrap_or(&0);
+		let mut best_length = 0;
+
+		for rule in rules.into_vec() {
+			let correct = transactions.entries
+				.iter()
+				.any(
+					|(itemset, class, _)| *class == rule.class && rule.antecedent.is_subset(itemset)
+				);
+
+			if !correct {
+				continue;
+			}
+
+			transactions.entries.retain(
+				|(itemset, class, count)| {
+					if !rule.antecedent.is_subset(itemset) {
+						return true;
+					}
+
+					if *class != rule.class {
+						rules_errors += count;
+					}
+
+					*counts.get_mut(class).expect("class should be counted") -= count;
+
+					false
+				}
+			);
+
+			selected.push(rule);
+
+			let default = Transactions::majority(&counts)
+				.filter(|class| counts[class] > 0)
+				.unwrap_or(default_class);
+
+			let errors = rules_errors
+				+ counts.values().sum::<usize>()
+				- counts.get(&default).unwrap_or(&0);
+
+			if errors < best_errors {
+				best_errors = errors;
+				best_length = selected.len();
+				default_class = default;
+			}
+
+			if transactions.entries.is_empty() {
+				break;
+			}
+		}
+
+		selected.truncate(best_length);
+
+		Self {
+			rules: selected.into_boxed_slice(),
+			default_class,
+		}
+	}
+
+
+	/// Predict the class of the given transaction.
+	pub fn predict(&self, transaction: &ItemSet) -> usize {
+		self.rules
+			.iter()
+			.find(|rule| rule.antecedent.is_subset(transaction))
+			.map(|rule| rule.class)
+			.unwrap_or(self.default_class)
+	}
+}
+
+
+impl fmt::Display for Classifier {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		for rule in self.rules.iter() {
+			writeln!(f, "{}", rule)?;
+		}
+
+		writeln!(f, "default -> {}", labels::item(self.default_class))
+	}
+}
+
+
+/// Mine the rules for each class, sorted by precedence: confidence, support and length.
+fn mine_rules(records: &[Record], classes: &[usize], parameters: &Parameters) -> Box<[Rule]> {
+	let dataset: Dataset = onehot::vertical(records).into();
+
+	let min_sup = (dataset.transactions_count() as f64 * parameters.min_sup_ratio) as usize;
+
+	let mut rules = Vec::new();
+
+	for &class in classes {
+		let mut class_item = ItemSet::empty();
+		class_item.add(class);
+
+		let mut other_classes = ItemSet::empty();
+		for &other in classes.iter().filter(|&&other| other != class) {
+			other_classes.add(other);
+		}
+
+		let constraints = Constraints {
+			must_contain: class_item.clone(),
+			must_not_contain: other_classes,
+			max_length: parameters.max_length.map(|length| length + 1),
+		};
+
+		for (itemset, support) in mining::closed(&dataset, min_sup, &constraints).into_vec() {
+			let mut antecedent = itemset;
+			antecedent.remove_all(&class_item);
+
+			if antecedent.is_empty() {
+				continue;
+			}
+
+			let confidence = support as f64 / dataset.support(&antecedent) as f64;
+
+			if confidence >= parameters.min_confidence {
+				rules.push(
+					Rule { antecedent, class, support, confidence }
+				);
+			}
+		}
+	}
+
+	rules.sort_by(
+		|a, b| b.confidence
+			.partial_cmp(&a.confidence)
+			.unwrap_or(cmp::Ordering::Equal)
+			.then(b.support.cmp(&a.support))
+			.then(a.antecedent.len().cmp(&b.antecedent.len()))
+	);
+
+	rules.into_boxed_slice()
+}
+
+
+/// The evaluation of a classifier's predictions.
+#[derive(Debug, Clone)]
+pub struct Evaluation {
+	classes: Box<[usize]>,
+	/// Confusion matrix, indexed by actual and predicted class positions.
+	confusion: Box<[Box<[usize]>]>,
+}
+
+
+impl Evaluation {
+	fn new(classes: Box<[usize]>) -> Self {
+		let confusion = vec![vec![0; classes.len()].into_boxed_slice(); classes.len()];
+
+		Self {
+			classes,
+			confusion: confusion.into_boxed_slice(),
+		}
+	}
+
+
+	fn position(&self, class: usize) -> usize {
+		self.classes
+			.iter()
+			.position(|&item| item == class)
+			.expect("unknown class")
+	}
+
+
+	fn insert(&mut self, actual: usize, predicted: usize, count: usize) {
+		let actual = self.position(actual);
+		let predicted = self.position(predicted);
+
+		self.confusion[actual][predicted] += count;
+	}
+
+
+	fn total(&self) -> usize {
+		self.confusion
+			.iter()
+			.flat_map(|row| row.iter())
+			.sum()
+	}
+
+
+	pub fn accuracy(&self) -> f64 {
+		let correct: usize = (0 .. self.classes.len())
+			.map(|ix| self.confusion[ix][ix])
+			.sum();
+
+		ratio(correct, self.total())
+	}
+
+
+	/// Precision of the class in the given position.
+	fn precision(&self, ix: usize) -> f64 {
+		let predicted: usize = self.confusion
+			.iter()
+			.map(|row| row[ix])
+			.sum();
+
+		ratio(self.confusion[ix][ix], predicted)
+	}
+
+
+	/// Recall of the class in the given position.
+	fn recall(&self, ix: usize) -> f64 {
+		let actual: usize = self.confusion[ix].iter().sum();
+
+		ratio(self.confusion[ix][ix], actual)
+	}
+}
+
+
+/// The ratio between the counts, or zero if there is nothing to count.
+fn ratio(count: usize, total: usize) -> f64 {
+	if total == 0 {
+		0.0
+	}
+	else {
+		count as f64 / total as f64
+	}
+}
+
+
+impl fmt::Display for Evaluation {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		writeln!(f, "transactions: {}", self.total())?;
+		writeln!(f, "accuracy: {:.1}%", 100.0 * self.accuracy())?;
+
+		writeln!(f, "class: precision, recall")?;
+
+		for (ix, &class) in self.classes.iter().enumerate() {
+			writeln!(
+				f,
+				"	{}: {:.1}%, {:.1}%",
+				labels::item(class),
+				100.0 * self.precision(ix),
+				100.0 * self.recall(ix),
+			)?;
+		}
+
+		writeln!(f, "confusion matrix (actual x predicted):")?;
+
+		for (ix, &class) in self.classes.iter().enumerate() {
+			write!(f, "	{}:", labels::item(class))?;
+
+			for count in self.confusion[ix].iter() {
+				write!(f, " {}", count)?;
+			}
+
+			writeln!(f)?;
+		}
+
+		Ok(())
+	}
+}
+
+
+/// The seed of the assignment of the records to the cross-validation folds, so that the
+/// evaluation is reproducible.
+const FOLDS_SEED: u64 = 36404;
+
+
+/// Assign the records to the folds, stratified by class: the records of each class are
+/// shuffled and dealt to the folds in turn, so that every fold has about the same class
+/// distribution.
+///
+/// Returns the fold of each record.
+fn assign_folds(records: &[Record], classes: &[usize], folds: usize) -> Box<[usize]> {
+	let mut strata: BTreeMap<Option<usize>, Vec<usize>> = BTreeMap::new();
+
+	for (ix, record) in records.iter().enumerate() {
+		strata
+			.entry(class(&record.into(), classes))
+			.or_default()
+			.push(ix);
+	}
+
+	let mut rng = StdRng::seed_from_u64(FOLDS_SEED);
+	let mut assignment = vec![0; records.len()].into_boxed_slice();
+
+	for (position, ix) in strata
+		.into_values()
+		.flat_map(
+			|mut stratum| {
+				stratum.shuffle(&mut rng);
+				stratum
+			}
+		)
+		.enumerate()
+	{
+		assignment[ix] = position % folds;
+	}
+
+	assignment
+}
+
+
+/// Evaluate the classifier with k-fold cross-validation, stratified by class.
+pub fn cross_validate(records: &[Record], parameters: &Parameters, folds: usize) -> Evaluation {
+	let classes = parameters.target.classes();
+
+	let assignment = assign_folds(records, &classes, folds);
+
+	let mut evaluation = Evaluation::new(classes.clone());
+
+	for fold in 0 .. folds {
+		let clock = time::Instant::now();
+
+		let (test, training): (Vec<_>, Vec<_>) = records
+			.iter()
+			.enumerate()
+			.partition(|(ix, _)| assignment[*ix] == fold);
+
+		let training: Vec<Record> = training
+			.into_iter()
+			.map(|(_, &record)| record)
+			.collect();
+
+		let classifier = Classifier::train(&training, parameters);
+
+		let test: Box<[Record]> = test
+			.into_iter()
+			.map(|(_, &record)| record)
+			.collect();
+
+		for (itemset, class, count) in Transactions::new(&test, &classes).entries {
+			evaluation.insert(class, classifier.predict(&itemset), count);
+		}
+
+		log::info!(
+			"Fold {} ({} rules) took {:.2?}",
+			fold + 1,
+			classifier.rules.len(),
+			clock.elapsed()
+		);
+	}
+
+	evaluation
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::data::*;
+
+
+	fn record(sex: Sex, race: Race) -> Record {
+		Record {
+			sex,
+			admission_type: AdmissionType::New,
+			offense_type: OffenseType::Violent,
+			sentence: Sentence::Years_0_1,
+			race,
+			age_admission: Age::Age_18_24,
+			time_served: TimeServed::Years_0_1,
+			release_type: ReleaseType::Conditional,
+			education: Education::NoHighSchool,
+			admission_year: 2000,
+			release_year: 2001,
+			mandatory_release_year: 2001,
+			projected_release_year: 2001,
+			parole_eligibility_year: 2001,
+			offense_detailed_type: OffenseDetailedType::Murder,
+			age_release: Age::Age_18_24,
+			state: State::Alabama,
+		}
+	}
+
+
+	fn item(label: &str) -> usize {
+		ItemSet::item(label).unwrap()
+	}
+
+
+	/// The white inmates are men, and the black inmates are women, except for one man.
+	fn records() -> Vec<Record> {
+		let mut records = vec![record(Sex::Male, Race::White); 12];
+		records.extend(vec![record(Sex::Female, Race::Black); 7]);
+		records.push(record(Sex::Male, Race::Black));
+		records
+	}
+
+
+	const PARAMETERS: Parameters = Parameters {
+		target: Target::Sex,
+		min_sup_ratio: 0.1,
+		min_confidence: 0.8,
+		max_length: None,
+	};
+
+
+	#[test]
+	fn train_and_predict() {
+		let classifier = Classifier::train(&records(), &PARAMETERS);
+
+		let (male, female) = (item("Sex::Male"), item("Sex::Female"));
+
+		// The rule of the white inmates, with confidence 1, precedes that of the black inmates,
+		// with 7/8, which is pruned, as the default class makes the same single error.
+		assert_eq!(classifier.rules.len(), 1);
+		assert_eq!(classifier.rules[0].class, male);
+		assert_eq!(classifier.rules[0].support, 12);
+		assert_eq!(classifier.rules[0].confidence, 1.0);
+		assert!(classifier.rules[0].antecedent.is_subset(&(&record(Sex::Male, Race::White)).into()));
+		assert_eq!(classifier.default_class, female);
+
+		assert_eq!(classifier.predict(&(&record(Sex::Male, Race::White)).into()), male);
+		assert_eq!(classifier.predict(&(&record(Sex::Male, Race::Black)).into()), female);
+
+		// Transactions no rule matches get the default class.
+		assert_eq!(classifier.predict(&ItemSet::empty()), female);
+	}
+
+
+	#[test]
+	fn folds_are_stratified_and_reproducible() {
+		let records = records();
+		let classes = Target::Sex.classes();
+
+		let assignment = assign_folds(&records, &classes, 4);
+
+		assert_eq!(assignment, assign_folds(&records, &classes, 4));
+
+		// The 13 men and 7 women are dealt in turn, so each fold gets 5 records, and 3 or 4
+		// men.
+		for fold in 0 .. 4 {
+			let men = (0 .. records.len())
+				.filter(|&ix| assignment[ix] == fold && records[ix].sex == Sex::Male)
+				.count();
+
+			assert_eq!(assignment.iter().filter(|&&assigned| assigned == fold).count(), 5);
+			assert!(men == 3 || men == 4, "{:?}", assignment);
+		}
+	}
+
+
+	#[test]
+	fn cross_validate() {
+		let evaluation = super::cross_validate(&records(), &PARAMETERS, 4);
+
+		// Only the black man is misclassified.
+		assert_eq!(evaluation.total(), 20);
+		assert_eq!(evaluation.accuracy(), 19.0 / 20.0);
+	}
+
+
+	#[test]
+	fn evaluation() {
+		let (male, female) = (item("Sex::Male"), item("Sex::Female"));
+
+		let mut evaluation = Evaluation::new(Target::Sex.classes());
+
+		assert_eq!(evaluation.accuracy(), 0.0);
+
+		evaluation.insert(male, male, 3);
+		evaluation.insert(male, female, 1);
+		evaluation.insert(female, female, 2);
+
+		let (male, female) = (evaluation.position(male), evaluation.position(female));
+
+		assert_eq!(evaluation.total(), 6);
+		assert_eq!(evaluation.accuracy(), 5.0 / 6.0);
+		assert_eq!((evaluation.precision(male), evaluation.recall(male)), (1.0, 3.0 / 4.0));
+		assert_eq!((evaluation.precision(female), evaluation.recall(female)), (2.0 / 3.0, 1.0));
+	}
+}
@@ -0,0 +1,65 @@
+//! Analysis profiles: named sets of options, read from a configuration file (TOML), so that
+//! the same filters need not be repeated on the command line of every subcommand.
+
+use std::{
+	collections::BTreeMap,
+	fs,
+	path::Path,
+};
+
+use serde::Deserialize;
+
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+	/// The profiles, by name.
+	pub profiles: BTreeMap<String, Profile>,
+}
+
+
+/// The options of a profile, by the name of the argument, e.g. `sex = "female"` for
+/// `--sex female`, or `min_sup = 0.05` for the minimum support. The options in a table named
+/// after a subcommand apply only to that subcommand, and override the others.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(transparent)]
+pub struct Profile(toml::value::Table);
+
+
+impl Config {
+	pub fn load(path: &Path) -> anyhow::Result<Self> {
+		let contents = fs::read_to_string(path)?;
+
+		Ok(toml::from_str(&contents)?)
+	}
+
+
+	pub fn profile(&self, name: &str) -> anyhow::Result<&Profile> {
+		self.profiles
+			.get(name)
+			.ok_or_else(|| anyhow::anyhow!("unknown profile: {}", name))
+	}
+}
+
+
+impl Profile {
+	/// The options of the profile for the given subcommand. The top-level options are
+	/// filtered by whether they apply to the subcommand, while those of its table always do.
+	pub fn options(&self, subcommand: &str, applies: impl Fn(&str) -> bool) -> BTreeMap<&str, &toml::Value> {
+		let mut options: BTreeMap<_, _> = self.0
+			.iter()
+			.filter(|(name, value)| !value.is_table() && applies(name))
+			.map(|(name, value)| (name.as_str(), value))
+			.collect();
+
+		if let Some(toml::Value::Table(table)) = self.0.get(subcommand) {
+			options.extend(
+				table
+					.iter()
+					.map(|(name, value)| (name.as_str(), value))
+			);
+		}
+
+		options
+	}
+}
@@ -0,0 +1,440 @@
+//! A compact columnar cache of the parsed records, with the inmate ids and before any
+//! filtering, so that the analyses can skip parsing the original dataset. Each enum field
+//! takes one byte per record, and each year takes two.
+
+use std::{
+	convert::TryFrom,
+	fs,
+	io::{self, Read, Write},
+	path::Path,
+	time,
+};
+
+use anyhow::Context;
+
+use super::{
+	reader,
+	variants,
+	AdmissionType,
+	Age,
+	Education,
+	OffenseDetailedType,
+	OffenseType,
+	Race,
+	Record,
+	ReleaseType,
+	Sentence,
+	Sex,
+	State,
+	TimeServed,
+};
+
+
+/// The file signature, including the format version.
+const MAGIC: &[u8; 8] = b"RCACHE01";
+
+/// The length of the header: the signature and the number of records.
+const HEADER_LEN: usize = 16;
+
+/// The length of the columns of a record: a byte for each of the 12 enum fields, and two
+/// for each of the 5 years.
+const RECORD_LEN: usize = 12 + 2 * 5;
+
+/// The file extension of caches.
+pub const EXTENSION: &str = "cache";
+
+
+/// Whether the given file is a cache, according to its extension.
+pub fn is_cache(path: &Path) -> bool {
+	path
+		.extension()
+		.and_then(|extension| extension.to_str())
+		.is_some_and(|extension| extension.eq_ignore_ascii_case(EXTENSION))
+}
+
+
+/// The parsed records, in the dataset order, and the inmate id of each one.
+#[derive(Debug, Clone, Default)]
+pub struct Cache {
+	/// The concatenated ids.
+	ids: Vec<u8>,
+	/// The end offset of each id.
+	ends: Vec<usize>,
+	records: Vec<Record>,
+}
+
+
+impl Cache {
+	/// Cache the records from the original dataset in the delimited format, as in
+	/// `read_records`.
+	pub fn read_records<R: io::BufRead>(reader: R) -> io::Result<Self> {
+		let clock = time::Instant::now();
+
+		let mut cache = Self::default();
+
+		reader::parse_records(reader, None, |id, record, _| cache.push(id, record))?;
+
+		log::info!("Importing dataset took {:.2?}", clock.elapsed());
+
+		Ok(cache)
+	}
+
+
+	/// Cache the records from the original dataset in the given file, as in `read_file`.
+	pub fn read_file(path: &Path) -> anyhow::Result<Self> {
+		let clock = time::Instant::now();
+
+		let mut cache = Self::default();
+
+		reader::parse_file(path, None, |id, record, _| cache.push(id, record))?;
+
+		log::info!("Importing dataset took {:.2?}", clock.elapsed());
+
+		Ok(cache)
+	}
+
+
+	pub fn push(&mut self, id: &[u8], record: Record) {
+		self.ids.extend_from_slice(id);
+		self.ends.push(self.ids.len());
+		self.records.push(record);
+	}
+
+
+	pub fn len(&self) -> usize {
+		self.records.len()
+	}
+
+
+	pub fn is_empty(&self) -> bool {
+		self.records.is_empty()
+	}
+
+
+	/// The id and record of each entry.
+	pub fn iter(&self) -> impl Iterator<Item = (&[u8], &Record)> {
+		let starts = std::iter::once(0).chain(self.ends.iter().copied());
+
+		starts
+			.zip(self.ends.iter().copied())
+			.map(move |(start, end)| &self.ids[start .. end])
+			.zip(self.records.iter())
+	}
+
+
+	/// Write the cache: the signature, the number of records, the end offsets of the ids, the
+	/// ids, and then each field as a column, in the order of the delimited format.
+	pub fn save<W: Write>(&self, writer: W) -> anyhow::Result<()> {
+		let clock = time::Instant::now();
+
+		let mut writer = io::BufWriter::with_capacity(8 * 1024 * 1024, writer);
+
+		let records = &self.records;
+
+		writer.write_all(MAGIC)?;
+		writer.write_all(&(records.len() as u64).to_le_bytes())?;
+
+		for &end in self.ends.iter() {
+			let end = u32::try_from(end).context("the inmate ids exceed the cache size limit of 4 GiB")?;
+			writer.write_all(&end.to_le_bytes())?;
+		}
+
+		writer.write_all(&self.ids)?;
+
+		write_codes(&mut writer, Sex::CODES, records.iter().map(|record| record.sex))?;
+		write_codes(&mut writer, AdmissionType::CODES, records.iter().map(|record| record.admission_type))?;
+		write_codes(&mut writer, OffenseType::CODES, records.iter().map(|record| record.offense_type))?;
+		write_codes(&mut writer, Education::CODES, records.iter().map(|record| record.education))?;
+		write_years(&mut writer, records.iter().map(|record| record.admission_year))?;
+		write_years(&mut writer, records.iter().map(|record| record.release_year))?;
+		write_years(&mut writer, records.iter().map(|record| record.mandatory_release_year))?;
+		write_years(&mut writer, records.iter().map(|record| record.projected_release_year))?;
+		write_years(&mut writer, records.iter().map(|record| record.parole_eligibility_year))?;
+		write_codes(&mut writer, Sentence::CODES, records.iter().map(|record| record.sentence))?;
+		write_codes(&mut writer, OffenseDetailedType::CODES, records.iter().map(|record| record.offense_detailed_type))?;
+		write_codes(&mut writer, Race::CODES, records.iter().map(|record| record.race))?;
+		write_codes(&mut writer, Age::CODES, records.iter().map(|record| record.age_admission))?;
+		write_codes(&mut writer, Age::CODES, records.iter().map(|record| record.age_release))?;
+		write_codes(&mut writer, TimeServed::CODES, records.iter().map(|record| record.time_served))?;
+		write_codes(&mut writer, ReleaseType::CODES, records.iter().map(|record| record.release_type))?;
+		write_codes(&mut writer, State::CODES, records.iter().map(|record| record.state))?;
+
+		writer.flush()?;
+
+		log::info!("Saving cache took {:.2?}", clock.elapsed());
+
+		Ok(())
+	}
+
+
+	/// Read a cache file, as written by `save`.
+	pub fn load(path: &Path) -> anyhow::Result<Self> {
+		let clock = time::Instant::now();
+
+		let file = fs::File::open(path)?;
+		let size = file.metadata()?.len();
+		let mut reader = io::BufReader::new(file);
+
+		let header = read_bytes(&mut reader, HEADER_LEN)?;
+
+		if &header[.. 8] != MAGIC {
+			anyhow::bail!("not a record cache, or an unsupported version");
+		}
+
+		let mut len = [0; 8];
+		len.copy_from_slice(&header[8 ..]);
+		let len = u64::from_le_bytes(len) as usize;
+
+		// The header is untrusted, so the size of the columns must not overflow nor exceed the
+		// file, and the end offsets must be sorted.
+		let columns_len = len
+			.checked_mul(4 + RECORD_LEN)
+			.and_then(|bytes| bytes.checked_add(HEADER_LEN))
+			.filter(|&bytes| bytes as u64 <= size)
+			.ok_or_else(|| anyhow::anyhow!("truncated or corrupt record cache"))?;
+
+		let ends: Vec<usize> = read_bytes(&mut reader, 4 * len)?
+			.chunks(4)
+			.map(|end| u32::from_le_bytes([end[0], end[1], end[2], end[3]]) as usize)
+			.collect();
+
+		let ids_len = ends.last().copied().unwrap_or(0);
+
+		if ends.windows(2).any(|ends| ends[0] > ends[1]) || (columns_len + ids_len) as u64 != size {
+			anyhow::bail!("truncated or corrupt record cache");
+		}
+
+		let ids = read_bytes(&mut reader, ids_len)?;
+
+		let sex = read_codes(&mut reader, Sex::CODES, len)?;
+		let admission_type = read_codes(&mut reader, AdmissionType::CODES, len)?;
+		let offense_type = read_codes(&mut reader, OffenseType::CODES, len)?;
+		let education = read_codes(&mut reader, Education::CODES, len)?;
+		let admission_year = read_years(&mut reader, len)?;
+		let release_year = read_years(&mut reader, len)?;
+		let mandatory_release_year = read_years(&mut reader, len)?;
+		let projected_release_year = read_years(&mut reader, len)?;
+		let parole_eligibility_year = read_years(&mut reader, len)?;
+		let sentence = read_codes(&mut reader, Sentence::CODES, len)?;
+		let offense_detailed_type = read_codes(&mut reader, OffenseDetailedType::CODES, len)?;
+		let race = read_codes(&mut reader, Race::CODES, len)?;
+		let age_admission = read_codes(&mut reader, Age::CODES, len)?;
+		let age_release = read_codes(&mut reader, Age::CODES, len)?;
+		let time_served = read_codes(&mut reader, TimeServed::CODES, len)?;
+		let release_type = read_codes(&mut reader, ReleaseType::CODES, len)?;
+		let state = read_codes(&mut reader, State::CODES, len)?;
+
+		let records = (0 .. len)
+			.map(
+				|ix| Record {
+					sex                     : sex[ix],
+					admission_type          : admission_type[ix],
+					offense_type            : offense_type[ix],
+					education               : education[ix],
+					admission_year          : admission_year[ix],
+					release_year            : release_year[ix],
+					mandatory_release_year  : mandatory_release_year[ix],
+					projected_release_year  : projected_release_year[ix],
+					parole_eligibility_year : parole_eligibility_year[ix],
+					sentence                : sentence[ix],
+					offense_detailed_type   : offense_detailed_type[ix],
+					race                    : race[ix],
+					age_admission           : age_admission[ix],
+					age_release             : age_release[ix],
+					time_served             : time_served[ix],
+					release_type            : release_type[ix],
+					state                   : state[ix],
+				}
+			)
+			.collect();
+
+		log::info!("Loading cache took {:.2?}", clock.elapsed());
+
+		Ok(Self { ids, ends, records })
+	}
+}
+
+
+/// Write an enum column, as the index of each value among the distinct values of the codes.
+fn write_codes<W, T>(writer: &mut W, codes: &[(&[u8], T)], values: impl Iterator<Item = T>) -> io::Result<()>
+where
+	W: Write,
+	T: Copy + PartialEq,
+{
+	let variants = variants(codes);
+
+	let column: Vec<u8> = values
+		.map(
+			|value| variants
+				.iter()
+				.position(|&variant| variant == value)
+				.expect("all variants should have a code") as u8
+		)
+		.collect();
+
+	writer.write_all(&column)
+}
+
+
+/// Write a year column, in little endian.
+fn write_years<W: Write>(writer: &mut W, values: impl Iterator<Item = u16>) -> io::Result<()> {
+	for value in values {
+		writer.write_all(&value.to_le_bytes())?;
+	}
+
+	Ok(())
+}
+
+
+/// Read an enum column, as written by `write_codes`.
+fn read_codes<R, T>(reader: &mut R, codes: &[(&[u8], T)], len: usize) -> anyhow::Result<Vec<T>>
+where
+	R: Read,
+	T: Copy + PartialEq,
+{
+	let variants = variants(codes);
+
+	read_bytes(reader, len)?
+		.into_iter()
+		.map(
+			|ix| variants
+				.get(ix as usize)
+				.copied()
+				.ok_or_else(|| anyhow::anyhow!("invalid value in cache: {}", ix))
+		)
+		.collect()
+}
+
+
+/// Read a year column, as written by `write_years`.
+fn read_years<R: Read>(reader: &mut R, len: usize) -> io::Result<Vec<u16>> {
+	Ok(
+		read_bytes(reader, 2 * len)?
+			.chunks(2)
+			.map(|year| u16::from_le_bytes([year[0], year[1]]))
+			.collect()
+	)
+}
+
+
+/// Read exactly `len` bytes.
+fn read_bytes<R: Read>(reader: &mut R, len: usize) -> io::Result<Vec<u8>> {
+	let mut buffer = vec![0; len];
+
+	reader.read_exact(&mut buffer)?;
+
+	Ok(buffer)
+}
+
+
+#[cfg(test)]
+mod tests {
+	use std::{
+		process,
+		sync::atomic::{AtomicUsize, Ordering},
+	};
+
+	use super::*;
+
+
+	fn record(sex: Sex, race: Race, release_year: u16) -> Record {
+		Record {
+			sex,
+			admission_type: AdmissionType::New,
+			offense_type: OffenseType::Violent,
+			sentence: Sentence::Years_0_1,
+			race,
+			age_admission: Age::Age_18_24,
+			time_served: TimeServed::Years_0_1,
+			release_type: ReleaseType::Conditional,
+			education: Education::NoHighSchool,
+			admission_year: 2000,
+			release_year,
+			mandatory_release_year: 2001,
+			projected_release_year: 2001,
+			parole_eligibility_year: 2001,
+			offense_detailed_type: OffenseDetailedType::Murder,
+			age_release: Age::Age_18_24,
+			state: State::Alabama,
+		}
+	}
+
+
+	/// Load the given bytes as a cache file.
+	fn load(bytes: &[u8]) -> anyhow::Result<Cache> {
+		static FILES: AtomicUsize = AtomicUsize::new(0);
+
+		let file = FILES.fetch_add(1, Ordering::Relaxed);
+		let path = std::env::temp_dir().join(format!("icpsr-36404-analysis-{}-{}.cache", process::id(), file));
+
+		fs::write(&path, bytes).unwrap();
+
+		let cache = Cache::load(&path);
+
+		fs::remove_file(&path).unwrap();
+
+		cache
+	}
+
+
+	fn saved(cache: &Cache) -> Vec<u8> {
+		let mut bytes = Vec::new();
+		cache.save(&mut bytes).unwrap();
+		bytes
+	}
+
+
+	#[test]
+	fn round_trip() {
+		let mut cache = Cache::default();
+
+		cache.push(b"A1", record(Sex::Male, Race::White, 2001));
+		cache.push(b"", record(Sex::Female, Race::Black, 2010));
+		cache.push(b"B22", record(Sex::Female, Race::Other, 2015));
+
+		let bytes = saved(&cache);
+
+		assert_eq!(bytes.len(), HEADER_LEN + 3 * (4 + RECORD_LEN) + 5);
+
+		let loaded = load(&bytes).unwrap();
+
+		assert_eq!(loaded.len(), 3);
+		assert!(cache.iter().eq(loaded.iter()));
+
+		assert!(load(&saved(&Cache::default())).unwrap().is_empty());
+	}
+
+
+	#[test]
+	fn load_rejects_corrupt_headers() {
+		let mut cache = Cache::default();
+
+		cache.push(b"A1", record(Sex::Male, Race::White, 2001));
+		cache.push(b"B2", record(Sex::Female, Race::Black, 2010));
+
+		let bytes = saved(&cache);
+
+		// A number of records whose size overflows, or exceeds the file.
+		for len in &[u64::MAX / 2, 3] {
+			let mut corrupt = bytes.clone();
+			corrupt[8 .. 16].copy_from_slice(&len.to_le_bytes());
+
+			assert!(load(&corrupt).is_err());
+		}
+
+		// Decreasing end offsets.
+		let mut corrupt = bytes.clone();
+		corrupt[16 .. 20].copy_from_slice(&3u32.to_le_bytes());
+		corrupt[20 .. 24].copy_from_slice(&1u32.to_le_bytes());
+
+		assert!(load(&corrupt).is_err());
+
+		// A truncated file.
+		assert!(load(&bytes[.. bytes.len() - 1]).is_err());
+
+		let mut corrupt = bytes;
+		corrupt[.. 8].copy_from_slice(b"RCACHE00");
+
+		assert!(load(&corrupt).is_err());
+	}
+}
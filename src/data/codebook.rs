@@ -0,0 +1,755 @@
+//! Importer for the value labels in the setup files distributed by ICPSR, in SPSS (`.sps`,
+//! `VALUE LABELS`) and Stata (`.do`, `label define`) syntax.
+
+use std::{
+	collections::HashMap,
+	fmt,
+	fs,
+	io,
+	path::Path,
+};
+
+use crate::{
+	schema::{self, Schema},
+	util,
+};
+
+use super::*;
+
+
+/// The columns that hold numbers instead of codes, and thus are not expected to be fully
+/// labeled.
+const NUMERIC: &[&str] = &[
+	"ADMITYR",
+	"RELEASEYR",
+	"MAND_PRISREL_YEAR",
+	"PROJ_PRISREL_YEAR",
+	"PARELIG_YEAR",
+];
+
+
+/// The code and label of each value of a variable.
+pub type Labels = Box<[(String, String)]>;
+
+
+/// The value labels of each variable, in the order of the setup file. The code prefix that
+/// ICPSR adds to the labels, e.g. `(1) Male`, is removed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Codebook {
+	pub variables: Vec<(String, Labels)>,
+}
+
+
+/// A difference between the codebook and the built-in enums.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mismatch {
+	/// The variable is not labeled in the codebook.
+	MissingVariable(&'static str),
+	/// The code is in the codebook, but not in the built-in enum.
+	UnknownCode {
+		variable: &'static str,
+		code: String,
+		label: String,
+	},
+	/// The code is in the built-in enum, but not in the codebook.
+	UndocumentedCode {
+		variable: &'static str,
+		code: String,
+		value: String,
+	},
+}
+
+
+impl fmt::Display for Mismatch {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Mismatch::MissingVariable(variable) => write!(
+				f,
+				"{}: missing from the codebook",
+				variable
+			),
+			Mismatch::UnknownCode { variable, code, label } => write!(
+				f,
+				"{}: code {:?} ({}) is not in the built-in enum",
+				variable,
+				code,
+				label
+			),
+			Mismatch::UndocumentedCode { variable, code, value } => write!(
+				f,
+				"{}: code {:?} ({}) is not in the codebook",
+				variable,
+				code,
+				value
+			),
+		}
+	}
+}
+
+
+/// A code present in the data, but absent from the codebook.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Undocumented {
+	pub variable: String,
+	pub code: String,
+	/// The number of records with the code.
+	pub count: usize,
+}
+
+
+impl fmt::Display for Undocumented {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"{}: code {:?} ({} records) is not in the codebook",
+			self.variable,
+			self.code,
+			self.count
+		)
+	}
+}
+
+
+/// The code of each value of an enum, with the name of the variant.
+type Codes = Box<[(&'static str, String)]>;
+
+
+/// The built-in codes of each coded column.
+fn builtin() -> Vec<(&'static str, Codes)> {
+	fn codes<T: fmt::Debug>(codes: &[(&'static [u8], T)]) -> Codes {
+		codes
+			.iter()
+			.map(
+				|(code, value)| (
+					std::str::from_utf8(code).expect("codes should be ascii"),
+					format!("{:?}", value)
+				)
+			)
+			.collect()
+	}
+
+	vec![
+		("SEX", codes(Sex::CODES)),
+		("ADMTYPE", codes(AdmissionType::CODES)),
+		("OFFGENERAL", codes(OffenseType::CODES)),
+		("EDUCATION", codes(Education::CODES)),
+		("SENTLGTH", codes(Sentence::CODES)),
+		("OFFDETAIL", codes(OffenseDetailedType::CODES)),
+		("RACE", codes(Race::CODES)),
+		("AGEADMIT", codes(Age::CODES)),
+		("AGERELEASE", codes(Age::CODES)),
+		("TIMESRVD", codes(TimeServed::CODES)),
+		("RELTYPE", codes(ReleaseType::CODES)),
+		("STATE", codes(State::CODES)),
+	]
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+	Word(String),
+	Quoted(String),
+	/// A Stata local macro, e.g. `` `name' ``.
+	Macro(String),
+	Slash,
+	Comma,
+	/// The end of a command.
+	End,
+}
+
+
+/// Split the setup file into tokens. Comments are discarded. In SPSS syntax, a period at the
+/// end of a line terminates the command. In Stata syntax, each line is a command, unless
+/// continued by `///`, or commands are terminated by semicolons after `#delimit ;`. Stata
+/// strings are only delimited by double quotes, or by compound quotes `` `"…"' ``.
+fn tokenize(contents: &str, stata: bool) -> anyhow::Result<Vec<Token>> {
+	let mut tokens = Vec::new();
+	let mut in_comment = false;
+	let mut semicolon = false;
+
+	for (line_ix, line) in contents.lines().enumerate() {
+		let mut chars = line.chars().peekable();
+		let mut continued = false;
+
+		if !in_comment && line.trim_start().starts_with('*') {
+			continue;
+		}
+
+		if let Some(directive) = line.trim().strip_prefix('#').filter(|_| stata && !in_comment) {
+			let end = directive
+				.find(|c: char| c.is_whitespace() || c == ';')
+				.unwrap_or(directive.len());
+
+			let (keyword, delimiter) = directive.split_at(end);
+
+			if abbreviates(keyword, "delimit", 1) {
+				semicolon = match delimiter.trim() {
+					";" => true,
+					"cr" => false,
+					_ => anyhow::bail!("invalid delimiter at line {}", line_ix + 1),
+				};
+
+				tokens.push(Token::End);
+				continue;
+			}
+		}
+
+		while let Some(c) = chars.next() {
+			if in_comment {
+				if c == '*' && chars.peek() == Some(&'/') {
+					chars.next();
+					in_comment = false;
+				}
+				continue;
+			}
+
+			match c {
+				'/' if chars.peek() == Some(&'*') => {
+					chars.next();
+					in_comment = true;
+				},
+
+				'/' if stata && chars.peek() == Some(&'/') => {
+					chars.next();
+					continued = chars.peek() == Some(&'/');
+					break;
+				},
+
+				'\'' | '"' if !stata || c == '"' => {
+					let mut quoted = String::new();
+
+					loop {
+						match chars.next() {
+							// Quotes are escaped by doubling them.
+							Some(q) if q == c && chars.peek() == Some(&c) => {
+								chars.next();
+								quoted.push(c);
+							},
+							Some(q) if q == c => break,
+							Some(q) => quoted.push(q),
+							None => anyhow::bail!("unterminated string at line {}", line_ix + 1),
+						}
+					}
+
+					tokens.push(Token::Quoted(quoted));
+				},
+
+				'`' if stata && chars.peek() == Some(&'"') => {
+					chars.next();
+
+					let mut quoted = String::new();
+					let mut depth = 1;
+
+					loop {
+						match chars.next() {
+							Some('"') if chars.peek() == Some(&'\'') => {
+								chars.next();
+								depth -= 1;
+
+								if depth == 0 {
+									break;
+								}
+
+								quoted.push_str("\"'");
+							},
+							Some('`') if chars.peek() == Some(&'"') => {
+								chars.next();
+								depth += 1;
+								quoted.push_str("`\"");
+							},
+							Some(q) => quoted.push(q),
+							None => anyhow::bail!("unterminated string at line {}", line_ix + 1),
+						}
+					}
+
+					tokens.push(Token::Quoted(quoted));
+				},
+
+				'`' if stata => {
+					let mut name = String::new();
+
+					loop {
+						match chars.next() {
+							Some('\'') => break,
+							Some(q) => name.push(q),
+							None => anyhow::bail!("unterminated macro at line {}", line_ix + 1),
+						}
+					}
+
+					tokens.push(Token::Macro(name));
+				},
+
+				';' if semicolon => tokens.push(Token::End),
+
+				'/' => tokens.push(Token::Slash),
+				',' => tokens.push(Token::Comma),
+
+				'.' if !stata && chars.clone().all(char::is_whitespace) => {
+					tokens.push(Token::End);
+				},
+
+				c if c.is_whitespace() => (),
+
+				c => {
+					let mut word = c.to_string();
+
+					let delimiters = match (stata, semicolon) {
+						(false, _) => "'\"/,",
+						(true, false) => "\"`/,",
+						(true, true) => "\"`/,;",
+					};
+
+					while let Some(&next) = chars.peek() {
+						let terminator = next == '.' && chars.clone().skip(1).all(char::is_whitespace);
+
+						if next.is_whitespace() || delimiters.contains(next) || terminator {
+							break;
+						}
+
+						word.push(next);
+						chars.next();
+					}
+
+					tokens.push(Token::Word(word));
+				},
+			}
+		}
+
+		if stata && !continued && !in_comment && !semicolon {
+			tokens.push(Token::End);
+		}
+	}
+
+	tokens.push(Token::End);
+
+	Ok(tokens)
+}
+
+
+/// Remove the code prefix from the label, e.g. `(1) Male`.
+pub(crate) fn clean_label(code: &str, label: &str) -> String {
+	let label = label.trim();
+
+	label
+		.strip_prefix('(')
+		.and_then(|rest| rest.strip_prefix(code))
+		.and_then(|rest| rest.strip_prefix(')'))
+		.map(str::trim_start)
+		.unwrap_or(label)
+		.to_owned()
+}
+
+
+/// Whether the word is an abbreviation of the given Stata keyword, e.g. `la` for `label`.
+fn abbreviates(word: &str, keyword: &str, min_len: usize) -> bool {
+	word.len() >= min_len && keyword.starts_with(word)
+}
+
+
+impl Codebook {
+	/// Load the codebook from a setup file, in SPSS or Stata syntax according to its
+	/// extension.
+	pub fn load(path: &Path) -> anyhow::Result<Self> {
+		let contents = fs::read_to_string(path)?;
+
+		match path.extension().and_then(|extension| extension.to_str()) {
+			Some("sps") => Self::parse_spss(&contents),
+			Some("do") => Self::parse_stata(&contents),
+			_ => Err(
+				anyhow::anyhow!("unknown setup file format: {}", path.display())
+			),
+		}
+	}
+
+
+	/// Parse the `VALUE LABELS` commands of an SPSS setup file.
+	pub fn parse_spss(contents: &str) -> anyhow::Result<Self> {
+		let tokens = tokenize(contents, false)?;
+
+		let mut codebook = Self::default();
+		let mut ix = 0;
+
+		let word = |ix: usize| match tokens.get(ix) {
+			Some(Token::Word(word)) => Some(word.to_ascii_uppercase()),
+			_ => None,
+		};
+
+		while ix < tokens.len() {
+			if word(ix).as_deref() != Some("VALUE") || word(ix + 1).as_deref() != Some("LABELS") {
+				ix += 1;
+				continue;
+			}
+
+			ix += 2;
+
+			// Each group is a list of variables followed by code and label pairs.
+			loop {
+				let mut variables = Vec::new();
+
+				while let Some(Token::Word(variable)) = tokens.get(ix) {
+					if let Some(Token::Quoted(_)) = tokens.get(ix + 1) {
+						break;
+					}
+
+					variables.push(variable.clone());
+					ix += 1;
+				}
+
+				let mut labels = Vec::new();
+
+				while let (Some(Token::Word(code) | Token::Quoted(code)), Some(Token::Quoted(label))) = (tokens.get(ix), tokens.get(ix + 1)) {
+					let code = code.trim();
+
+					labels.push((code.to_owned(), clean_label(code, label)));
+					ix += 2;
+				}
+
+				if variables.is_empty() && !labels.is_empty() {
+					anyhow::bail!("value labels without variables");
+				}
+
+				codebook.insert(variables, labels);
+
+				match tokens.get(ix) {
+					Some(Token::Slash) => ix += 1,
+					Some(Token::End) | None => break,
+					Some(token) => anyhow::bail!("unexpected token in value labels: {:?}", token),
+				}
+			}
+		}
+
+		Ok(codebook)
+	}
+
+
+	/// Parse the `label define` and `label values` commands of a Stata setup file. Value
+	/// labels that are not attached to any variable are assumed to share its name.
+	pub fn parse_stata(contents: &str) -> anyhow::Result<Self> {
+		let tokens = tokenize(contents, true)?;
+
+		let mut definitions: Vec<(String, Vec<(String, String)>)> = Vec::new();
+		let mut attached: Vec<(String, String)> = Vec::new();
+
+		for command in tokens.split(|token| *token == Token::End) {
+			let words: Vec<&str> = command
+				.iter()
+				.map_while(
+					|token| match token {
+						Token::Word(word) => Some(word.as_str()),
+						_ => None,
+					}
+				)
+				.collect();
+
+			if words.len() < 3 || !abbreviates(words[0], "label", 2) {
+				continue;
+			}
+
+			if abbreviates(words[1], "define", 3) {
+				let name = words[2].to_owned();
+				let mut labels = Vec::new();
+
+				for pair in command[3 ..].chunks(2) {
+					match pair {
+						[Token::Word(code), Token::Quoted(label)] => {
+							labels.push((code.clone(), clean_label(code, label)));
+						},
+						// Options, e.g. `, modify`.
+						[Token::Comma, ..] => break,
+						_ => anyhow::bail!("invalid label define: {}", name),
+					}
+				}
+
+				match definitions.iter_mut().find(|(other, _)| *other == name) {
+					Some((_, existing)) => existing.extend(labels),
+					None => definitions.push((name, labels)),
+				}
+			}
+			else if abbreviates(words[1], "values", 3) {
+				let (label, variables) = words[2 ..]
+					.split_last()
+					.expect("there should be at least one word");
+
+				for variable in variables {
+					attached.push((variable.to_string(), label.to_string()));
+				}
+			}
+		}
+
+		let mut codebook = Self::default();
+
+		for (name, labels) in definitions {
+			let mut variables: Vec<String> = attached
+				.iter()
+				.filter(|(_, label)| *label == name)
+				.map(|(variable, _)| variable.clone())
+				.collect();
+
+			if variables.is_empty() {
+				variables.push(name);
+			}
+
+			codebook.insert(variables, labels);
+		}
+
+		Ok(codebook)
+	}
+
+
+	pub(crate) fn insert(&mut self, variables: Vec<String>, labels: Vec<(String, String)>) {
+		let labels: Labels = labels.into_boxed_slice();
+
+		for variable in variables {
+			let variable = variable.to_ascii_uppercase();
+
+			match self.variables.iter_mut().find(|(other, _)| *other == variable) {
+				Some((_, existing)) => *existing = labels.clone(),
+				None => self.variables.push((variable, labels.clone())),
+			}
+		}
+	}
+
+
+	/// The value labels of the given variable.
+	pub fn labels(&self, variable: &str) -> Option<&[(String, String)]> {
+		self.variables
+			.iter()
+			.find(|(other, _)| other.eq_ignore_ascii_case(variable))
+			.map(|(_, labels)| labels.as_ref())
+	}
+
+
+	/// Compare the codebook with the codes of the built-in enums.
+	pub fn validate(&self) -> Vec<Mismatch> {
+		let mut mismatches = Vec::new();
+
+		for (variable, codes) in builtin() {
+			let labels = match self.labels(variable) {
+				Some(labels) => labels,
+				None => {
+					mismatches.push(Mismatch::MissingVariable(variable));
+					continue;
+				},
+			};
+
+			for (code, label) in labels {
+				if !codes.iter().any(|(other, _)| other == code) {
+					mismatches.push(
+						Mismatch::UnknownCode {
+							variable,
+							code: code.clone(),
+							label: label.clone(),
+						}
+					);
+				}
+			}
+
+			for (code, value) in codes.iter() {
+				// Blank codes are not part of the codebook, see the `tryfrom!` invocations.
+				if code.trim().is_empty() {
+					continue;
+				}
+
+				if !labels.iter().any(|(other, _)| other == code) {
+					mismatches.push(
+						Mismatch::UndocumentedCode {
+							variable,
+							code: code.to_string(),
+							value: value.clone(),
+						}
+					);
+				}
+			}
+		}
+
+		mismatches
+	}
+
+
+	/// Find the codes present in the delimited (tsv) dataset, but absent from the codebook.
+	/// Only the labeled variables are checked, except for the numeric ones, e.g. years.
+	pub fn check<R: io::BufRead>(&self, reader: R) -> anyhow::Result<Vec<Undocumented>> {
+		let mut line_splitter = util::RawLineSplitter::new(reader);
+
+		let header: Vec<String> = line_splitter
+			.read_line()?
+			.ok_or_else(|| anyhow::anyhow!("empty dataset"))?
+			.split(|&c| c == b'\t')
+			.map(|name| String::from_utf8_lossy(name).trim().to_ascii_uppercase())
+			.collect();
+
+		let columns: Vec<_> = header
+			.iter()
+			.enumerate()
+			.filter(|(_, name)| !NUMERIC.contains(&name.as_str()))
+			.filter_map(
+				|(ix, name)| self
+					.labels(name)
+					.map(|labels| (ix, name.as_str(), labels))
+			)
+			.collect();
+
+		let mut counts: Vec<HashMap<Box<[u8]>, usize>> = vec![HashMap::new(); columns.len()];
+
+		while let Some(line) = line_splitter.read_line()? {
+			let fields: Vec<&[u8]> = line.split(|&c| c == b'\t').collect();
+
+			for ((ix, _, labels), counts) in columns.iter().zip(counts.iter_mut()) {
+				let field = fields.get(*ix).copied().unwrap_or(b"");
+
+				if !labels.iter().any(|(code, _)| code.as_bytes() == field) {
+					*counts.entry(field.into()).or_insert(0) += 1;
+				}
+			}
+		}
+
+		let mut undocumented = Vec::new();
+
+		for ((_, variable, _), counts) in columns.iter().zip(counts) {
+			let mut codes: Vec<_> = counts.into_iter().collect();
+			codes.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+
+			undocumented.extend(
+				codes
+					.into_iter()
+					.map(
+						|(code, count)| Undocumented {
+							variable: variable.to_string(),
+							code: String::from_utf8_lossy(&code).into_owned(),
+							count,
+						}
+					)
+			);
+		}
+
+		Ok(undocumented)
+	}
+
+
+	/// A schema with the value labels of every variable, as a starting point for a new
+	/// study. Values labeled as missing are declared as such, and no variable is an item.
+	pub fn schema(&self, name: &str) -> anyhow::Result<Schema> {
+		let columns = self.variables
+			.iter()
+			.map(
+				|(variable, labels)| {
+					let (missing, codes): (Vec<_>, Vec<_>) = labels
+						.iter()
+						.cloned()
+						.partition(
+							|(_, label)| label.to_ascii_lowercase().contains("missing")
+						);
+
+					schema::Column {
+						name: variable.clone(),
+						attribute: None,
+						item: false,
+						codes,
+						missing: missing
+							.into_iter()
+							.map(|(code, _)| code)
+							.collect(),
+					}
+				}
+			)
+			.collect();
+
+		Schema::new(name.to_owned(), columns)
+	}
+}
+
+
+impl fmt::Display for Codebook {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		for (variable, labels) in self.variables.iter() {
+			writeln!(f, "{}:", variable)?;
+
+			for (code, label) in labels.iter() {
+				writeln!(f, "	{}: {}", code, label)?;
+			}
+		}
+
+		Ok(())
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+
+	fn labels(labels: &[(&str, &str)]) -> Labels {
+		labels
+			.iter()
+			.map(|&(code, label)| (code.to_owned(), label.to_owned()))
+			.collect()
+	}
+
+
+	#[test]
+	fn parse_spss_value_labels() {
+		let contents = r#"
+* SPSS setup file.
+DATA LIST FILE=data /SEX 1 RACE 2 ETHNIC 3.
+/* Value labels of several variables. */
+VALUE LABELS
+  SEX
+    1 '(1) Male'
+    2 '(2) Female' /
+  RACE ETHNIC
+    1 "(1) White"
+    2 'Black or ''African'' American'
+    '3' "(3) Hispanic" /
+  STATE 1 '(1) Alabama'.
+value labels admtype 1 "(1) New" 2 "(2) Parole".
+EXECUTE.
+"#;
+
+		let codebook = Codebook::parse_spss(contents).unwrap();
+
+		let race = labels(&[("1", "White"), ("2", "Black or 'African' American"), ("3", "Hispanic")]);
+
+		assert_eq!(
+			codebook.variables,
+			vec![
+				("SEX".to_owned(), labels(&[("1", "Male"), ("2", "Female")])),
+				("RACE".to_owned(), race.clone()),
+				("ETHNIC".to_owned(), race),
+				("STATE".to_owned(), labels(&[("1", "Alabama")])),
+				("ADMTYPE".to_owned(), labels(&[("1", "New"), ("2", "Parole")])),
+			]
+		);
+
+		assert!(Codebook::parse_spss("VALUE LABELS 1 'Male'.").is_err());
+		assert!(Codebook::parse_spss("VALUE LABELS SEX 1 'Male', 2 'Female'.").is_err());
+		assert!(Codebook::parse_spss("VALUE LABELS SEX 1 'Male.").is_err());
+	}
+
+
+	#[test]
+	fn parse_stata_macros_quotes_and_delimiters() {
+		let contents = r#"
+use `dta_file', clear
+infile using `dict'
+notes SEX: the sex isn't imputed
+label define SEX 1 "(1) Male" 2 `"(2) "Female""' // compound quotes
+#delimit ;
+label define RACE 1 "(1) White"
+	2 "(2) Black" ;
+label values RACE RACE ;
+#delimit cr
+label values SEX SEX
+"#;
+
+		let codebook = Codebook::parse_stata(contents).unwrap();
+
+		assert_eq!(
+			codebook.variables,
+			vec![
+				("SEX".to_owned(), labels(&[("1", "Male"), ("2", "\"Female\"")])),
+				("RACE".to_owned(), labels(&[("1", "White"), ("2", "Black")])),
+			]
+		);
+	}
+}
@@ -2,27 +2,58 @@ use std::collections::HashMap;
 
 use super::*;
 
+use crate::labels;
 
+
+/// The name of an attribute, and the labeled count of each of its values.
+pub type Attribute<'a> = (&'a str, Box<[(String, usize)]>);
+
+
+/// The labeled total weight of each value of an attribute, in the order of `Attribute`.
+pub type WeightedAttribute<'a> = (&'a str, Box<[(String, f64)]>);
+
+
+/// The name of an attribute, and the labeled count and weight of each of its values.
+type Values = (&'static str, Vec<(String, Count)>);
+
+
+/// The number of records with a given value, and their total weight.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Count {
+	pub records: usize,
+	pub weight: f64,
+}
+
+
+/// The count of each value of each field, over a set of records.
 #[derive(Debug, Default)]
 pub struct Distribution {
 	pub total_records: usize,
-	pub sex: HashMap<Sex, usize>,
-	pub admission_type: HashMap<AdmissionType, usize>,
-	pub offense_type: HashMap<OffenseType, usize>,
-	pub education: HashMap<Education, usize>,
-	pub admission_year: HashMap<u16, usize>,
-	pub release_year: HashMap<u16, usize>,
-	pub mandatory_release_year: HashMap<u16, usize>,
-	pub projected_release_year: HashMap<u16, usize>,
-	pub parole_eligibility_year: HashMap<u16, usize>,
-	pub sentence: HashMap<Sentence, usize>,
-	pub offense_detailed_type: HashMap<OffenseDetailedType, usize>,
-	pub race: HashMap<Race, usize>,
-	pub age_admission: HashMap<Age, usize>,
-	pub age_release: HashMap<Age, usize>,
-	pub time_served: HashMap<TimeServed, usize>,
-	pub release_type: HashMap<ReleaseType, usize>,
-	pub state: HashMap<State, usize>,
+	pub sex: HashMap<Sex, Count>,
+	pub admission_type: HashMap<AdmissionType, Count>,
+	pub offense_type: HashMap<OffenseType, Count>,
+	pub education: HashMap<Education, Count>,
+	pub admission_year: HashMap<u16, Count>,
+	pub release_year: HashMap<u16, Count>,
+	pub mandatory_release_year: HashMap<u16, Count>,
+	pub projected_release_year: HashMap<u16, Count>,
+	pub parole_eligibility_year: HashMap<u16, Count>,
+	pub sentence: HashMap<Sentence, Count>,
+	pub offense_detailed_type: HashMap<OffenseDetailedType, Count>,
+	pub race: HashMap<Race, Count>,
+	pub age_admission: HashMap<Age, Count>,
+	pub age_release: HashMap<Age, Count>,
+	pub time_served: HashMap<TimeServed, Count>,
+	pub release_type: HashMap<ReleaseType, Count>,
+	pub state: HashMap<State, Count>,
+	/// The total weight of the records, which is their number if not weighted.
+	pub total_weight: f64,
+	/// The sampling frame, if the records are a sample.
+	pub frame: Option<sample::Frame>,
+	/// The weight of each record, in order, if weighted.
+	pub weights: Option<weight::Weights>,
+	/// The number of records left after each step of the filtering.
+	pub funnel: Funnel,
 }
 
 
@@ -32,15 +63,19 @@ impl Distribution {
 	}
 
 
+	/// Count the values of the given record.
 	pub fn insert(&mut self, record: &Record) {
+		self.insert_weighted(record, 1.0);
+	}
+
+
+	/// Count the values of the given record, with the given weight.
+	pub fn insert_weighted(&mut self, record: &Record, weight: f64) {
 		macro_rules! insert_field {
 			($field: ident) =>  {
-				if let Some(count) = self.$field.get_mut(&record.$field) {
-					*count += 1;
-				}
-				else {
-					self.$field.insert(record.$field, 1);
-				}
+				let count = self.$field.entry(record.$field).or_default();
+				count.records += 1;
+				count.weight += weight;
 			};
 		}
 
@@ -63,6 +98,157 @@ impl Distribution {
 		insert_field!(state);
 
 		self.total_records += 1;
+		self.total_weight += weight;
+	}
+
+
+	/// Replace the weights by those of the given distributions of subsets of the records,
+	/// scaled by the given weight of each subset.
+	pub(super) fn reweight<'a>(&mut self, subsets: impl Iterator<Item = (&'a Distribution, f64)>) {
+		macro_rules! for_fields {
+			($macro: ident) => {
+				$macro!(sex);
+				$macro!(admission_type);
+				$macro!(offense_type);
+				$macro!(education);
+				$macro!(admission_year);
+				$macro!(release_year);
+				$macro!(mandatory_release_year);
+				$macro!(projected_release_year);
+				$macro!(parole_eligibility_year);
+				$macro!(sentence);
+				$macro!(offense_detailed_type);
+				$macro!(race);
+				$macro!(age_admission);
+				$macro!(age_release);
+				$macro!(time_served);
+				$macro!(release_type);
+				$macro!(state);
+			};
+		}
+
+		macro_rules! clear_field {
+			($field: ident) => {
+				for count in self.$field.values_mut() {
+					count.weight = 0.0;
+				}
+			};
+		}
+
+		for_fields!(clear_field);
+
+		self.total_weight = 0.0;
+
+		for (subset, weight) in subsets {
+			macro_rules! add_field {
+				($field: ident) => {
+					for (value, count) in subset.$field.iter() {
+						self.$field.entry(*value).or_default().weight += count.records as f64 * weight;
+					}
+				};
+			}
+
+			for_fields!(add_field);
+
+			self.total_weight += subset.total_records as f64 * weight;
+		}
+	}
+}
+
+
+impl Distribution {
+	/// The counts of each attribute, sorted by count. Enum values are labeled as in
+	/// `Record::labels()`, e.g. `Sex::Male`.
+	pub fn attributes(&self) -> Box<[Attribute<'static>]> {
+		self.values()
+			.into_vec()
+			.into_iter()
+			.map(
+				|(attribute, values)| (
+					attribute,
+					values
+						.into_iter()
+						.map(|(label, count)| (label, count.records))
+						.collect()
+				)
+			)
+			.collect()
+	}
+
+
+	/// The weights of each attribute, in the same order as `attributes()`.
+	pub fn weighted_attributes(&self) -> Box<[WeightedAttribute<'static>]> {
+		self.values()
+			.into_vec()
+			.into_iter()
+			.map(
+				|(attribute, values)| (
+					attribute,
+					values
+						.into_iter()
+						.map(|(label, count)| (label, count.weight))
+						.collect()
+				)
+			)
+			.collect()
+	}
+
+
+	fn values(&self) -> Box<[Values]> {
+		fn sorted(mut values: Vec<(String, Count)>) -> Vec<(String, Count)> {
+			values.sort_by(
+				|(a_label, a_count), (b_label, b_count)| b_count.records
+					.cmp(&a_count.records)
+					.then(a_label.cmp(b_label))
+			);
+
+			values
+		}
+
+		macro_rules! attribute {
+			($field: ident, $type: ident) => {
+				(
+					stringify!($field),
+					sorted(
+						self.$field
+							.iter()
+							.map(|(value, &count)| (format!("{}::{:?}", stringify!($type), value), count))
+							.collect()
+					)
+				)
+			};
+			($field: ident) => {
+				(
+					stringify!($field),
+					sorted(
+						self.$field
+							.iter()
+							.map(|(value, &count)| (value.to_string(), count))
+							.collect()
+					)
+				)
+			};
+		}
+
+		Box::new([
+			attribute!(sex, Sex),
+			attribute!(admission_type, AdmissionType),
+			attribute!(offense_type, OffenseType),
+			attribute!(education, Education),
+			attribute!(admission_year),
+			attribute!(release_year),
+			attribute!(mandatory_release_year),
+			attribute!(projected_release_year),
+			attribute!(parole_eligibility_year),
+			attribute!(sentence, Sentence),
+			attribute!(offense_detailed_type, OffenseDetailedType),
+			attribute!(race, Race),
+			attribute!(age_admission, Age),
+			attribute!(age_release, Age),
+			attribute!(time_served, TimeServed),
+			attribute!(release_type, ReleaseType),
+			attribute!(state, State),
+		])
 	}
 }
 
@@ -71,37 +257,35 @@ impl std::fmt::Display for Distribution {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		let total_records = self.total_records as f64;
 
-		macro_rules! print_field {
-			($field: ident) =>  {
-				writeln!(f, "{}:", stringify!($field))?;
+		writeln!(f, "records: {}", self.total_records)?;
 
-				for (value, count) in &self.$field {
-					let percentage = (*count as f64 * 100.0) / total_records;
+		if let Some(frame) = &self.frame {
+			write!(f, "sample: {}", frame)?;
+		}
 
-					writeln!(f, "	{:?}: {} ({:.1}%)", value, count, percentage,)?;
-				}
-			};
+		let weighted = self.weights.is_some();
+
+		if weighted {
+			writeln!(f, "weight: {:.1}", self.total_weight)?;
 		}
 
-		writeln!(f, "records: {}", self.total_records)?;
+		for (attribute, values) in self.values().iter() {
+			writeln!(f, "{}:", labels::attribute(attribute))?;
+
+			for (label, count) in values.iter() {
+				let percentage = (count.records as f64 * 100.0) / total_records;
+
+				write!(f, "	{}: {} ({:.1}%)", labels::value(label), count.records, percentage)?;
+
+				if weighted {
+					let percentage = count.weight * 100.0 / self.total_weight;
 
-		print_field!(sex);
-		print_field!(admission_type);
-		print_field!(offense_type);
-		print_field!(education);
-		print_field!(admission_year);
-		print_field!(release_year);
-		print_field!(mandatory_release_year);
-		print_field!(projected_release_year);
-		print_field!(parole_eligibility_year);
-		print_field!(sentence);
-		print_field!(offense_detailed_type);
-		print_field!(race);
-		print_field!(age_admission);
-		print_field!(age_release);
-		print_field!(time_served);
-		print_field!(release_type);
-		print_field!(state);
+					write!(f, ", weighted {:.1} ({:.1}%)", count.weight, percentage)?;
+				}
+
+				writeln!(f)?;
+			}
+		}
 
 		Ok(())
 	}
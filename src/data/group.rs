@@ -0,0 +1,217 @@
+//! External-memory grouping of the records by inmate id. The records are sorted in runs
+//! that are spilled to temporary files, which are then merged, so that only one run is held
+//! in memory at a time.
+
+use std::{
+	cmp::Reverse,
+	collections::BinaryHeap,
+	fs,
+	io::{self, Read, Write},
+	path::PathBuf,
+	sync::atomic::{AtomicUsize, Ordering},
+};
+
+use super::Record;
+
+
+/// The number of records of each run.
+pub(super) const RUN_LEN: usize = 1 << 20;
+
+
+/// The number of runs spilled by the process, to name their files.
+static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+
+/// A sorted run, in a temporary file that is removed when dropped.
+struct Run {
+	path: PathBuf,
+}
+
+
+impl Drop for Run {
+	fn drop(&mut self) {
+		let _ = fs::remove_file(&self.path);
+	}
+}
+
+
+/// An entry of a run: the inmate id, the sequence number in the dataset, the record and its
+/// weight.
+type Entry = (Box<[u8]>, u64, Record, f64);
+
+
+pub(super) struct External {
+	/// The number of records of each run.
+	run_len: usize,
+	/// The entries of the current run.
+	buffer: Vec<Entry>,
+	runs: Vec<Run>,
+	count: u64,
+	/// The first error while spilling, reported when finishing.
+	error: Option<io::Error>,
+}
+
+
+impl External {
+	pub(super) fn new(run_len: usize) -> Self {
+		Self {
+			run_len,
+			buffer: Vec::new(),
+			runs: Vec::new(),
+			count: 0,
+			error: None,
+		}
+	}
+
+
+	pub(super) fn insert(&mut self, id: &[u8], record: Record, weight: f64) {
+		self.buffer.push((id.into(), self.count, record, weight));
+		self.count += 1;
+
+		if self.buffer.len() >= self.run_len && self.error.is_none() {
+			if let Err(error) = self.spill() {
+				self.error = Some(error);
+			}
+		}
+	}
+
+
+	/// Sort the current run and write it to a temporary file.
+	fn spill(&mut self) -> io::Result<()> {
+		self.buffer.sort_unstable_by(|(a, a_seq, ..), (b, b_seq, ..)| (a, a_seq).cmp(&(b, b_seq)));
+
+		let path = std::env::temp_dir().join(
+			format!("icpsr-36404-{}-{}.run", std::process::id(), RUNS.fetch_add(1, Ordering::Relaxed))
+		);
+
+		let file = fs::OpenOptions::new()
+			.write(true)
+			.create_new(true)
+			.open(&path)?;
+
+		self.runs.push(Run { path });
+
+		let mut writer = io::BufWriter::new(file);
+
+		for (id, seq, record, weight) in self.buffer.drain(..) {
+			let record = rmp_serde::to_vec(&record)
+				.map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+
+			writer.write_all(&(id.len() as u32).to_le_bytes())?;
+			writer.write_all(&id)?;
+			writer.write_all(&seq.to_le_bytes())?;
+			writer.write_all(&weight.to_le_bytes())?;
+			writer.write_all(&(record.len() as u32).to_le_bytes())?;
+			writer.write_all(&record)?;
+		}
+
+		writer.flush()?;
+
+		log::debug!("Spilled run {} to {}", self.runs.len(), self.runs[self.runs.len() - 1].path.display());
+
+		Ok(())
+	}
+
+
+	/// Merge the runs, calling `group` with the id of each inmate, and their records and
+	/// weights in the dataset order. The groups are given in the order of the ids.
+	pub(super) fn finish(mut self, mut group: impl FnMut(&[u8], &[(Record, f64)])) -> anyhow::Result<()> {
+		if let Some(error) = self.error.take() {
+			return Err(error.into());
+		}
+
+		if !self.runs.is_empty() && !self.buffer.is_empty() {
+			self.spill()?;
+		}
+
+		let mut records = Vec::new();
+		let mut current: Option<Box<[u8]>> = None;
+
+		let mut emit = |id: Box<[u8]>, record: (Record, f64)| {
+			if current.as_ref() != Some(&id) {
+				if let Some(current) = &current {
+					group(current, &records);
+					records.clear();
+				}
+
+				current = Some(id);
+			}
+
+			records.push(record);
+		};
+
+		if self.runs.is_empty() {
+			// Everything fits in a single run, which need not be spilled.
+			self.buffer.sort_unstable_by(|(a, a_seq, ..), (b, b_seq, ..)| (a, a_seq).cmp(&(b, b_seq)));
+
+			for (id, _, record, weight) in self.buffer.drain(..) {
+				emit(id, (record, weight));
+			}
+		}
+		else {
+			let mut readers = self.runs
+				.iter()
+				.map(|run| Ok(io::BufReader::new(fs::File::open(&run.path)?)))
+				.collect::<io::Result<Vec<_>>>()?;
+
+			let mut pending: Vec<Option<(Record, f64)>> = vec![None; readers.len()];
+			let mut heap = BinaryHeap::new();
+
+			for (ix, reader) in readers.iter_mut().enumerate() {
+				if let Some((id, seq, record, weight)) = read_entry(reader)? {
+					pending[ix] = Some((record, weight));
+					heap.push(Reverse((id, seq, ix)));
+				}
+			}
+
+			while let Some(Reverse((id, _, ix))) = heap.pop() {
+				let record = pending[ix].take().expect("merged run should have a pending record");
+
+				if let Some((id, seq, record, weight)) = read_entry(&mut readers[ix])? {
+					pending[ix] = Some((record, weight));
+					heap.push(Reverse((id, seq, ix)));
+				}
+
+				emit(id, record);
+			}
+		}
+
+		if let Some(current) = &current {
+			group(current, &records);
+		}
+
+		Ok(())
+	}
+}
+
+
+/// Read the next entry of a run, as written by `spill`.
+fn read_entry<R: Read>(reader: &mut R) -> anyhow::Result<Option<Entry>> {
+	let mut len = [0; 4];
+
+	match reader.read_exact(&mut len) {
+		Ok(()) => (),
+		Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+		Err(error) => return Err(error.into()),
+	}
+
+	let mut id = vec![0; u32::from_le_bytes(len) as usize];
+	reader.read_exact(&mut id)?;
+
+	let mut seq = [0; 8];
+	reader.read_exact(&mut seq)?;
+
+	let mut weight = [0; 8];
+	reader.read_exact(&mut weight)?;
+
+	reader.read_exact(&mut len)?;
+
+	let mut record = vec![0; u32::from_le_bytes(len) as usize];
+	reader.read_exact(&mut record)?;
+
+	Ok(
+		Some(
+			(id.into(), u64::from_le_bytes(seq), rmp_serde::from_slice(&record)?, f64::from_le_bytes(weight))
+		)
+	)
+}
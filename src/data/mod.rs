@@ -1,4 +1,16 @@
+//! The ICPSR 36404 records, as described in the study codebook. Each categorical field is
+//! an enum, parsed from its numeric code in the delimited dataset.
+
+pub mod cache;
+pub mod codebook;
 pub mod distribution;
+mod group;
+pub(crate) mod package;
+mod reader;
+pub mod sample;
+pub mod weight;
+
+pub use reader::{Filter, Funnel, Grouping, read_file, read_records, stream_file, stream_records};
 
 use std::convert::TryFrom;
 
@@ -7,14 +19,35 @@ use serde::{Serialize, Deserialize};
 use onehot::OneHot;
 
 
+/// The distinct values of the given codes, in the codebook order.
+pub(crate) fn variants<T: Copy + PartialEq>(codes: &[(&[u8], T)]) -> Vec<T> {
+	let mut variants: Vec<T> = Vec::new();
+
+	for &(_, variant) in codes {
+		if !variants.contains(&variant) {
+			variants.push(variant);
+		}
+	}
+
+	variants
+}
+
+
 macro_rules! tryfrom {
-	($name: ident, $( $pattern: pat => $value: expr ),+) => {
+	($name: ident, $( $code: literal => $value: expr ),+) => {
+		impl $name {
+			/// The code of each value, as in the codebook.
+			pub const CODES: &'static [(&'static [u8], $name)] = &[
+				$( ($code, $value) ),+
+			];
+		}
+
 		impl TryFrom<&[u8]> for $name {
 			type Error = String;
 
 			fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
 				match value {
-					$( $pattern => Ok($value) ),+,
+					$( $code => Ok($value) ),+,
 					_ => Err(
 						format!(
 							"invalid {}: {}",
@@ -29,6 +62,7 @@ macro_rules! tryfrom {
 }
 
 
+/// The inmate's sex (`SEX`).
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 #[derive(Serialize, Deserialize)]
 #[derive(OneHot)]
@@ -44,6 +78,7 @@ tryfrom!(
 );
 
 
+/// The type of admission to prison (`ADMTYPE`).
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 #[derive(Serialize, Deserialize)]
 #[derive(OneHot)]
@@ -64,6 +99,7 @@ tryfrom!(
 );
 
 
+/// The most serious offense category (`OFFGENERAL`).
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 #[derive(Serialize, Deserialize)]
 #[derive(OneHot)]
@@ -88,6 +124,7 @@ tryfrom!(
 );
 
 
+/// The most serious offense, in detail (`OFFDETAIL`).
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 #[derive(Serialize, Deserialize)]
 #[derive(OneHot)]
@@ -130,6 +167,7 @@ tryfrom!(
 );
 
 
+/// The highest education level (`EDUCATION`).
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 #[derive(Serialize, Deserialize)]
 #[derive(OneHot)]
@@ -150,6 +188,7 @@ tryfrom!(
 );
 
 
+/// The inmate's race or Hispanic origin (`RACE`).
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 #[derive(Serialize, Deserialize)]
 #[derive(OneHot)]
@@ -172,6 +211,7 @@ tryfrom!(
 );
 
 
+/// An age range, at admission (`AGEADMIT`) or at release (`AGERELEASE`).
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 #[derive(Serialize, Deserialize)]
 #[derive(OneHot)]
@@ -199,6 +239,7 @@ tryfrom!(
 );
 
 
+/// The time served in prison (`TIMESRVD`).
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 #[derive(Serialize, Deserialize)]
 #[derive(OneHot)]
@@ -224,6 +265,7 @@ tryfrom!(
 );
 
 
+/// The sentence length (`SENTLGTH`).
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 #[derive(Serialize, Deserialize)]
 #[derive(OneHot)]
@@ -255,6 +297,7 @@ tryfrom!(
 );
 
 
+/// The type of release from prison (`RELTYPE`).
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 #[derive(Serialize, Deserialize)]
 #[derive(OneHot)]
@@ -276,6 +319,7 @@ tryfrom!(
 );
 
 
+/// The state that submitted the record (`STATE`).
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 #[derive(Serialize, Deserialize)]
 #[derive(OneHot)]
@@ -390,6 +434,32 @@ tryfrom!(
 );
 
 
+/// The columns of the delimited dataset, in order: the inmate id, followed by the fields
+/// parsed by `Record::parse`.
+pub const COLUMNS: [&str; 18] = [
+	"ABT_INMATE_ID",
+	"SEX",
+	"ADMTYPE",
+	"OFFGENERAL",
+	"EDUCATION",
+	"ADMITYR",
+	"RELEASEYR",
+	"MAND_PRISREL_YEAR",
+	"PROJ_PRISREL_YEAR",
+	"PARELIG_YEAR",
+	"SENTLGTH",
+	"OFFDETAIL",
+	"RACE",
+	"AGEADMIT",
+	"AGERELEASE",
+	"TIMESRVD",
+	"RELTYPE",
+	"STATE",
+];
+
+
+/// A single prison term record. Only the fields which are not ignored are one-hot encoded
+/// as items.
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 #[derive(Serialize, Deserialize)]
 #[derive(OneHot)]
@@ -425,6 +495,8 @@ pub struct Record {
 
 
 impl Record {
+	/// Parse a record from the fields of a line of the delimited dataset, excluding the
+	/// inmate id.
 	pub fn parse<'a>(mut fields: impl Iterator<Item = &'a[u8]>) -> Result<Self, String> {
 		fn parse<'a, T>(fields: &mut impl Iterator<Item = &'a[u8]>) -> Result<T, String>
 		where
@@ -441,7 +513,7 @@ impl Record {
 						::try_from(field)
 						.map_err(Into::into)
 				)
-		};
+		}
 
 		use std::{
 			error::Error,
@@ -476,7 +548,7 @@ impl Record {
 				.map_err(
 					|error| error.to_string()
 				)
-		};
+		}
 
 		let record = Record {
 			sex                     : parse(&mut fields)?,
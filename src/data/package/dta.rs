@@ -0,0 +1,510 @@
+//! Stata data files (`.dta`), in the formats of Stata 13 and later (releases 117 to 119).
+
+use std::io::{Read, Seek, SeekFrom};
+
+use super::{
+	code,
+	BLANK,
+	read_bytes,
+	trim,
+	Codebook,
+	Endian,
+	Source,
+};
+
+
+/// The position of each section in the map.
+const MAP_VARIABLE_TYPES: usize = 2;
+const MAP_VARNAMES: usize = 3;
+const MAP_VALUE_LABEL_NAMES: usize = 6;
+const MAP_DATA: usize = 9;
+const MAP_VALUE_LABELS: usize = 11;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Type {
+	/// A fixed width string.
+	Str(usize),
+	/// A long string, stored separately. These are not decoded.
+	StrL,
+	Double,
+	Float,
+	Long,
+	Int,
+	Byte,
+}
+
+
+impl Type {
+	fn new(code: u16) -> anyhow::Result<Self> {
+		Ok(
+			match code {
+				1 ..= 2045 => Type::Str(code as usize),
+				32768 => Type::StrL,
+				65526 => Type::Double,
+				65527 => Type::Float,
+				65528 => Type::Long,
+				65529 => Type::Int,
+				65530 => Type::Byte,
+				_ => anyhow::bail!("invalid variable type: {}", code),
+			}
+		)
+	}
+
+
+	fn width(self) -> usize {
+		match self {
+			Type::Str(width) => width,
+			Type::StrL | Type::Double => 8,
+			Type::Float | Type::Long => 4,
+			Type::Int => 2,
+			Type::Byte => 1,
+		}
+	}
+}
+
+
+#[derive(Debug, Clone)]
+struct Variable {
+	kind: Type,
+	/// The code labeled as missing, if any.
+	missing: Option<String>,
+}
+
+
+pub struct Reader<R> {
+	reader: R,
+	endian: Endian,
+	variables: Box<[Variable]>,
+	columns: Box<[String]>,
+	codebook: Codebook,
+	/// The number of rows left.
+	remaining: u64,
+	buffer: Vec<u8>,
+}
+
+
+/// Read the given tag, e.g. `<header>`.
+fn expect_tag<R: Read>(reader: &mut R, tag: &str) -> anyhow::Result<()> {
+	if read_bytes(reader, tag.len())? != tag.as_bytes() {
+		anyhow::bail!("invalid Stata file: expected {}", tag);
+	}
+
+	Ok(())
+}
+
+
+impl<R: Read + Seek> Reader<R> {
+	/// Read the file header, the variable descriptors and the value labels.
+	pub fn new(mut reader: R) -> anyhow::Result<Self> {
+		expect_tag(&mut reader, "<stata_dta><header><release>")?;
+
+		let release: u32 = String::from_utf8_lossy(&read_bytes(&mut reader, 3)?)
+			.parse()
+			.map_err(|_| anyhow::anyhow!("unsupported Stata file, older than release 117"))?;
+
+		if !(117 ..= 119).contains(&release) {
+			anyhow::bail!("unsupported Stata release: {}", release);
+		}
+
+		expect_tag(&mut reader, "</release><byteorder>")?;
+
+		let endian = match &read_bytes(&mut reader, 3)?[..] {
+			b"MSF" => Endian { big: true },
+			b"LSF" => Endian { big: false },
+			_ => anyhow::bail!("invalid byte order"),
+		};
+
+		expect_tag(&mut reader, "</byteorder><K>")?;
+
+		let variables_count = match release {
+			119 => endian.u32(&read_bytes(&mut reader, 4)?) as usize,
+			_ => endian.u16(&read_bytes(&mut reader, 2)?) as usize,
+		};
+
+		expect_tag(&mut reader, "</K><N>")?;
+
+		let rows = match release {
+			117 => endian.u32(&read_bytes(&mut reader, 4)?) as u64,
+			_ => endian.u64(&read_bytes(&mut reader, 8)?),
+		};
+
+		let name_len = if release == 117 { 33 } else { 129 };
+
+		let mut map = [0; 14];
+
+		// The map is found right after the header, whose remaining fields are not needed.
+		let mut header = Vec::new();
+
+		loop {
+			let mut byte = [0; 1];
+			reader.read_exact(&mut byte)?;
+			header.push(byte[0]);
+
+			if header.ends_with(b"</header><map>") {
+				break;
+			}
+		}
+
+		for offset in map.iter_mut() {
+			*offset = endian.u64(&read_bytes(&mut reader, 8)?);
+		}
+
+		let mut section = |ix: usize, tag: &str, len: usize| -> anyhow::Result<Vec<u8>> {
+			reader.seek(SeekFrom::Start(map[ix]))?;
+			expect_tag(&mut reader, tag)?;
+			Ok(read_bytes(&mut reader, len)?)
+		};
+
+		let types = section(MAP_VARIABLE_TYPES, "<variable_types>", 2 * variables_count)?
+			.chunks(2)
+			.map(|code| Type::new(endian.u16(code)))
+			.collect::<anyhow::Result<Vec<_>>>()?;
+
+		let names: Box<[String]> = section(MAP_VARNAMES, "<varnames>", name_len * variables_count)?
+			.chunks(name_len)
+			.map(|name| String::from_utf8_lossy(trim(name)).into_owned())
+			.collect();
+
+		let label_names: Box<[String]> = section(MAP_VALUE_LABEL_NAMES, "<value_label_names>", name_len * variables_count)?
+			.chunks(name_len)
+			.map(|name| String::from_utf8_lossy(trim(name)).into_owned())
+			.collect();
+
+		section(MAP_VALUE_LABELS, "<value_labels>", 0)?;
+
+		let mut tables = Vec::new();
+
+		loop {
+			let tag = read_bytes(&mut reader, 5)?;
+
+			if tag != b"<lbl>" {
+				break;
+			}
+
+			let len = endian.i32(&read_bytes(&mut reader, 4)?) as usize;
+			let name = String::from_utf8_lossy(trim(&read_bytes(&mut reader, name_len)?)).into_owned();
+			read_bytes(&mut reader, 3)?; // padding
+			let table = read_bytes(&mut reader, len)?;
+
+			expect_tag(&mut reader, "</lbl>")?;
+
+			let count = endian.i32(&table[0 ..]) as usize;
+			let text_len = endian.i32(&table[4 ..]) as usize;
+			let offsets = &table[8 ..];
+			let values = &table[8 + 4 * count ..];
+			let text = &table[8 + 8 * count ..];
+
+			let labels: Vec<(String, String)> = (0 .. count)
+				.map(
+					|ix| {
+						let code = endian.i32(&values[4 * ix ..]).to_string();
+						let offset = (endian.i32(&offsets[4 * ix ..]) as usize).min(text_len);
+						let label = String::from_utf8_lossy(trim(&text[offset .. text_len])).into_owned();
+						let label = crate::data::codebook::clean_label(&code, &label);
+
+						(code, label)
+					}
+				)
+				.collect();
+
+			tables.push((name, labels));
+		}
+
+		let mut codebook = Codebook::default();
+
+		for (name, labels) in tables.iter() {
+			let variables = names
+				.iter()
+				.zip(label_names.iter())
+				.filter(|(_, label_name)| *label_name == name)
+				.map(|(variable, _)| variable.clone())
+				.collect();
+
+			codebook.insert(variables, labels.clone());
+		}
+
+		let variables = types
+			.into_iter()
+			.zip(label_names.iter())
+			.map(
+				|(kind, label_name)| Variable {
+					kind,
+					missing: tables
+						.iter()
+						.find(|(name, _)| name == label_name)
+						.and_then(
+							|(_, labels)| labels
+								.iter()
+								.find(|(_, label)| label.to_ascii_lowercase().contains("missing"))
+						)
+						.map(|(code, _)| code.clone()),
+				}
+			)
+			.collect();
+
+		reader.seek(SeekFrom::Start(map[MAP_DATA]))?;
+		expect_tag(&mut reader, "<data>")?;
+
+		Ok(
+			Self {
+				reader,
+				endian,
+				variables,
+				columns: names,
+				codebook,
+				remaining: rows,
+				buffer: Vec::new(),
+			}
+		)
+	}
+}
+
+
+impl<R: Read + Seek> Source for Reader<R> {
+	fn columns(&self) -> &[String] {
+		&self.columns
+	}
+
+
+	fn codebook(&self) -> &Codebook {
+		&self.codebook
+	}
+
+
+	fn read_row(&mut self, row: &mut Vec<Vec<u8>>) -> anyhow::Result<bool> {
+		if self.remaining == 0 {
+			return Ok(false);
+		}
+
+		self.remaining -= 1;
+
+		let width = self.variables
+			.iter()
+			.map(|variable| variable.kind.width())
+			.sum();
+
+		self.buffer.resize(width, 0);
+		self.reader.read_exact(&mut self.buffer)?;
+
+		row.resize(self.variables.len(), Vec::new());
+
+		let endian = self.endian;
+		let mut offset = 0;
+
+		for (variable, field) in self.variables.iter().zip(row.iter_mut()) {
+			let bytes = &self.buffer[offset .. offset + variable.kind.width()];
+			offset += variable.kind.width();
+
+			field.clear();
+
+			// Missing values are the largest ones of each type.
+			let value = match variable.kind {
+				Type::Str(_) => {
+					field.extend_from_slice(trim(bytes));
+					continue;
+				},
+				Type::StrL => continue,
+				Type::Byte => Some(bytes[0] as i8 as f64).filter(|&value| value <= 100.0),
+				Type::Int => Some(endian.i16(bytes) as f64).filter(|&value| value <= 32_740.0),
+				Type::Long => Some(endian.i32(bytes) as f64).filter(|&value| value <= 2_147_483_620.0),
+				Type::Float => Some(endian.f32(bytes)).filter(|&value| value <= f32::from_bits(0x7eff_ffff)).map(f64::from),
+				Type::Double => Some(endian.f64(bytes)).filter(|&value| value <= f64::from_bits(0x7fdf_ffff_ffff_ffff)),
+			};
+
+			match (value, &variable.missing) {
+				(Some(value), _) => field.extend_from_slice(code(value).as_bytes()),
+				(None, Some(missing)) => field.extend_from_slice(missing.as_bytes()),
+				(None, None) => field.extend_from_slice(BLANK),
+			}
+		}
+
+		Ok(true)
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use std::io;
+
+	use super::*;
+
+
+	fn padded(text: &str, len: usize) -> Vec<u8> {
+		let mut bytes = text.as_bytes().to_vec();
+		bytes.resize(len, 0);
+		bytes
+	}
+
+
+	/// A value label table.
+	fn table(name: &str, name_len: usize, labels: &[(i32, &str)]) -> Vec<u8> {
+		let mut offsets = Vec::new();
+		let mut values = Vec::new();
+		let mut text = Vec::new();
+
+		for (value, label) in labels {
+			offsets.extend_from_slice(&(text.len() as i32).to_le_bytes());
+			values.extend_from_slice(&value.to_le_bytes());
+			text.extend_from_slice(label.as_bytes());
+			text.push(0);
+		}
+
+		let mut table = (labels.len() as i32).to_le_bytes().to_vec();
+		table.extend_from_slice(&(text.len() as i32).to_le_bytes());
+		table.extend(offsets);
+		table.extend(values);
+		table.extend(text);
+
+		let mut lbl = b"<lbl>".to_vec();
+		lbl.extend_from_slice(&(table.len() as i32).to_le_bytes());
+		lbl.extend(padded(name, name_len));
+		lbl.extend_from_slice(&[0; 3]);
+		lbl.extend(table);
+		lbl.extend_from_slice(b"</lbl>");
+
+		lbl
+	}
+
+
+	/// A file of the given release, with a labeled byte variable and one of each other type.
+	fn file(release: u32) -> Vec<u8> {
+		let name_len = if release == 117 { 33 } else { 129 };
+
+		let types: &[u16] = &[65530, 8, 65529, 65526, 65527, 65528];
+		let names = ["sex", "state", "age", "weight", "score", "id"];
+
+		let rows: &[(i8, &str, i16, f64, f32, i32)] = &[
+			(1, "Alabama", 30, 1.5, 2.5, 7),
+			// The smallest missing value of each type.
+			(101, "Texas", 32_741, f64::from_bits(0x7fe0_0000_0000_0000), f32::from_bits(0x7f00_0000), 2_147_483_621),
+			// The largest valid value of each integer type.
+			(100, "", 32_740, -2.0, -0.5, 2_147_483_620),
+		];
+
+		let mut file = b"<stata_dta><header><release>".to_vec();
+		file.extend_from_slice(release.to_string().as_bytes());
+		file.extend_from_slice(b"</release><byteorder>LSF</byteorder><K>");
+
+		match release {
+			119 => file.extend_from_slice(&(types.len() as u32).to_le_bytes()),
+			_ => file.extend_from_slice(&(types.len() as u16).to_le_bytes()),
+		}
+
+		file.extend_from_slice(b"</K><N>");
+
+		match release {
+			117 => file.extend_from_slice(&(rows.len() as u32).to_le_bytes()),
+			_ => file.extend_from_slice(&(rows.len() as u64).to_le_bytes()),
+		}
+
+		file.extend_from_slice(b"<label>\0</label><timestamp>\0</timestamp></header><map>");
+
+		let map_offset = file.len();
+		file.resize(map_offset + 14 * 8, 0);
+
+		let mut map = [0u64; 14];
+		let mut section = |file: &mut Vec<u8>, ix: usize, contents: Vec<u8>| {
+			map[ix] = file.len() as u64;
+			file.extend(contents);
+		};
+
+		let mut variable_types = b"<variable_types>".to_vec();
+		variable_types.extend(types.iter().flat_map(|kind| kind.to_le_bytes()));
+		section(&mut file, MAP_VARIABLE_TYPES, variable_types);
+
+		let mut varnames = b"<varnames>".to_vec();
+		varnames.extend(names.iter().flat_map(|name| padded(name, name_len)));
+		section(&mut file, MAP_VARNAMES, varnames);
+
+		let mut label_names = b"<value_label_names>".to_vec();
+		label_names.extend(padded("sexlbl", name_len));
+		label_names.extend(padded("", name_len * (names.len() - 1)));
+		section(&mut file, MAP_VALUE_LABEL_NAMES, label_names);
+
+		let mut data = b"<data>".to_vec();
+
+		for &(sex, state, age, weight, score, id) in rows {
+			data.push(sex as u8);
+			data.extend(padded(state, 8));
+			data.extend_from_slice(&age.to_le_bytes());
+			data.extend_from_slice(&weight.to_le_bytes());
+			data.extend_from_slice(&score.to_le_bytes());
+			data.extend_from_slice(&id.to_le_bytes());
+		}
+
+		data.extend_from_slice(b"</data>");
+		section(&mut file, MAP_DATA, data);
+
+		let mut value_labels = b"<value_labels>".to_vec();
+		value_labels.extend(table("sexlbl", name_len, &[(1, "(1) Male"), (2, "(2) Female"), (9, "(9) Missing")]));
+		value_labels.extend_from_slice(b"</value_labels>");
+		section(&mut file, MAP_VALUE_LABELS, value_labels);
+
+		for (ix, offset) in map.iter().enumerate() {
+			file[map_offset + 8 * ix .. map_offset + 8 * (ix + 1)].copy_from_slice(&offset.to_le_bytes());
+		}
+
+		file
+	}
+
+
+	#[test]
+	fn releases() {
+		for &release in &[117, 118, 119] {
+			let mut reader = Reader::new(io::Cursor::new(file(release))).unwrap();
+
+			assert_eq!(reader.columns(), ["sex", "state", "age", "weight", "score", "id"]);
+
+			assert_eq!(
+				reader.codebook().variables,
+				vec![
+					(
+						"SEX".to_owned(),
+						vec![
+							("1".to_owned(), "Male".to_owned()),
+							("2".to_owned(), "Female".to_owned()),
+							("9".to_owned(), "Missing".to_owned()),
+						]
+						.into(),
+					),
+				]
+			);
+
+			let mut rows = Vec::new();
+			let mut row = Vec::new();
+
+			while reader.read_row(&mut row).unwrap() {
+				rows.push(
+					row
+						.iter()
+						.map(|field| String::from_utf8_lossy(field).into_owned())
+						.collect::<Vec<_>>()
+				);
+			}
+
+			assert_eq!(
+				rows,
+				[
+					["1", "Alabama", "30", "1.5", "2.5", "7"],
+					// Missing values are labeled, or blank.
+					["9", "Texas", " ", " ", " ", " "],
+					["100", "", "32740", "-2", "-0.5", "2147483620"],
+				],
+				"release {}",
+				release
+			);
+		}
+	}
+
+
+	#[test]
+	fn unsupported_releases() {
+		let mut file = file(117);
+		file[28 .. 31].copy_from_slice(b"116");
+
+		assert!(Reader::new(io::Cursor::new(file)).is_err());
+		assert!(Reader::new(io::Cursor::new(b"<stata_dta><header><release>115".to_vec())).is_err());
+	}
+}
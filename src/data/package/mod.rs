@@ -0,0 +1,130 @@
+//! Readers for the distributions of the dataset in the formats of statistical packages. The
+//! values are decoded into the codes of the delimited format, so that they can be parsed as
+//! records.
+
+mod dta;
+mod sav;
+
+use std::{
+	fs,
+	io,
+	path::Path,
+};
+
+use super::codebook::Codebook;
+
+
+/// A dataset in the format of a statistical package.
+pub trait Source {
+	/// The column names, in the file order.
+	fn columns(&self) -> &[String];
+
+	/// The embedded value labels.
+	fn codebook(&self) -> &Codebook;
+
+	/// Read the next row, one field per column. Missing values are given by the column's
+	/// declared missing code, if any, or by a blank, as in the delimited format. Returns false
+	/// at the end of the data.
+	fn read_row(&mut self, row: &mut Vec<Vec<u8>>) -> anyhow::Result<bool>;
+}
+
+
+/// Open the given file according to its extension: `.sav` for SPSS, and `.dta` for Stata.
+/// Returns none for other extensions.
+pub fn open(path: &Path) -> anyhow::Result<Option<Box<dyn Source>>> {
+	let extension = path
+		.extension()
+		.and_then(|extension| extension.to_str())
+		.map(str::to_ascii_lowercase);
+
+	let reader = || -> anyhow::Result<_> {
+		Ok(io::BufReader::new(fs::File::open(path)?))
+	};
+
+	Ok(
+		match extension.as_deref() {
+			Some("sav") => Some(Box::new(sav::Reader::new(reader()?)?)),
+			Some("dta") => Some(Box::new(dta::Reader::new(reader()?)?)),
+			_ => None,
+		}
+	)
+}
+
+
+/// The code of a numeric value, e.g. `1` for `1.0`.
+fn code(value: f64) -> String {
+	if value.fract() == 0.0 && value.abs() < 1e15 {
+		format!("{}", value as i64)
+	}
+	else {
+		format!("{}", value)
+	}
+}
+
+
+/// The code of a missing value without a declared missing code.
+const BLANK: &[u8] = b" ";
+
+
+/// Trim the padding of a fixed width string.
+fn trim(bytes: &[u8]) -> &[u8] {
+	let end = bytes
+		.iter()
+		.position(|&c| c == 0)
+		.unwrap_or(bytes.len());
+
+	let bytes = &bytes[.. end];
+
+	let end = bytes
+		.iter()
+		.rposition(|&c| c != b' ')
+		.map_or(0, |ix| ix + 1);
+
+	&bytes[.. end]
+}
+
+
+/// Decode numbers in either byte order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Endian {
+	big: bool,
+}
+
+
+macro_rules! decode {
+	($name: ident, $type: ty) => {
+		fn $name(self, bytes: &[u8]) -> $type {
+			let mut buffer = [0; std::mem::size_of::<$type>()];
+			let len = buffer.len();
+			buffer.copy_from_slice(&bytes[.. len]);
+
+			if self.big {
+				<$type>::from_be_bytes(buffer)
+			}
+			else {
+				<$type>::from_le_bytes(buffer)
+			}
+		}
+	};
+}
+
+
+impl Endian {
+	decode!(i16, i16);
+	decode!(u16, u16);
+	decode!(i32, i32);
+	decode!(u32, u32);
+	decode!(u64, u64);
+	decode!(f32, f32);
+	decode!(f64, f64);
+}
+
+
+/// Read exactly `len` bytes.
+fn read_bytes<R: io::Read>(reader: &mut R, len: usize) -> io::Result<Vec<u8>> {
+	let mut buffer = vec![0; len];
+
+	reader.read_exact(&mut buffer)?;
+
+	Ok(buffer)
+}
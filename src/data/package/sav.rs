@@ -0,0 +1,606 @@
+//! SPSS system files (`.sav`), uncompressed or with bytecode compression.
+
+use std::{
+	collections::HashMap,
+	io::Read,
+};
+
+use super::{
+	code,
+	BLANK,
+	read_bytes,
+	trim,
+	Codebook,
+	Endian,
+	Source,
+};
+
+
+/// The record types of the dictionary.
+const VARIABLE: i32 = 2;
+const VALUE_LABELS: i32 = 3;
+const VALUE_LABEL_VARIABLES: i32 = 4;
+const DOCUMENT: i32 = 6;
+const EXTENSION: i32 = 7;
+const DICTIONARY_END: i32 = 999;
+
+/// The extension subtype with the long variable names.
+const LONG_NAMES: i32 = 13;
+
+/// The system missing value.
+const SYSMIS: f64 = -f64::MAX;
+
+/// The lowest value, the open lower bound of missing ranges (`LO THRU`).
+const LOWEST: f64 = SYSMIS.next_up();
+
+/// The highest value, the open upper bound of missing ranges (`THRU HI`).
+const HIGHEST: f64 = f64::MAX;
+
+
+#[derive(Debug, Clone)]
+struct Variable {
+	name: String,
+	/// The string width, or zero for numeric variables.
+	width: usize,
+	/// The code of a declared missing value, if any: the first discrete one, or else a
+	/// closed bound of the range.
+	missing: Option<String>,
+}
+
+
+impl Variable {
+	/// The number of 8 byte slots of each value.
+	fn slots(&self) -> usize {
+		if self.width == 0 { 1 } else { self.width.div_ceil(8) }
+	}
+}
+
+
+/// The raw value and label of each value, and the slot indexes of the labeled variables.
+type ValueLabels = (Vec<([u8; 8], String)>, Vec<usize>);
+
+
+/// A slot of the compressed data.
+enum Slot {
+	Number(f64),
+	Raw([u8; 8]),
+	Spaces,
+	Sysmis,
+	End,
+}
+
+
+pub struct Reader<R> {
+	reader: R,
+	endian: Endian,
+	compressed: bool,
+	bias: f64,
+	variables: Box<[Variable]>,
+	columns: Box<[String]>,
+	codebook: Codebook,
+	/// The pending compression commands.
+	commands: Vec<u8>,
+}
+
+
+impl<R: Read> Reader<R> {
+	/// Read the file header and dictionary.
+	pub fn new(mut reader: R) -> anyhow::Result<Self> {
+		let header = read_bytes(&mut reader, 176)?;
+
+		match &header[.. 4] {
+			b"$FL2" => (),
+			b"$FL3" => anyhow::bail!("zlib compressed system files are not supported"),
+			_ => anyhow::bail!("not an SPSS system file"),
+		}
+
+		let layout = i32::from_le_bytes([header[64], header[65], header[66], header[67]]);
+		let endian = Endian { big: layout != 2 && layout != 3 };
+
+		let compression = endian.i32(&header[72 ..]);
+		let bias = endian.f64(&header[84 ..]);
+
+		if compression > 1 {
+			anyhow::bail!("unsupported compression: {}", compression);
+		}
+
+		let mut variables = Vec::new();
+		// The variable of each slot, including continuations.
+		let mut slots: Vec<Option<usize>> = Vec::new();
+		let mut value_labels: Vec<ValueLabels> = Vec::new();
+		let mut long_names: HashMap<String, String> = HashMap::new();
+
+		let read_i32 = |reader: &mut R| -> anyhow::Result<i32> {
+			Ok(endian.i32(&read_bytes(reader, 4)?))
+		};
+
+		loop {
+			match read_i32(&mut reader)? {
+				VARIABLE => {
+					let fields = read_bytes(&mut reader, 28)?;
+
+					let kind = endian.i32(&fields[0 ..]);
+					let has_label = endian.i32(&fields[4 ..]);
+					let missing_count = endian.i32(&fields[8 ..]);
+					let name = String::from_utf8_lossy(trim(&fields[20 ..])).into_owned();
+
+					if has_label != 0 {
+						let len = read_i32(&mut reader)? as usize;
+						read_bytes(&mut reader, len.div_ceil(4) * 4)?;
+					}
+
+					let missing = read_bytes(&mut reader, 8 * missing_count.unsigned_abs() as usize)?;
+
+					if kind < 0 {
+						slots.push(None);
+						continue;
+					}
+
+					// Ranges are given by their bounds, followed by the discrete value if any.
+					let value = |ix: usize| endian.f64(&missing[8 * ix ..]);
+
+					let missing = match missing_count {
+						0 => None,
+						_ if kind > 0 => Some(String::from_utf8_lossy(trim(&missing[.. 8])).into_owned()),
+						-2 => [value(0), value(1)]
+							.iter()
+							.find(|&&bound| bound != LOWEST && bound != HIGHEST)
+							.map(|&bound| code(bound)),
+						-3 => Some(code(value(2))),
+						_ => Some(code(value(0))),
+					};
+
+					slots.push(Some(variables.len()));
+
+					variables.push(
+						Variable {
+							name,
+							width: kind as usize,
+							missing,
+						}
+					);
+				},
+
+				VALUE_LABELS => {
+					let count = read_i32(&mut reader)?;
+					let mut labels = Vec::new();
+
+					for _ in 0 .. count {
+						let mut value = [0; 8];
+						reader.read_exact(&mut value)?;
+
+						let mut len = [0; 1];
+						reader.read_exact(&mut len)?;
+
+						let len = len[0] as usize;
+						let label = read_bytes(&mut reader, (len + 8) / 8 * 8 - 1)?;
+
+						labels.push((value, String::from_utf8_lossy(&label[.. len]).into_owned()));
+					}
+
+					if read_i32(&mut reader)? != VALUE_LABEL_VARIABLES {
+						anyhow::bail!("value labels without variables");
+					}
+
+					let count = read_i32(&mut reader)?;
+					let mut indexes = Vec::new();
+
+					for _ in 0 .. count {
+						indexes.push(read_i32(&mut reader)? as usize);
+					}
+
+					value_labels.push((labels, indexes));
+				},
+
+				DOCUMENT => {
+					let lines = read_i32(&mut reader)? as usize;
+					read_bytes(&mut reader, 80 * lines)?;
+				},
+
+				EXTENSION => {
+					let subtype = read_i32(&mut reader)?;
+					let size = read_i32(&mut reader)? as usize;
+					let count = read_i32(&mut reader)? as usize;
+
+					let data = read_bytes(&mut reader, size * count)?;
+
+					if subtype == LONG_NAMES {
+						for pair in String::from_utf8_lossy(&data).split('\t') {
+							if let Some((short, long)) = pair.split_once('=') {
+								long_names.insert(short.to_owned(), long.to_owned());
+							}
+						}
+					}
+				},
+
+				DICTIONARY_END => {
+					read_i32(&mut reader)?;
+					break;
+				},
+
+				kind => anyhow::bail!("invalid dictionary record: {}", kind),
+			}
+		}
+
+		for variable in variables.iter_mut() {
+			if let Some(name) = long_names.remove(&variable.name) {
+				variable.name = name;
+			}
+		}
+
+		let mut codebook = Codebook::default();
+
+		for (labels, indexes) in value_labels {
+			let variables: Vec<&Variable> = indexes
+				.iter()
+				.filter_map(
+					|&index| slots
+						.get(index.wrapping_sub(1))
+						.copied()
+						.flatten()
+				)
+				.map(|ix| &variables[ix])
+				.collect();
+
+			let numeric = variables.iter().all(|variable| variable.width == 0);
+
+			let labels = labels
+				.into_iter()
+				.map(
+					|(value, label)| {
+						let code = if numeric {
+							code(endian.f64(&value))
+						}
+						else {
+							String::from_utf8_lossy(trim(&value)).into_owned()
+						};
+
+						let label = crate::data::codebook::clean_label(&code, &label);
+
+						(code, label)
+					}
+				)
+				.collect();
+
+			codebook.insert(
+				variables
+					.iter()
+					.map(|variable| variable.name.clone())
+					.collect(),
+				labels
+			);
+		}
+
+		let columns = variables
+			.iter()
+			.map(|variable| variable.name.clone())
+			.collect();
+
+		Ok(
+			Self {
+				reader,
+				endian,
+				compressed: compression == 1,
+				bias,
+				variables: variables.into_boxed_slice(),
+				columns,
+				codebook,
+				commands: Vec::new(),
+			}
+		)
+	}
+
+
+	fn read_slot(&mut self) -> anyhow::Result<Slot> {
+		let mut raw = [0; 8];
+
+		if !self.compressed {
+			return match self.reader.read(&mut raw[.. 1])? {
+				0 => Ok(Slot::End),
+				_ => {
+					self.reader.read_exact(&mut raw[1 ..])?;
+					Ok(Slot::Raw(raw))
+				},
+			};
+		}
+
+		loop {
+			if self.commands.is_empty() {
+				let mut commands = [0; 8];
+
+				match self.reader.read(&mut commands[.. 1])? {
+					0 => return Ok(Slot::End),
+					_ => self.reader.read_exact(&mut commands[1 ..])?,
+				}
+
+				self.commands.extend(commands.iter().rev());
+			}
+
+			match self.commands.pop().expect("commands should not be empty") {
+				0 => continue,
+				252 => return Ok(Slot::End),
+				253 => {
+					self.reader.read_exact(&mut raw)?;
+					return Ok(Slot::Raw(raw));
+				},
+				254 => return Ok(Slot::Spaces),
+				255 => return Ok(Slot::Sysmis),
+				command => return Ok(Slot::Number(command as f64 - self.bias)),
+			}
+		}
+	}
+}
+
+
+impl<R: Read> Source for Reader<R> {
+	fn columns(&self) -> &[String] {
+		&self.columns
+	}
+
+
+	fn codebook(&self) -> &Codebook {
+		&self.codebook
+	}
+
+
+	fn read_row(&mut self, row: &mut Vec<Vec<u8>>) -> anyhow::Result<bool> {
+		row.resize(self.variables.len(), Vec::new());
+
+		for (ix, field) in row.iter_mut().enumerate() {
+			let width = self.variables[ix].width;
+
+			field.clear();
+
+			if width == 0 {
+				let value = match self.read_slot()? {
+					Slot::End if ix == 0 => return Ok(false),
+					Slot::End => anyhow::bail!("truncated case"),
+					Slot::Number(value) => value,
+					Slot::Raw(raw) => self.endian.f64(&raw),
+					Slot::Spaces | Slot::Sysmis => SYSMIS,
+				};
+
+				if value == SYSMIS {
+					match &self.variables[ix].missing {
+						Some(missing) => field.extend_from_slice(missing.as_bytes()),
+						None => field.extend_from_slice(BLANK),
+					}
+				}
+				else {
+					field.extend_from_slice(code(value).as_bytes());
+				}
+			}
+			else {
+				for slot in 0 .. self.variables[ix].slots() {
+					match self.read_slot()? {
+						Slot::End if ix == 0 && slot == 0 => return Ok(false),
+						Slot::End => anyhow::bail!("truncated case"),
+						Slot::Raw(raw) => field.extend_from_slice(&raw),
+						_ => field.extend_from_slice(b"        "),
+					}
+				}
+
+				let len = trim(&field[.. width]).len();
+				field.truncate(len);
+			}
+		}
+
+		Ok(true)
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use std::io;
+
+	use super::*;
+
+
+	fn i32s(values: &[i32]) -> Vec<u8> {
+		values
+			.iter()
+			.flat_map(|value| value.to_le_bytes())
+			.collect()
+	}
+
+
+	fn raw(text: &str) -> [u8; 8] {
+		let mut raw = [b' '; 8];
+		raw[.. text.len()].copy_from_slice(text.as_bytes());
+		raw
+	}
+
+
+	/// A variable record, with a short name, the type (zero for numeric, or the string width),
+	/// the label, which is skipped, and the missing values, which are a range if their count
+	/// is negative.
+	fn variable(name: &str, kind: i32, label: Option<&str>, missing_count: i32, missing: &[f64]) -> Vec<u8> {
+		let mut record = i32s(&[VARIABLE, kind, label.is_some() as i32, missing_count, 0, 0]);
+		record.extend_from_slice(&raw(name));
+
+		if let Some(label) = label {
+			record.extend(i32s(&[label.len() as i32]));
+			record.extend_from_slice(label.as_bytes());
+			record.resize(record.len() + label.len().next_multiple_of(4) - label.len(), b' ');
+		}
+
+		for value in missing {
+			record.extend_from_slice(&value.to_le_bytes());
+		}
+
+		record
+	}
+
+
+	fn value_labels(labels: &[(f64, &str)], indexes: &[i32]) -> Vec<u8> {
+		let mut record = i32s(&[VALUE_LABELS, labels.len() as i32]);
+
+		for (value, label) in labels {
+			record.extend_from_slice(&value.to_le_bytes());
+			record.push(label.len() as u8);
+
+			let mut padded = label.as_bytes().to_vec();
+			padded.resize((label.len() + 8) / 8 * 8 - 1, b' ');
+			record.extend_from_slice(&padded);
+		}
+
+		record.extend(i32s(&[VALUE_LABEL_VARIABLES, indexes.len() as i32]));
+		record.extend(i32s(indexes));
+
+		record
+	}
+
+
+	/// A file with a labeled numeric variable with a missing value, a string variable of two
+	/// slots, and a numeric variable with a long name, followed by the given data.
+	/// The file header, with the given compression and number of slots of each case.
+	fn header(compression: i32, slots: i32) -> Vec<u8> {
+		let mut header = b"$FL2".to_vec();
+		header.resize(64, b' ');
+		header.extend(i32s(&[2, slots, compression, 0, -1]));
+		header.extend_from_slice(&100f64.to_le_bytes());
+		header.resize(176, b' ');
+		header
+	}
+
+
+	fn file(compression: i32, data: &[u8]) -> Vec<u8> {
+		let mut file = header(compression, 4);
+
+		file.extend(variable("SEX", 0, Some("Sex"), 1, &[9.0]));
+		file.extend(variable("STATE", 10, None, 0, &[]));
+		file.extend(variable("", -1, None, 0, &[]));
+		file.extend(variable("V3", 0, None, 0, &[]));
+
+		file.extend(value_labels(&[(1.0, "(1) Male"), (2.0, "(2) Female")], &[1]));
+		file.extend(value_labels(&[(1.0, "White")], &[4]));
+
+		file.extend(i32s(&[DOCUMENT, 1]));
+		file.extend_from_slice(&[b' '; 80]);
+
+		let names = b"V3=RACE_GROUP";
+		file.extend(i32s(&[EXTENSION, LONG_NAMES, 1, names.len() as i32]));
+		file.extend_from_slice(names);
+
+		file.extend(i32s(&[DICTIONARY_END, 0]));
+		file.extend_from_slice(data);
+
+		file
+	}
+
+
+	fn read(file: Vec<u8>) -> (Reader<io::Cursor<Vec<u8>>>, Vec<Vec<String>>) {
+		let mut reader = Reader::new(io::Cursor::new(file)).unwrap();
+
+		let mut rows = Vec::new();
+		let mut row = Vec::new();
+
+		while reader.read_row(&mut row).unwrap() {
+			rows.push(
+				row
+					.iter()
+					.map(|field| String::from_utf8_lossy(field).into_owned())
+					.collect()
+			);
+		}
+
+		(reader, rows)
+	}
+
+
+	fn expected_rows() -> Vec<Vec<String>> {
+		vec![
+			vec!["1".to_owned(), "Alabama".to_owned(), "1".to_owned()],
+			vec!["9".to_owned(), "Texas".to_owned(), "250.5".to_owned()],
+		]
+	}
+
+
+	#[test]
+	fn dictionary() {
+		let (reader, _) = read(file(0, &[]));
+
+		assert_eq!(reader.columns(), ["SEX", "STATE", "RACE_GROUP"]);
+
+		assert_eq!(
+			reader.codebook().variables,
+			vec![
+				(
+					"SEX".to_owned(),
+					vec![("1".to_owned(), "Male".to_owned()), ("2".to_owned(), "Female".to_owned())].into(),
+				),
+				("RACE_GROUP".to_owned(), vec![("1".to_owned(), "White".to_owned())].into()),
+			]
+		);
+	}
+
+
+	#[test]
+	fn uncompressed_data() {
+		let mut data = Vec::new();
+
+		for slot in &[1f64.to_le_bytes(), raw("Alabama"), raw(""), 1f64.to_le_bytes()] {
+			data.extend_from_slice(slot);
+		}
+
+		for slot in &[SYSMIS.to_le_bytes(), raw("Texas"), raw(""), 250.5f64.to_le_bytes()] {
+			data.extend_from_slice(slot);
+		}
+
+		let (_, rows) = read(file(0, &data));
+
+		assert_eq!(rows, expected_rows());
+	}
+
+
+	#[test]
+	fn compressed_data() {
+		// Numbers are biased by 100, and raw values follow each block of commands.
+		let mut data = vec![101, 253, 254, 101, 255, 253, 254, 253];
+		data.extend_from_slice(&raw("Alabama"));
+		data.extend_from_slice(&raw("Texas"));
+		data.extend_from_slice(&250.5f64.to_le_bytes());
+		data.extend_from_slice(&[0, 252, 0, 0, 0, 0, 0, 0]);
+
+		let (_, rows) = read(file(1, &data));
+
+		assert_eq!(rows, expected_rows());
+	}
+
+
+	#[test]
+	fn unsupported_files() {
+		let mut zlib = file(0, &[]);
+		zlib[.. 4].copy_from_slice(b"$FL3");
+
+		assert!(Reader::new(&zlib[..]).is_err());
+		assert!(Reader::new(&b"not a system file"[..]).is_err());
+	}
+
+
+	#[test]
+	fn missing_ranges() {
+		// The code of a system missing value of a variable with the given missing values.
+		let missing = |missing_count: i32, values: &[f64]| {
+			let mut file = header(0, 1);
+			file.extend(variable("V1", 0, None, missing_count, values));
+			file.extend(i32s(&[DICTIONARY_END, 0]));
+			file.extend_from_slice(&SYSMIS.to_le_bytes());
+
+			let (_, rows) = read(file);
+
+			rows[0][0].clone()
+		};
+
+		assert_eq!(missing(0, &[]), " ");
+		assert_eq!(missing(2, &[8.0, 9.0]), "8");
+
+		// A range is given by a closed bound, and a range with a discrete value by the value.
+		assert_eq!(missing(-2, &[97.0, 99.0]), "97");
+		assert_eq!(missing(-2, &[LOWEST, -1.0]), "-1");
+		assert_eq!(missing(-2, &[90.0, HIGHEST]), "90");
+		assert_eq!(missing(-2, &[LOWEST, HIGHEST]), " ");
+		assert_eq!(missing(-3, &[LOWEST, -1.0, 99.0]), "99");
+	}
+}
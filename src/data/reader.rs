@@ -0,0 +1,658 @@
+use std::{
+	collections::HashMap,
+	fs,
+	io,
+	path::Path,
+	time,
+};
+
+use serde::Serialize;
+
+use crate::util;
+
+use super::{
+	cache::{self, Cache},
+	group::{self, External},
+	package,
+	sample::{Sampler, Sampling},
+	weight::{self, Weigher, Weighting},
+	COLUMNS,
+	AdmissionType,
+	Age,
+	OffenseType,
+	Race,
+	Record,
+	ReleaseType,
+	Sentence,
+	Sex,
+	TimeServed,
+	distribution::Distribution,
+};
+
+
+/// Which records to include in the analysis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Filter {
+	/// Include only recidivists, i.e. inmates with more than one record. The earliest record
+	/// of each inmate is discarded.
+	pub recidivists: bool,
+	/// Include only the given sex.
+	pub sex: Option<Sex>,
+	/// Include only the given admission type.
+	pub admission_type: Option<AdmissionType>,
+	/// Include only the given race.
+	pub race: Option<Race>,
+}
+
+
+impl Filter {
+	/// Whether the given record is accepted, regardless of recidivism. Records with missing
+	/// values in any of the encoded fields are never accepted.
+	pub fn accepts(&self, record: &Record) -> bool {
+		let mut valid = record.admission_type != AdmissionType::Missing
+		             && record.offense_type   != OffenseType::Missing
+		             && record.sentence       != Sentence::Missing
+		             && record.race           != Race::Missing
+		             && record.age_admission  != Age::Missing
+		             && record.time_served    != TimeServed::Missing
+		             && record.release_type   != ReleaseType::Missing;
+
+		if let Some(sex) = self.sex {
+			valid &= record.sex == sex;
+		}
+
+		if let Some(admission_type) = self.admission_type {
+			valid &= record.admission_type == admission_type;
+		}
+
+		if let Some(race) = self.race {
+			valid &= record.race == race;
+		}
+
+		valid
+	}
+}
+
+
+/// The number of records left after each step of the filtering, in order. The accepted
+/// records may be further sampled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub struct Funnel {
+	/// The valid records read.
+	pub read: usize,
+	/// Without the earliest record of each inmate, if only recidivists are included.
+	pub recidivists: usize,
+	/// Without missing values in any of the encoded fields.
+	pub complete: usize,
+	pub sex: usize,
+	pub admission_type: usize,
+	pub race: usize,
+}
+
+
+impl Funnel {
+	/// Count the given record through the checks of the filter, as in `Filter::accepts`,
+	/// returning whether it is accepted.
+	fn insert(&mut self, filter: &Filter, record: &Record) -> bool {
+		let complete = Filter::default().accepts(record);
+		let sex = complete && filter.sex.is_none_or(|sex| record.sex == sex);
+		let admission_type = sex && filter.admission_type
+			.is_none_or(|admission_type| record.admission_type == admission_type);
+		let race = admission_type && filter.race.is_none_or(|race| record.race == race);
+
+		self.recidivists += 1;
+		self.complete += complete as usize;
+		self.sex += sex as usize;
+		self.admission_type += admission_type as usize;
+		self.race += race as usize;
+
+		race
+	}
+}
+
+
+/// How to group the records of each inmate, in order to discard the earliest one when only
+/// recidivists are included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Grouping {
+	/// Keep the earliest record of each inmate in memory.
+	#[default]
+	Memory,
+	/// Sort the records by inmate id on disk, in the temporary directory. The accepted records
+	/// are then given in the order of the ids.
+	External,
+}
+
+
+enum Groups {
+	/// All records are independent.
+	None,
+	/// The earliest record of each inmate, and its weight.
+	Memory(HashMap<Box<[u8]>, (Record, f64)>),
+	External(External),
+}
+
+
+/// The accepted records, given to a sink, and their distribution. When sampling, only the
+/// sampled records are given to the sink, once all records are read.
+struct Accepted<F> {
+	filter: Filter,
+	sampler: Option<Sampler>,
+	weigher: Option<Weigher>,
+	distribution: Distribution,
+	sink: F,
+}
+
+
+impl<F: FnMut(&[u8], &Record)> Accepted<F> {
+	fn insert(&mut self, id: &[u8], record: &Record, weight: f64) {
+		if !self.distribution.funnel.insert(&self.filter, record) {
+			return;
+		}
+
+		match &mut self.sampler {
+			Some(sampler) => sampler.insert(id, *record, weight),
+			None => Self::accept(
+				&mut self.distribution,
+				&mut self.weigher,
+				&mut self.sink,
+				id,
+				record,
+				weight,
+			),
+		}
+	}
+
+
+	fn accept(
+		distribution: &mut Distribution,
+		weigher: &mut Option<Weigher>,
+		sink: &mut F,
+		id: &[u8],
+		record: &Record,
+		weight: f64,
+	) {
+		distribution.insert_weighted(record, weight);
+
+		if let Some(weigher) = weigher {
+			weigher.insert(record, weight);
+		}
+
+		sink(id, record);
+	}
+
+
+	fn finish(mut self) -> anyhow::Result<Distribution> {
+		if let Some(sampler) = self.sampler.take() {
+			let distribution = &mut self.distribution;
+			let weigher = &mut self.weigher;
+			let sink = &mut self.sink;
+
+			let frame = sampler.finish(
+				|id, record, weight| Self::accept(distribution, weigher, sink, id, record, weight)
+			);
+
+			self.distribution.frame = Some(frame);
+		}
+
+		if let Some(weigher) = self.weigher.take() {
+			let clock = time::Instant::now();
+
+			let weights = weigher.finish(&mut self.distribution)?;
+
+			log::info!("Weighting records took {:.2?}", clock.elapsed());
+
+			self.distribution.weights = Some(weights);
+		}
+
+		Ok(self.distribution)
+	}
+}
+
+
+/// The records being read, grouped by inmate when only recidivists are included.
+pub(super) struct Records<F> {
+	groups: Groups,
+	accepted: Accepted<F>,
+}
+
+
+impl<F: FnMut(&[u8], &Record)> Records<F> {
+	pub(super) fn new(
+		filter: Filter,
+		grouping: Grouping,
+		sampling: Option<Sampling>,
+		weighting: Option<&Weighting>,
+		sink: F,
+	) -> anyhow::Result<Self> {
+		let groups = match (filter.recidivists, grouping) {
+			(false, _) => Groups::None,
+			(true, Grouping::Memory) => Groups::Memory(HashMap::new()),
+			(true, Grouping::External) => Groups::External(External::new(group::RUN_LEN)),
+		};
+
+		Ok(
+			Self {
+				groups,
+				accepted: Accepted {
+					filter,
+					sampler: sampling.map(Sampler::new),
+					weigher: weighting.map(Weigher::new).transpose()?,
+					distribution: Distribution::new(),
+					sink,
+				},
+			}
+		)
+	}
+
+
+	pub(super) fn insert(&mut self, id: &[u8], record: Record, weight: f64) {
+		self.accepted.distribution.funnel.read += 1;
+
+		let mut record = (record, weight);
+
+		match &mut self.groups {
+			Groups::None => self.accepted.insert(id, &record.0, record.1),
+
+			Groups::Memory(earliest_records) => match earliest_records.get_mut(id) {
+				// Found an earlier record. We can swap *before* the validity check.
+				Some(early_record) => {
+					if early_record.0.admission_year > record.0.admission_year {
+						std::mem::swap(early_record, &mut record);
+					}
+
+					self.accepted.insert(id, &record.0, record.1);
+				},
+				None => { // The current record is the earliest for now.
+					earliest_records.insert(id.into(), record);
+				},
+			},
+
+			Groups::External(external) => external.insert(id, record.0, record.1),
+		}
+	}
+
+
+	/// Finish grouping the records, and return their distribution.
+	pub(super) fn finish(self) -> anyhow::Result<Distribution> {
+		let mut accepted = self.accepted;
+
+		if let Groups::External(external) = self.groups {
+			let clock = time::Instant::now();
+
+			external.finish(
+				|id, records| {
+					// The earliest record is the first one with the least admission year.
+					let earliest = records
+						.iter()
+						.enumerate()
+						.min_by_key(|(ix, (record, _))| (record.admission_year, *ix))
+						.map(|(ix, _)| ix);
+
+					for (ix, (record, weight)) in records.iter().enumerate() {
+						if Some(ix) != earliest {
+							accepted.insert(id, record, *weight);
+						}
+					}
+				}
+			)?;
+
+			log::info!("Grouping records took {:.2?}", clock.elapsed());
+		}
+
+		accepted.finish()
+	}
+}
+
+
+/// The column of the weights, if weighting by column.
+fn weight_column(weighting: Option<&Weighting>) -> Option<&str> {
+	match weighting {
+		Some(Weighting::Column(column)) => Some(column),
+		_ => None,
+	}
+}
+
+
+/// Parse the records from the original dataset in the delimited format, including the
+/// header, calling `insert` with the inmate id, the record and the weight of each row. The
+/// weights are read from the given column, or are all one. Invalid records are logged and
+/// discarded.
+pub(super) fn parse_records<R: io::BufRead>(
+	reader: R,
+	weight_column: Option<&str>,
+	mut insert: impl FnMut(&[u8], Record, f64),
+) -> io::Result<()> {
+	let mut line_splitter = util::RawLineSplitter::new(reader);
+
+	let header = line_splitter.read_line()?.unwrap_or_default();
+
+	let weight_position = match weight_column {
+		Some(column) => Some(
+			header
+				.split(|&c| c == b'\t')
+				.position(|name| name.trim_ascii().eq_ignore_ascii_case(column.as_bytes()))
+				.ok_or_else(
+					|| io::Error::new(
+						io::ErrorKind::InvalidData,
+						format!("missing weight column in dataset: {}", column),
+					)
+				)?
+		),
+		None => None,
+	};
+
+	let mut line_count: usize = 0;
+
+	while let Some(line) = line_splitter.read_line()? {
+		line_count += 1;
+
+		let mut fields = line
+			.split(|&c| c == b'\t')
+			.enumerate()
+			.filter(|&(ix, _)| Some(ix) != weight_position)
+			.map(|(_, field)| field);
+
+		let id = fields.next().unwrap();
+
+		let weight = match weight_position {
+			Some(position) => line
+				.split(|&c| c == b'\t')
+				.nth(position)
+				.ok_or_else(|| "missing weight".to_owned())
+				.and_then(weight::parse),
+			None => Ok(1.0),
+		};
+
+		match weight.and_then(|weight| Ok((Record::parse(fields)?, weight))) {
+			Ok((record, weight)) => insert(id, record, weight),
+
+			Err(err) => {
+				log::warn!(
+					"invalid record at line {}: {}\n{:?}",
+					line_count,
+					err,
+					String::from_utf8_lossy(line)
+				);
+			}
+		};
+	}
+
+	Ok(())
+}
+
+
+/// Parse the records from the original dataset in the given file, as in `parse_records`,
+/// according to its extension: SPSS (`.sav`), Stata (`.dta`), a record cache (`.cache`), or
+/// the delimited format otherwise. Columns are matched by name in the SPSS and Stata formats,
+/// and their embedded value labels are validated against the built-in codes. The record
+/// cache has no weights.
+pub(super) fn parse_file(
+	path: &Path,
+	weight_column: Option<&str>,
+	mut insert: impl FnMut(&[u8], Record, f64),
+) -> anyhow::Result<()> {
+	if cache::is_cache(path) {
+		if let Some(column) = weight_column {
+			return Err(anyhow::anyhow!("missing weight column in record cache: {}", column));
+		}
+
+		let cache = Cache::load(path)?;
+
+		for (id, record) in cache.iter() {
+			insert(id, *record, 1.0);
+		}
+
+		return Ok(());
+	}
+
+	let mut source = match package::open(path)? {
+		Some(source) => source,
+		None => {
+			let file = io::BufReader::new(fs::File::open(path)?);
+			return Ok(parse_records(file, weight_column, insert)?);
+		},
+	};
+
+	for mismatch in source.codebook().validate() {
+		log::warn!("value labels: {}", mismatch);
+	}
+
+	let positions = COLUMNS
+		.iter()
+		.map(
+			|column| source
+				.columns()
+				.iter()
+				.position(|name| name.eq_ignore_ascii_case(column))
+				.ok_or_else(|| anyhow::anyhow!("missing column in dataset: {}", column))
+		)
+		.collect::<anyhow::Result<Vec<_>>>()?;
+
+	let weight_position = match weight_column {
+		Some(column) => Some(
+			source
+				.columns()
+				.iter()
+				.position(|name| name.eq_ignore_ascii_case(column))
+				.ok_or_else(|| anyhow::anyhow!("missing weight column in dataset: {}", column))?
+		),
+		None => None,
+	};
+
+	let mut row = Vec::new();
+	let mut row_count: usize = 0;
+
+	while source.read_row(&mut row)? {
+		row_count += 1;
+
+		let fields = positions[1 ..]
+			.iter()
+			.map(|&ix| &row[ix][..]);
+
+		let weight = match weight_position {
+			Some(position) => weight::parse(&row[position]),
+			None => Ok(1.0),
+		};
+
+		match weight.and_then(|weight| Ok((Record::parse(fields)?, weight))) {
+			Ok((record, weight)) => insert(&row[positions[0]], record, weight),
+
+			Err(err) => {
+				log::warn!("invalid record at row {}: {}", row_count, err);
+			}
+		}
+	}
+
+	Ok(())
+}
+
+
+/// Read the records from the original dataset, in the delimited (tsv) format, including the
+/// header. Records that have missing values in any of the encoded fields, or that do not
+/// pass the filter, are discarded. Invalid records are logged and discarded. The accepted
+/// records are given to `sink`, with their inmate id, as they are read, so that they need
+/// not be held in memory, or, if sampling, the sampled records are given once all records
+/// are read. If weighting, the weights of the accepted records are computed once all
+/// records are read.
+///
+/// Returns the distribution of the accepted records, with their weights.
+pub fn stream_records<R: io::BufRead>(
+	reader: R,
+	filter: Filter,
+	grouping: Grouping,
+	sampling: Option<Sampling>,
+	weighting: Option<&Weighting>,
+	sink: impl FnMut(&[u8], &Record),
+) -> anyhow::Result<Distribution> {
+	let clock = time::Instant::now();
+
+	let mut records = Records::new(filter, grouping, sampling, weighting, sink)?;
+
+	parse_records(reader, weight_column(weighting), |id, record, weight| records.insert(id, record, weight))?;
+
+	let distribution = records.finish()?;
+
+	log::info!("Importing dataset took {:.2?}", clock.elapsed());
+
+	util::log_peak_memory();
+
+	Ok(distribution)
+}
+
+
+/// Read the records from the original dataset in the given file, according to its
+/// extension: SPSS (`.sav`), Stata (`.dta`), a record cache (`.cache`), or the delimited
+/// format otherwise. The records are filtered, sampled, weighted and given to `sink` as in
+/// `stream_records`.
+pub fn stream_file(
+	path: &Path,
+	filter: Filter,
+	grouping: Grouping,
+	sampling: Option<Sampling>,
+	weighting: Option<&Weighting>,
+	sink: impl FnMut(&[u8], &Record),
+) -> anyhow::Result<Distribution> {
+	let clock = time::Instant::now();
+
+	let mut records = Records::new(filter, grouping, sampling, weighting, sink)?;
+
+	parse_file(path, weight_column(weighting), |id, record, weight| records.insert(id, record, weight))?;
+
+	let distribution = records.finish()?;
+
+	log::info!("Importing dataset took {:.2?}", clock.elapsed());
+
+	util::log_peak_memory();
+
+	Ok(distribution)
+}
+
+
+/// Read the records from the original dataset, in the delimited (tsv) format, as in
+/// `stream_records`.
+///
+/// Returns the accepted records and their distribution.
+pub fn read_records<R: io::BufRead>(
+	reader: R,
+	filter: Filter
+) -> anyhow::Result<(Vec<Record>, Distribution)> {
+	let mut records = Vec::new();
+
+	let distribution = stream_records(
+		reader,
+		filter,
+		Grouping::Memory,
+		None,
+		None,
+		|_, record| records.push(*record),
+	)?;
+
+	Ok((records, distribution))
+}
+
+
+/// Read the records from the original dataset in the given file, as in `stream_file`.
+///
+/// Returns the accepted records and their distribution.
+pub fn read_file(path: &Path, filter: Filter) -> anyhow::Result<(Vec<Record>, Distribution)> {
+	let mut records = Vec::new();
+
+	let distribution = stream_file(
+		path,
+		filter,
+		Grouping::Memory,
+		None,
+		None,
+		|_, record| records.push(*record),
+	)?;
+
+	Ok((records, distribution))
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::data::*;
+
+
+	fn record(admission_year: u16, release_year: u16) -> Record {
+		Record {
+			sex: Sex::Male,
+			admission_type: AdmissionType::New,
+			offense_type: OffenseType::Violent,
+			sentence: Sentence::Years_0_1,
+			race: Race::White,
+			age_admission: Age::Age_18_24,
+			time_served: TimeServed::Years_0_1,
+			release_type: ReleaseType::Conditional,
+			education: Education::NoHighSchool,
+			admission_year,
+			release_year,
+			mandatory_release_year: 2001,
+			projected_release_year: 2001,
+			parole_eligibility_year: 2001,
+			offense_detailed_type: OffenseDetailedType::Murder,
+			age_release: Age::Age_18_24,
+			state: State::Alabama,
+		}
+	}
+
+
+	/// Group the given records of recidivists, and give the accepted ones, sorted by id and
+	/// release year.
+	fn group(entries: &[(Box<[u8]>, Record)], groups: Groups) -> Vec<(Box<[u8]>, Record)> {
+		let mut accepted: Vec<(Box<[u8]>, Record)> = Vec::new();
+
+		let filter = Filter { recidivists: true, ..Filter::default() };
+
+		let sink = |id: &[u8], record: &Record| accepted.push((id.into(), *record));
+		let mut records = Records::new(filter, Grouping::Memory, None, None, sink).unwrap();
+
+		records.groups = groups;
+
+		for (id, record) in entries {
+			records.insert(id, *record, 1.0);
+		}
+
+		let distribution = records.finish().unwrap();
+
+		assert_eq!(distribution.funnel.read, entries.len());
+
+		accepted.sort_by_key(|(id, record)| (id.clone(), record.release_year));
+		accepted
+	}
+
+
+	#[test]
+	fn external_grouping_matches_memory() {
+		// Records of 13 inmates, with admission years that repeat, so that the earliest record
+		// is sometimes tied. The release year numbers the records.
+		let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+
+		let entries: Vec<(Box<[u8]>, Record)> = (0 .. 100)
+			.map(
+				|ix| {
+					state ^= state << 13;
+					state ^= state >> 7;
+					state ^= state << 17;
+
+					let id = format!("inmate {}", state % 13).into_bytes().into_boxed_slice();
+
+					(id, record(1990 + (state >> 8) as u16 % 5, ix))
+				}
+			)
+			.collect();
+
+		let memory = group(&entries, Groups::Memory(HashMap::new()));
+
+		// Several runs of 7 records, and a single one that is not spilled.
+		let external = group(&entries, Groups::External(External::new(7)));
+		let single = group(&entries, Groups::External(External::new(group::RUN_LEN)));
+
+		assert_eq!(memory.len(), 100 - 13);
+		assert_eq!(external, memory);
+		assert_eq!(single, memory);
+	}
+}
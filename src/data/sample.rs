@@ -0,0 +1,508 @@
+//! Random sampling of the accepted records, for exploratory runs. A sample of a given size
+//! is drawn by reservoir sampling, and a sample of a given ratio by including each record
+//! independently, as the number of records is only known at the end of the stream.
+
+use std::{
+	fmt,
+	str::FromStr,
+};
+
+use rand::{
+	rngs::StdRng,
+	seq::SliceRandom,
+	RngExt,
+	SeedableRng,
+};
+
+use super::Record;
+
+
+/// The size of a sample.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Size {
+	/// The ratio of the records, in `(0, 1]`.
+	Ratio(f64),
+	/// The number of records.
+	Count(usize),
+}
+
+
+impl FromStr for Size {
+	type Err = anyhow::Error;
+
+	/// Parse a percentage of the records, e.g. `5%`, or a number of records, e.g. `1000`.
+	fn from_str(size: &str) -> anyhow::Result<Self> {
+		match size.strip_suffix('%') {
+			Some(percentage) => match percentage.trim().parse::<f64>() {
+				Ok(percentage) if percentage > 0.0 && percentage <= 100.0 => Ok(Size::Ratio(percentage / 100.0)),
+				_ => Err(anyhow::anyhow!("invalid sample percentage: {}", size)),
+			},
+			None => match size.parse::<usize>() {
+				Ok(count) if count > 0 => Ok(Size::Count(count)),
+				_ => Err(anyhow::anyhow!("invalid sample size: {} (expected a number of records, or a percentage such as 5%)", size)),
+			},
+		}
+	}
+}
+
+
+/// The attribute whose values define the strata of a stratified sample.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Attribute {
+	Sex,
+	AdmissionType,
+	OffenseType,
+	Sentence,
+	Race,
+	Age,
+	TimeServed,
+	ReleaseType,
+	State,
+}
+
+
+impl Attribute {
+	/// The stratum of the given record, i.e. the index of its value.
+	pub(super) fn stratum(self, record: &Record) -> u8 {
+		match self {
+			Attribute::Sex           => record.sex as u8,
+			Attribute::AdmissionType => record.admission_type as u8,
+			Attribute::OffenseType   => record.offense_type as u8,
+			Attribute::Sentence      => record.sentence as u8,
+			Attribute::Race          => record.race as u8,
+			Attribute::Age           => record.age_admission as u8,
+			Attribute::TimeServed    => record.time_served as u8,
+			Attribute::ReleaseType   => record.release_type as u8,
+			Attribute::State         => record.state as u8,
+		}
+	}
+
+
+	/// The label of the value of the given record, e.g. `Sex::Male`.
+	pub(super) fn label(self, record: &Record) -> String {
+		match self {
+			Attribute::Sex           => format!("Sex::{:?}", record.sex),
+			Attribute::AdmissionType => format!("AdmissionType::{:?}", record.admission_type),
+			Attribute::OffenseType   => format!("OffenseType::{:?}", record.offense_type),
+			Attribute::Sentence      => format!("Sentence::{:?}", record.sentence),
+			Attribute::Race          => format!("Race::{:?}", record.race),
+			Attribute::Age           => format!("Age::{:?}", record.age_admission),
+			Attribute::TimeServed    => format!("TimeServed::{:?}", record.time_served),
+			Attribute::ReleaseType   => format!("ReleaseType::{:?}", record.release_type),
+			Attribute::State         => format!("State::{:?}", record.state),
+		}
+	}
+}
+
+
+impl FromStr for Attribute {
+	type Err = anyhow::Error;
+
+	/// Parse an attribute by its argument name, e.g. `admission_type`.
+	fn from_str(name: &str) -> anyhow::Result<Self> {
+		match name {
+			"sex"            => Ok(Attribute::Sex),
+			"admission_type" => Ok(Attribute::AdmissionType),
+			"offense_type"   => Ok(Attribute::OffenseType),
+			"sentence"       => Ok(Attribute::Sentence),
+			"race"           => Ok(Attribute::Race),
+			"age"            => Ok(Attribute::Age),
+			"time_served"    => Ok(Attribute::TimeServed),
+			"release_type"   => Ok(Attribute::ReleaseType),
+			"state"          => Ok(Attribute::State),
+			_ => Err(anyhow::anyhow!("invalid attribute: {}", name)),
+		}
+	}
+}
+
+
+/// How to sample the accepted records.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sampling {
+	pub size: Size,
+	/// Sample each value of the attribute in proportion to its number of records.
+	pub stratify_by: Option<Attribute>,
+	/// The seed of the random generator, or none for a random seed.
+	pub seed: Option<u64>,
+}
+
+
+/// A stratum of the sampling frame: its label, population and sample size.
+pub type Stratum = (String, usize, usize);
+
+
+/// The sampling frame: the number of records the sample was drawn from, in total and in
+/// each stratum. Sample supports can be extrapolated to the population through the frame.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Frame {
+	pub population: usize,
+	pub sample: usize,
+	pub stratify_by: Option<Attribute>,
+	pub strata: Box<[Stratum]>,
+	pub seed: u64,
+}
+
+
+impl Frame {
+	/// Extrapolate a support in the sample to the population, with a 95% confidence
+	/// interval, by the normal approximation with the finite population correction. The
+	/// variance of a simple random sample is used for stratified samples, which is
+	/// conservative for proportional allocation.
+	///
+	/// Returns the estimate and the bounds of the interval.
+	pub fn extrapolate(&self, support: usize) -> (f64, f64, f64) {
+		if self.sample == 0 {
+			return (0.0, 0.0, 0.0);
+		}
+
+		let n = self.sample as f64;
+		let population = self.population as f64;
+		let p = support as f64 / n;
+
+		let correction = if self.population > 1 {
+			((population - n) / (population - 1.0)).max(0.0)
+		}
+		else {
+			0.0
+		};
+
+		let error = 1.96 * (p * (1.0 - p) / n * correction).sqrt();
+
+		(
+			p * population,
+			((p - error) * population).max(0.0),
+			((p + error) * population).min(population),
+		)
+	}
+}
+
+
+impl fmt::Display for Frame {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"{} of {} records ({:.1}%), seed {}",
+			self.sample,
+			self.population,
+			self.sample as f64 * 100.0 / self.population.max(1) as f64,
+			self.seed,
+		)?;
+
+		match self.stratify_by {
+			Some(attribute) => writeln!(f, ", stratified by {:?}", attribute)?,
+			None => return writeln!(f),
+		}
+
+		for (label, population, sample) in self.strata.iter() {
+			writeln!(f, "	{}: {} of {}", label, sample, population)?;
+		}
+
+		Ok(())
+	}
+}
+
+
+struct Reservoir {
+	label: String,
+	key: u8,
+	population: usize,
+	/// The sampled records, with their inmate ids and weights.
+	records: Vec<(Box<[u8]>, Record, f64)>,
+}
+
+
+/// Draws a sample from a stream of records.
+pub(super) struct Sampler {
+	sampling: Sampling,
+	seed: u64,
+	rng: StdRng,
+	/// A reservoir for each stratum, or a single one if not stratified. Sized samples keep up
+	/// to the sample size in each stratum, as the allocation is only known at the end.
+	reservoirs: Vec<Reservoir>,
+}
+
+
+impl Sampler {
+	pub(super) fn new(sampling: Sampling) -> Self {
+		let seed = sampling.seed.unwrap_or_else(rand::random);
+
+		Self {
+			sampling,
+			seed,
+			rng: StdRng::seed_from_u64(seed),
+			reservoirs: Vec::new(),
+		}
+	}
+
+
+	pub(super) fn insert(&mut self, id: &[u8], record: Record, weight: f64) {
+		let key = self.sampling.stratify_by.map_or(0, |attribute| attribute.stratum(&record));
+
+		let ix = match self.reservoirs.iter().position(|reservoir| reservoir.key == key) {
+			Some(ix) => ix,
+			None => {
+				self.reservoirs.push(
+					Reservoir {
+						label: self.sampling.stratify_by.map_or_else(String::new, |attribute| attribute.label(&record)),
+						key,
+						population: 0,
+						records: Vec::new(),
+					}
+				);
+
+				self.reservoirs.len() - 1
+			},
+		};
+
+		let reservoir = &mut self.reservoirs[ix];
+
+		reservoir.population += 1;
+
+		match self.sampling.size {
+			Size::Ratio(ratio) => if self.rng.random_bool(ratio) {
+				reservoir.records.push((id.into(), record, weight));
+			},
+
+			Size::Count(count) => if reservoir.records.len() < count {
+				reservoir.records.push((id.into(), record, weight));
+			}
+			else {
+				let ix = self.rng.random_range(0 .. reservoir.population);
+
+				if ix < count {
+					reservoir.records[ix] = (id.into(), record, weight);
+				}
+			},
+		}
+	}
+
+
+	/// Give the sampled records, with their inmate ids and weights, to `sink`, stratum by
+	/// stratum.
+	///
+	/// Returns the sampling frame.
+	pub(super) fn finish(mut self, mut sink: impl FnMut(&[u8], &Record, f64)) -> Frame {
+		let population: usize = self.reservoirs
+			.iter()
+			.map(|reservoir| reservoir.population)
+			.sum();
+
+		if let Size::Count(count) = self.sampling.size {
+			let count = count.min(population);
+
+			// Proportional allocation, by the largest remainder.
+			let quotas: Vec<f64> = self.reservoirs
+				.iter()
+				.map(|reservoir| count as f64 * reservoir.population as f64 / population as f64)
+				.collect();
+
+			let mut allocation: Vec<usize> = quotas
+				.iter()
+				.map(|quota| quota.floor() as usize)
+				.collect();
+
+			let mut remainders: Vec<usize> = (0 .. quotas.len()).collect();
+			remainders.sort_by(|&a, &b| (quotas[b] - quotas[b].floor()).total_cmp(&(quotas[a] - quotas[a].floor())));
+
+			let allocated: usize = allocation.iter().sum();
+
+			for &ix in remainders.iter().take(count - allocated) {
+				allocation[ix] += 1;
+			}
+
+			for (reservoir, size) in self.reservoirs.iter_mut().zip(allocation) {
+				// A random subset of a uniform sample is also a uniform sample.
+				let (sample, _) = reservoir.records.partial_shuffle(&mut self.rng, size);
+				reservoir.records = sample.to_vec();
+			}
+		}
+
+		for reservoir in self.reservoirs.iter() {
+			for (id, record, weight) in reservoir.records.iter() {
+				sink(id, record, *weight);
+			}
+		}
+
+		let mut strata: Vec<Stratum> = self.reservoirs
+			.into_iter()
+			.map(|reservoir| (reservoir.label, reservoir.population, reservoir.records.len()))
+			.collect();
+
+		strata.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+		Frame {
+			population,
+			sample: strata.iter().map(|stratum| stratum.2).sum(),
+			stratify_by: self.sampling.stratify_by,
+			strata: if self.sampling.stratify_by.is_some() { strata.into() } else { Box::new([]) },
+			seed: self.seed,
+		}
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::data::*;
+
+
+	fn record(race: Race) -> Record {
+		Record {
+			sex: Sex::Male,
+			admission_type: AdmissionType::New,
+			offense_type: OffenseType::Violent,
+			sentence: Sentence::Years_0_1,
+			race,
+			age_admission: Age::Age_18_24,
+			time_served: TimeServed::Years_0_1,
+			release_type: ReleaseType::Conditional,
+			education: Education::NoHighSchool,
+			admission_year: 2000,
+			release_year: 2001,
+			mandatory_release_year: 2001,
+			projected_release_year: 2001,
+			parole_eligibility_year: 2001,
+			offense_detailed_type: OffenseDetailedType::Murder,
+			age_release: Age::Age_18_24,
+			state: State::Alabama,
+		}
+	}
+
+
+	/// Sample the given records, identified by their index, and give the sampled ids.
+	fn sample(sampling: Sampling, records: &[Record]) -> (Vec<u8>, Frame) {
+		let mut sampler = Sampler::new(sampling);
+
+		for (id, record) in records.iter().enumerate() {
+			sampler.insert(&[id as u8], *record, 1.0);
+		}
+
+		let mut ids = Vec::new();
+		let frame = sampler.finish(|id, _, _| ids.push(id[0]));
+
+		(ids, frame)
+	}
+
+
+	#[test]
+	fn sizes_are_percentages_or_counts() {
+		assert_eq!("5%".parse::<Size>().unwrap(), Size::Ratio(0.05));
+		assert_eq!("100%".parse::<Size>().unwrap(), Size::Ratio(1.0));
+		assert_eq!("1e-2%".parse::<Size>().unwrap(), Size::Ratio(1e-4));
+		assert_eq!("1".parse::<Size>().unwrap(), Size::Count(1));
+		assert_eq!("1000".parse::<Size>().unwrap(), Size::Count(1000));
+
+		for size in ["0", "0%", "101%", "1.0", "0.5", "-1", "%", "five"].iter() {
+			assert!(size.parse::<Size>().is_err(), "{}", size);
+		}
+	}
+
+
+	#[test]
+	fn counts_are_allocated_by_the_largest_remainder() {
+		let records: Vec<Record> = [(Race::White, 5), (Race::Black, 3), (Race::Hispanic, 2)]
+			.iter()
+			.flat_map(|&(race, count)| std::iter::repeat_n(record(race), count))
+			.collect();
+
+		let sampling = Sampling {
+			size: Size::Count(4),
+			stratify_by: Some(Attribute::Race),
+			seed: Some(1),
+		};
+
+		let (ids, frame) = sample(sampling, &records);
+
+		// The quotas are 2, 1.2 and 0.8: the last remaining record goes to the largest
+		// remainder, 0.8.
+		assert_eq!(
+			&*frame.strata,
+			&[
+				("Race::White".to_owned(), 5, 2),
+				("Race::Black".to_owned(), 3, 1),
+				("Race::Hispanic".to_owned(), 2, 1),
+			],
+		);
+		assert_eq!((frame.population, frame.sample, frame.seed), (10, 4, 1));
+		assert_eq!(ids.len(), 4);
+
+		for id in ids.iter() {
+			assert!(frame.strata.iter().any(|stratum| stratum.0 == format!("Race::{:?}", records[*id as usize].race)));
+		}
+
+		// The sample never exceeds the population.
+		let sampling = Sampling { size: Size::Count(20), ..sampling };
+
+		assert_eq!(sample(sampling, &records).1.sample, 10);
+	}
+
+
+	#[test]
+	fn reservoirs_are_uniform() {
+		let records = [record(Race::White); 10];
+		let runs = 2000;
+		let mut counts = [0; 10];
+
+		for seed in 0 .. runs {
+			let sampling = Sampling {
+				size: Size::Count(3),
+				stratify_by: None,
+				seed: Some(seed),
+			};
+
+			let (ids, _) = sample(sampling, &records);
+
+			assert_eq!(ids.len(), 3);
+
+			for id in ids {
+				counts[id as usize] += 1;
+			}
+		}
+
+		// Each record is expected 600 times, with a standard deviation of about 20.
+		for count in counts.iter() {
+			assert!((500 ..= 700).contains(count), "{:?}", counts);
+		}
+
+		// The same seed draws the same sample.
+		let sampling = Sampling {
+			size: Size::Count(3),
+			stratify_by: None,
+			seed: Some(7),
+		};
+
+		assert_eq!(sample(sampling, &records).0, sample(sampling, &records).0);
+	}
+
+
+	#[test]
+	fn extrapolate() {
+		let frame = Frame {
+			population: 100,
+			sample: 10,
+			..Frame::default()
+		};
+
+		// p = 0.5, with a standard error of sqrt(0.25 / 10 * 90 / 99).
+		let (estimate, lower, upper) = frame.extrapolate(5);
+		let error = 1.96 * (0.25f64 / 10.0 * 90.0 / 99.0).sqrt() * 100.0;
+
+		assert!((estimate - 50.0).abs() < 1e-9);
+		assert!((lower - (50.0 - error)).abs() < 1e-9);
+		assert!((upper - (50.0 + error)).abs() < 1e-9);
+
+		// The bounds are clamped to the population.
+		let (_, lower, upper) = frame.extrapolate(0);
+		assert_eq!((lower, upper), (0.0, 0.0));
+
+		let (_, lower, upper) = frame.extrapolate(10);
+		assert_eq!((lower, upper), (100.0, 100.0));
+
+		// A census has no sampling error.
+		let census = Frame { population: 10, sample: 10, ..Frame::default() };
+		assert_eq!(census.extrapolate(4), (4.0, 4.0, 4.0));
+
+		assert_eq!(Frame::default().extrapolate(0), (0.0, 0.0, 0.0));
+	}
+}
@@ -0,0 +1,450 @@
+//! Survey weights of the records, so that the supports reflect a population instead of the
+//! records that happened to be accepted. The weights are either given by a column of the
+//! dataset, or computed by raking the records to external population totals.
+
+use std::{
+	collections::HashMap,
+	fs,
+	path::{Path, PathBuf},
+};
+
+use bitvec::{order::Lsb0, slice::BitSlice};
+
+use super::{
+	distribution::Distribution,
+	sample::Attribute,
+	Record,
+};
+
+
+/// The maximum number of raking iterations.
+const MAX_ITERATIONS: usize = 1000;
+
+/// The relative change of the weights under which raking has converged.
+const TOLERANCE: f64 = 1e-9;
+
+
+/// How to weight the records.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Weighting {
+	/// The weight of each record is in the given column of the dataset.
+	Column(Box<str>),
+	/// Rake the records by the given attributes to the marginal totals in the given file.
+	Rake {
+		attributes: Box<[Attribute]>,
+		marginals: PathBuf,
+	},
+}
+
+
+/// The weight of each record, in order.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Weights(Box<[f64]>);
+
+
+impl Weights {
+	pub fn len(&self) -> usize {
+		self.0.len()
+	}
+
+
+	pub fn is_empty(&self) -> bool {
+		self.0.is_empty()
+	}
+
+
+	/// The weight of the given record.
+	pub fn get(&self, record: usize) -> f64 {
+		self.0[record]
+	}
+
+
+	/// The total weight of the records.
+	pub fn total(&self) -> f64 {
+		self.0.iter().sum()
+	}
+
+
+	/// The greatest weight of a record.
+	pub fn max(&self) -> f64 {
+		self.0.iter().copied().fold(0.0, f64::max)
+	}
+
+
+	/// The total weight of the records in the given cover, e.g. the transactions that
+	/// contain an itemset.
+	pub fn support(&self, cover: &BitSlice<Lsb0, usize>) -> f64 {
+		cover
+			.iter()
+			.zip(self.0.iter())
+			.filter_map(|(&covered, &weight)| if covered { Some(weight) } else { None })
+			.sum()
+	}
+}
+
+
+impl From<Vec<f64>> for Weights {
+	fn from(weights: Vec<f64>) -> Self {
+		Self(weights.into())
+	}
+}
+
+
+/// The population total of each value of some attributes, from external counts.
+///
+/// The file is in TOML, with a table for each attribute, named as in `--stratify-by`, which
+/// maps the labels of the values to their totals, e.g. `[state]` and `California = 115000`.
+/// Labels may also be given with the type, e.g. `"State::California"`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Marginals(HashMap<Attribute, HashMap<String, f64>>);
+
+
+impl Marginals {
+	pub fn load(path: &Path) -> anyhow::Result<Self> {
+		let contents = fs::read_to_string(path)?;
+
+		let tables: HashMap<String, HashMap<String, toml::Value>> = toml::from_str(&contents)?;
+
+		let mut marginals = HashMap::new();
+
+		for (name, values) in tables {
+			let attribute: Attribute = name.parse()?;
+
+			let totals = values
+				.into_iter()
+				.map(
+					|(label, total)| {
+						let total = match total {
+							toml::Value::Integer(total) => total as f64,
+							toml::Value::Float(total) => total,
+							_ => return Err(anyhow::anyhow!("invalid marginal total for {}: {}", label, total)),
+						};
+
+						if !(total.is_finite() && total >= 0.0) {
+							return Err(anyhow::anyhow!("invalid marginal total for {}: {}", label, total));
+						}
+
+						// Normalize to the labels of `Attribute::label`.
+						let label = match label.split_once("::") {
+							Some((_, value)) => value.to_owned(),
+							None => label,
+						};
+
+						Ok((label, total))
+					}
+				)
+				.collect::<anyhow::Result<_>>()?;
+
+			marginals.insert(attribute, totals);
+		}
+
+		Ok(Self(marginals))
+	}
+
+
+	/// The total of the value with the given label, e.g. `State::California`.
+	fn total(&self, attribute: Attribute, label: &str) -> Option<f64> {
+		let value = label.split_once("::").map_or(label, |(_, value)| value);
+
+		self.0
+			.get(&attribute)
+			.and_then(|totals| totals.get(value))
+			.copied()
+	}
+}
+
+
+/// Rakes the accepted records to the marginal totals of some attributes, by iterative
+/// proportional fitting. The records are grouped in cells, by the values of the attributes,
+/// and each cell is given a weight.
+pub(super) struct Raker {
+	attributes: Box<[Attribute]>,
+	marginals: Marginals,
+	/// The cell of each record.
+	cells: Vec<u32>,
+	/// The index of each cell, by the values of the attributes.
+	keys: HashMap<Box<[u8]>, u32>,
+	/// The labels of the values of each cell, and the distribution of its records.
+	distributions: Vec<(Box<[String]>, Distribution)>,
+}
+
+
+impl Raker {
+	pub(super) fn new(attributes: &[Attribute], marginals: &Path) -> anyhow::Result<Self> {
+		let marginals = Marginals::load(marginals)?;
+
+		for attribute in attributes {
+			if !marginals.0.contains_key(attribute) {
+				return Err(anyhow::anyhow!("missing marginal totals for {:?}", attribute));
+			}
+		}
+
+		Ok(
+			Self {
+				attributes: attributes.into(),
+				marginals,
+				cells: Vec::new(),
+				keys: HashMap::new(),
+				distributions: Vec::new(),
+			}
+		)
+	}
+
+
+	pub(super) fn insert(&mut self, record: &Record) {
+		let key: Box<[u8]> = self.attributes
+			.iter()
+			.map(|attribute| attribute.stratum(record))
+			.collect();
+
+		let distributions = &mut self.distributions;
+		let attributes = &self.attributes;
+
+		let cell = *self.keys
+			.entry(key)
+			.or_insert_with(
+				|| {
+					let labels = attributes
+						.iter()
+						.map(|attribute| attribute.label(record))
+						.collect();
+
+					distributions.push((labels, Distribution::new()));
+
+					(distributions.len() - 1) as u32
+				}
+			);
+
+		self.distributions[cell as usize].1.insert(record);
+		self.cells.push(cell);
+	}
+
+
+	/// Compute the weight of each record, and reweight the given distribution of the records.
+	pub(super) fn finish(self, distribution: &mut Distribution) -> anyhow::Result<Weights> {
+		// The target total of each value of each attribute, by cell.
+		let mut targets = Vec::with_capacity(self.attributes.len());
+
+		for (ix, &attribute) in self.attributes.iter().enumerate() {
+			let mut totals: HashMap<&str, f64> = HashMap::new();
+
+			for (labels, _) in self.distributions.iter() {
+				let label = &labels[ix];
+
+				let total = self.marginals
+					.total(attribute, label)
+					.ok_or_else(|| anyhow::anyhow!("missing marginal total for {}", label))?;
+
+				totals.insert(label, total);
+			}
+
+			targets.push(totals);
+		}
+
+		for (attribute, totals) in self.attributes.iter().zip(targets.iter()) {
+			for label in self.marginals.0[attribute].keys() {
+				let label = format!("{:?}::{}", attribute, label);
+
+				if !totals.contains_key(label.as_str()) {
+					log::warn!("no records for the marginal total of {}", label);
+				}
+			}
+		}
+
+		let counts: Vec<f64> = self.distributions
+			.iter()
+			.map(|(_, distribution)| distribution.total_records as f64)
+			.collect();
+
+		let mut weights = vec![1.0; counts.len()];
+		let mut iterations = 0;
+
+		loop {
+			let mut change: f64 = 0.0;
+
+			for (ix, totals) in targets.iter().enumerate() {
+				let mut sums: HashMap<&str, f64> = HashMap::new();
+
+				for ((labels, _), (&count, &weight)) in self.distributions.iter().zip(counts.iter().zip(weights.iter())) {
+					*sums.entry(&labels[ix]).or_default() += count * weight;
+				}
+
+				for ((labels, _), weight) in self.distributions.iter().zip(weights.iter_mut()) {
+					let label = labels[ix].as_str();
+					let sum = sums[label];
+
+					let factor = if sum > 0.0 { totals[label] / sum } else { 1.0 };
+
+					change = change.max((factor - 1.0).abs());
+
+					*weight *= factor;
+				}
+			}
+
+			iterations += 1;
+
+			if change < TOLERANCE {
+				log::info!("Raking converged after {} iterations", iterations);
+				break;
+			}
+
+			if iterations == MAX_ITERATIONS {
+				log::warn!("Raking did not converge after {} iterations", iterations);
+				break;
+			}
+		}
+
+		distribution.reweight(
+			self.distributions
+				.iter()
+				.map(|(_, distribution)| distribution)
+				.zip(weights.iter().copied())
+		);
+
+		Ok(
+			self.cells
+				.into_iter()
+				.map(|cell| weights[cell as usize])
+				.collect::<Vec<_>>()
+				.into()
+		)
+	}
+}
+
+
+/// Collects the weight of each accepted record.
+pub(super) enum Weigher {
+	Column(Vec<f64>),
+	Rake(Raker),
+}
+
+
+impl Weigher {
+	pub(super) fn new(weighting: &Weighting) -> anyhow::Result<Self> {
+		match weighting {
+			Weighting::Column(_) => Ok(Weigher::Column(Vec::new())),
+			Weighting::Rake { attributes, marginals } => Ok(Weigher::Rake(Raker::new(attributes, marginals)?)),
+		}
+	}
+
+
+	/// Insert an accepted record, with its weight in the dataset if weighted by column.
+	pub(super) fn insert(&mut self, record: &Record, weight: f64) {
+		match self {
+			Weigher::Column(weights) => weights.push(weight),
+			Weigher::Rake(raker) => raker.insert(record),
+		}
+	}
+
+
+	/// Compute the weight of each record, reweighting the given distribution if needed.
+	pub(super) fn finish(self, distribution: &mut Distribution) -> anyhow::Result<Weights> {
+		match self {
+			Weigher::Column(weights) => Ok(weights.into()),
+			Weigher::Rake(raker) => raker.finish(distribution),
+		}
+	}
+}
+
+
+/// Parse the weight of a record, which must be a non-negative number.
+pub(super) fn parse(field: &[u8]) -> Result<f64, String> {
+	let field = std::str
+		::from_utf8(field)
+		.map_err(|_| "invalid weight")?;
+
+	let weight: f64 = field
+		.trim()
+		.parse()
+		.map_err(|_| format!("invalid weight: {:?}", field))?;
+
+	if weight.is_finite() && weight >= 0.0 {
+		Ok(weight)
+	}
+	else {
+		Err(format!("invalid weight: {}", weight))
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use std::process;
+
+	use super::*;
+	use crate::data::*;
+
+
+	fn record(sex: Sex, race: Race) -> Record {
+		Record {
+			sex,
+			admission_type: AdmissionType::New,
+			offense_type: OffenseType::Violent,
+			sentence: Sentence::Years_0_1,
+			race,
+			age_admission: Age::Age_18_24,
+			time_served: TimeServed::Years_0_1,
+			release_type: ReleaseType::Conditional,
+			education: Education::NoHighSchool,
+			admission_year: 2000,
+			release_year: 2001,
+			mandatory_release_year: 2001,
+			projected_release_year: 2001,
+			parole_eligibility_year: 2001,
+			offense_detailed_type: OffenseDetailedType::Murder,
+			age_release: Age::Age_18_24,
+			state: State::Alabama,
+		}
+	}
+
+
+	#[test]
+	fn raking_converges_to_the_marginals() {
+		let marginals = std::env::temp_dir().join(format!("icpsr-36404-analysis-{}-marginals.toml", process::id()));
+
+		fs::write(&marginals, "[sex]\nMale = 60\nFemale = 40\n\n[race]\nWhite = 50\nBlack = 50\n").unwrap();
+
+		let raker = Raker::new(&[Attribute::Sex, Attribute::Race], &marginals);
+
+		fs::remove_file(&marginals).unwrap();
+
+		let mut raker = raker.unwrap();
+
+		let records = [
+			record(Sex::Male, Race::White),
+			record(Sex::Male, Race::White),
+			record(Sex::Male, Race::White),
+			record(Sex::Male, Race::Black),
+			record(Sex::Female, Race::White),
+			record(Sex::Female, Race::Black),
+		];
+
+		for record in records.iter() {
+			raker.insert(record);
+		}
+
+		let weights = raker.finish(&mut Distribution::new()).unwrap();
+
+		// The fitted cell totals 3w, x, y and z keep the odds ratio of the counts, 3, so
+		// wz = xy, and sum to the marginals: 3w + x = 60, y + z = 40, 3w + y = 50 and
+		// x + z = 50. Thus w is the smallest root of 3t² - 160t + 1500.
+		let male_white = (160.0 - 7600_f64.sqrt()) / 6.0;
+
+		let expected = [
+			male_white,
+			male_white,
+			male_white,
+			60.0 - 3.0 * male_white,
+			50.0 - 3.0 * male_white,
+			3.0 * male_white - 10.0,
+		];
+
+		assert_eq!(weights.len(), records.len());
+
+		for (ix, expected) in expected.iter().enumerate() {
+			assert!((weights.get(ix) - expected).abs() < 1e-6, "weight {}: {} != {}", ix, weights.get(ix), expected);
+		}
+
+		assert!((weights.total() - 100.0).abs() < 1e-6);
+	}
+}
@@ -0,0 +1,470 @@
+use std::{
+	borrow::Cow,
+	fs,
+	io::{self, Read, Write},
+	path::Path,
+	time,
+};
+
+use bitmatrix::BitMatrix;
+
+use bitvec::{
+	boxed::BitBox,
+	order::Lsb0,
+	slice::BitSlice,
+	vec::BitVec,
+};
+
+use dci::DataSet;
+
+use onehot::OneHot;
+
+use crate::{
+	data::Record,
+	itemset::ItemSet,
+};
+
+
+/// The signature of the aligned layout, including the format version.
+const MAGIC: &[u8; 8] = b"BITMAT01";
+
+/// The size of the aligned layout header: the signature, the number of items, the number
+/// of transactions, and the number of words per row.
+const HEADER_LEN: usize = 32;
+
+/// The number of bits in a word of the aligned layout.
+const WORD_BITS: usize = 64;
+
+
+/// The encoded dataset: a vertical bit matrix, where each row is an item and each column
+/// is a transaction (record). The matrix is either in memory, or memory-mapped from a file
+/// in the aligned layout.
+pub struct Dataset(Storage);
+
+
+enum Storage {
+	Memory(BitMatrix),
+	/// Each row in a separate bit vector, as built by the `Encoder`.
+	Rows(Box<[BitBox<Lsb0, usize>]>),
+	Mapped(Mapped),
+}
+
+
+/// A matrix in the aligned layout, where each row starts at a word boundary.
+struct Mapped {
+	map: memmap2::Mmap,
+	items: usize,
+	transactions: usize,
+	/// The number of words of each row.
+	row_len: usize,
+}
+
+
+impl Mapped {
+	fn row(&self, item: usize) -> &BitSlice<Lsb0, usize> {
+		assert!(item < self.items, "item out of bounds");
+
+		// The map is page aligned and the header is a multiple of the word size, so the rows
+		// are word aligned. The length was checked when mapping.
+		let words = unsafe {
+			std::slice::from_raw_parts(
+				self.map.as_ptr().add(HEADER_LEN + 8 * item * self.row_len) as *const usize,
+				self.row_len,
+			)
+		};
+
+		let row = BitSlice::<Lsb0, usize>
+			::from_slice(words)
+			.expect("row should fit in a bit slice");
+
+		&row[.. self.transactions]
+	}
+}
+
+
+impl Dataset {
+	/// The transactions that contain the given item.
+	pub fn row(&self, item: usize) -> &BitSlice<Lsb0, usize> {
+		match &self.0 {
+			Storage::Memory(matrix) => &matrix[item],
+			Storage::Rows(rows) => &rows[item],
+			Storage::Mapped(mapped) => mapped.row(item),
+		}
+	}
+
+
+	/// A dataset of the given transactions, in the given order. Transactions may be repeated.
+	pub fn select(&self, transactions: &[usize]) -> Dataset {
+		let rows = (0 .. self.items_count())
+			.map(
+				|item| {
+					let row = self.row(item);
+
+					transactions
+						.iter()
+						.map(|&transaction| row[transaction])
+						.collect::<BitVec<Lsb0, usize>>()
+						.into_boxed_bitslice()
+				}
+			)
+			.collect();
+
+		Dataset(Storage::Rows(rows))
+	}
+
+
+	/// The matrix in memory, copying it if stored otherwise.
+	fn matrix(&self) -> Cow<'_, BitMatrix> {
+		match &self.0 {
+			Storage::Memory(matrix) => Cow::Borrowed(matrix),
+			_ => {
+				let mut matrix = BitMatrix::new(self.items_count(), self.transactions_count());
+
+				for item in 0 .. self.items_count() {
+					matrix[item].copy_from_bitslice(self.row(item));
+				}
+
+				Cow::Owned(matrix)
+			},
+		}
+	}
+}
+
+
+impl DataSet for Dataset {
+	type ItemSet = ItemSet;
+	type Cover = BitBox;
+
+	fn items_count(&self) -> usize {
+		match &self.0 {
+			Storage::Memory(matrix) => matrix.height(),
+			Storage::Rows(rows) => rows.len(),
+			Storage::Mapped(mapped) => mapped.items,
+		}
+	}
+
+	fn transactions_count(&self) -> usize {
+		match &self.0 {
+			Storage::Memory(matrix) => matrix.width(),
+			Storage::Rows(rows) => rows.first().map_or(0, |row| row.len()),
+			Storage::Mapped(mapped) => mapped.transactions,
+		}
+	}
+
+	fn item_support(&self, item: usize) -> usize {
+		self.row(item).count_ones()
+	}
+
+	fn support(&self, itemset: &ItemSet) -> usize {
+		self
+			.cover(itemset)
+			.count_ones()
+	}
+
+	fn supports(&self, item: usize, cover: &BitBox) -> bool {
+		self
+			.row(item)
+			.iter()
+			.zip(cover.iter())
+			.all(
+				|(&a, &b)| (!b) || a
+			)
+	}
+
+	fn cover(&self, itemset: &ItemSet) -> BitBox {
+		let length = self.transactions_count();
+
+		let mut cover = {
+			let mut vec = BitVec::with_capacity(length);
+			vec.resize(length, true);
+			vec.into_boxed_bitslice()
+		};
+
+		for item in itemset {
+			cover &= self
+				.row(item)
+				.iter()
+				.copied();
+		}
+
+		cover
+	}
+}
+
+
+impl From<BitMatrix> for Dataset {
+	fn from(matrix: BitMatrix) -> Self {
+		Self(Storage::Memory(matrix))
+	}
+}
+
+
+/// One-hot encode the given records.
+pub fn encode(records: &[Record]) -> Dataset {
+	let clock = time::Instant::now();
+
+	let dataset: Dataset = onehot::vertical(records).into();
+
+	log::info!("Encoding dataset took {:.2?}", clock.elapsed());
+
+	dataset
+}
+
+
+/// One-hot encode records incrementally, as they are read, without holding them in memory.
+/// Each item is bit-packed in its own row.
+pub struct Encoder {
+	rows: Vec<BitVec<Lsb0, usize>>,
+}
+
+
+impl Encoder {
+	pub fn new() -> Self {
+		Self {
+			rows: vec![BitVec::new(); Record::ONEHOT_LEN],
+		}
+	}
+
+
+	pub fn push(&mut self, record: &Record) {
+		for (row, value) in self.rows.iter_mut().zip(record.onehot()) {
+			row.push(value);
+		}
+	}
+
+
+	pub fn finish(self) -> Dataset {
+		let rows = self.rows
+			.into_iter()
+			.map(BitVec::into_boxed_bitslice)
+			.collect();
+
+		Dataset(Storage::Rows(rows))
+	}
+}
+
+
+impl Default for Encoder {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+
+/// Serialize the encoded dataset in MessagePack.
+pub fn save<W: Write>(dataset: &Dataset, writer: W) -> anyhow::Result<()> {
+	let mut writer = io::BufWriter::with_capacity(
+		8 * 1024 * 1204,
+		writer,
+	);
+
+	let clock = time::Instant::now();
+
+	rmp_serde::encode::write(&mut writer, &*dataset.matrix())?;
+	writer.flush()?;
+
+	log::info!("Saving dataset took {:.2?}", clock.elapsed());
+
+	Ok(())
+}
+
+
+/// Deserialize an encoded dataset, as written by `save`.
+pub fn load<R: io::BufRead>(reader: R) -> anyhow::Result<Dataset> {
+	let clock = time::Instant::now();
+
+	let dataset: BitMatrix = rmp_serde::decode::from_read(reader)?;
+
+	log::info!("Restoring dataset took {:.2?}", clock.elapsed());
+
+	log::info!("Restored {}x{} matrix.", dataset.height(), dataset.width());
+
+	Ok(dataset.into())
+}
+
+
+/// Write the encoded dataset in the aligned layout, which can be memory-mapped by `map`:
+/// the header, followed by each row padded to a whole number of 64 bit words, in little
+/// endian.
+pub fn save_aligned<W: Write>(dataset: &Dataset, writer: W) -> anyhow::Result<()> {
+	let mut writer = io::BufWriter::with_capacity(
+		8 * 1024 * 1204,
+		writer,
+	);
+
+	let clock = time::Instant::now();
+
+	let items = dataset.items_count();
+	let transactions = dataset.transactions_count();
+	let row_len = transactions.div_ceil(WORD_BITS);
+
+	writer.write_all(MAGIC)?;
+
+	for value in &[items, transactions, row_len] {
+		writer.write_all(&(*value as u64).to_le_bytes())?;
+	}
+
+	let mut words = vec![0u64; row_len];
+
+	for item in 0 .. items {
+		words.iter_mut().for_each(|word| *word = 0);
+
+		for (ix, &value) in dataset.row(item).iter().enumerate() {
+			if value {
+				words[ix / WORD_BITS] |= 1 << (ix % WORD_BITS);
+			}
+		}
+
+		for word in words.iter() {
+			writer.write_all(&word.to_le_bytes())?;
+		}
+	}
+
+	writer.flush()?;
+
+	log::info!("Saving dataset took {:.2?}", clock.elapsed());
+
+	Ok(())
+}
+
+
+/// Memory-map an encoded dataset, as written by `save_aligned`. The rows are used directly
+/// from the map, which is shared with other processes through the page cache.
+pub fn map(path: &Path) -> anyhow::Result<Dataset> {
+	if cfg!(target_endian = "big") || std::mem::size_of::<usize>() != 8 {
+		anyhow::bail!("the aligned layout requires a 64 bit little endian platform");
+	}
+
+	let clock = time::Instant::now();
+
+	let file = fs::File::open(path)?;
+
+	// The file must not be modified while mapped, which is the case for saved datasets.
+	let map = unsafe { memmap2::Mmap::map(&file)? };
+
+	if map.len() < HEADER_LEN || &map[.. 8] != MAGIC {
+		anyhow::bail!("not an aligned dataset, or an unsupported version");
+	}
+
+	let field = |ix: usize| {
+		let mut bytes = [0; 8];
+		bytes.copy_from_slice(&map[8 * ix .. 8 * (ix + 1)]);
+		u64::from_le_bytes(bytes) as usize
+	};
+
+	let items = field(1);
+	let transactions = field(2);
+	let row_len = field(3);
+
+	// The header is untrusted, so the expected size must not overflow.
+	let len = items
+		.checked_mul(row_len)
+		.and_then(|words| words.checked_mul(8))
+		.and_then(|bytes| bytes.checked_add(HEADER_LEN));
+
+	if row_len != transactions.div_ceil(WORD_BITS) || len != Some(map.len()) {
+		anyhow::bail!("truncated or corrupt aligned dataset");
+	}
+
+	log::info!("Mapping dataset took {:.2?}", clock.elapsed());
+
+	log::info!("Mapped {}x{} matrix.", items, transactions);
+
+	Ok(
+		Dataset(
+			Storage::Mapped(
+				Mapped { map, items, transactions, row_len }
+			)
+		)
+	)
+}
+
+
+/// Open an encoded dataset file, memory-mapping it if in the aligned layout, or
+/// deserializing it otherwise.
+pub fn open(path: &Path) -> anyhow::Result<Dataset> {
+	let mut magic = [0; 8];
+
+	let is_aligned = match fs::File::open(path)?.read_exact(&mut magic) {
+		Ok(()) => &magic == MAGIC,
+		Err(_) => false,
+	};
+
+	if is_aligned {
+		map(path)
+	}
+	else {
+		load(io::BufReader::new(fs::File::open(path)?))
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use std::process;
+
+	use super::*;
+
+
+	/// A pseudorandom matrix, with a fixed seed.
+	fn matrix(items: usize, transactions: usize) -> BitMatrix {
+		let mut matrix = BitMatrix::new(items, transactions);
+		let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+
+		for item in 0 .. items {
+			for transaction in 0 .. transactions {
+				state ^= state << 13;
+				state ^= state >> 7;
+				state ^= state << 17;
+
+				matrix.set((item, transaction), state.is_multiple_of(3));
+			}
+		}
+
+		matrix
+	}
+
+
+	#[test]
+	fn aligned_round_trip() {
+		let path = std::env::temp_dir().join(format!("icpsr-36404-analysis-{}.bin", process::id()));
+
+		for &(items, transactions) in &[(0, 10), (3, 0), (1, 1), (4, 64), (5, 130), (7, 1000)] {
+			let dataset = Dataset::from(matrix(items, transactions));
+
+			save_aligned(&dataset, fs::File::create(&path).unwrap()).unwrap();
+
+			let mapped = map(&path).unwrap();
+
+			assert_eq!(mapped.items_count(), items);
+			assert_eq!(mapped.transactions_count(), transactions);
+
+			for item in 0 .. items {
+				assert_eq!(mapped.row(item), dataset.row(item), "row {} of {}x{}", item, items, transactions);
+			}
+		}
+
+		fs::remove_file(&path).unwrap();
+	}
+
+
+	#[test]
+	fn map_rejects_overflowing_header() {
+		let path = std::env::temp_dir().join(format!("icpsr-36404-analysis-{}-overflow.bin", process::id()));
+
+		// 8 * items * row_len wraps around to zero.
+		let mut header = MAGIC.to_vec();
+
+		for value in &[1u64 << 61, 64, 1] {
+			header.extend_from_slice(&value.to_le_bytes());
+		}
+
+		fs::write(&path, header).unwrap();
+
+		let result = map(&path);
+
+		fs::remove_file(&path).unwrap();
+
+		assert!(result.is_err());
+	}
+}
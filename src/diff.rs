@@ -0,0 +1,369 @@
+//! Comparison of two mining results, saved with `--format json` (or as a lattice JSON), e.g.
+//! before and after changing a filter. The itemsets are matched by their item labels, so
+//! that results can be compared across encodings.
+
+use std::{
+	cmp::Ordering,
+	collections::HashMap,
+	fmt,
+	fs,
+	io,
+	path::Path,
+};
+
+use serde::Deserialize;
+
+use crate::{
+	itemset::ItemSet,
+	labels,
+};
+
+
+/// An itemset of a result file.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Entry {
+	/// The raw item labels, e.g. `Sex::Male`.
+	pub items: Box<[String]>,
+	pub support: f64,
+}
+
+
+/// A result file: the total support and the itemsets.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Results {
+	/// The number of transactions, or their total weight.
+	pub transactions: f64,
+	#[serde(alias = "nodes")]
+	pub itemsets: Box<[Entry]>,
+}
+
+
+impl Results {
+	pub fn load(path: &Path) -> anyhow::Result<Self> {
+		let file = io::BufReader::new(fs::File::open(path)?);
+
+		serde_json::from_reader(file)
+			.map_err(|error| anyhow::anyhow!("invalid result file {}: {}", path.display(), error))
+	}
+}
+
+
+/// An itemset in one of the results: its support, relative support and rank by support.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Side {
+	pub support: f64,
+	pub ratio: f64,
+	/// The rank by support, from 1. Itemsets with the same support share the rank.
+	pub rank: usize,
+}
+
+
+/// An itemset matched in both results.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+	pub items: Box<[String]>,
+	pub before: Side,
+	pub after: Side,
+}
+
+
+impl Change {
+	/// The change of the relative support.
+	pub fn delta(&self) -> f64 {
+		self.after.ratio - self.before.ratio
+	}
+}
+
+
+/// The differences between two results.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Diff {
+	/// The itemsets only in the second result, by support.
+	pub added: Box<[(Box<[String]>, Side)]>,
+	/// The itemsets only in the first result, by support.
+	pub removed: Box<[(Box<[String]>, Side)]>,
+	/// The itemsets whose support changed, by the magnitude of the change of the relative
+	/// support.
+	pub changed: Box<[Change]>,
+	/// The itemsets whose rank changed, by the magnitude of the change.
+	pub reranked: Box<[Change]>,
+}
+
+
+/// The itemsets of the results by their sorted item labels, with their support, relative
+/// support and rank. The relative supports of results without transactions are zero.
+fn sides(results: &Results) -> HashMap<Box<[String]>, Side> {
+	let mut supports: Vec<f64> = results.itemsets
+		.iter()
+		.map(|entry| entry.support)
+		.collect();
+
+	supports.sort_unstable_by(|a, b| b.total_cmp(a));
+
+	results.itemsets
+		.iter()
+		.map(
+			|entry| {
+				let mut items = entry.items.clone();
+				items.sort_unstable();
+
+				// The number of itemsets with a greater support.
+				let rank = supports.partition_point(|&support| support > entry.support) + 1;
+
+				let ratio = if results.transactions > 0.0 {
+					entry.support / results.transactions
+				}
+				else {
+					0.0
+				};
+
+				let side = Side {
+					support: entry.support,
+					ratio,
+					rank,
+				};
+
+				(items, side)
+			}
+		)
+		.collect()
+}
+
+
+/// Compare the itemsets of two results.
+pub fn diff(before: &Results, after: &Results) -> Diff {
+	let before = sides(before);
+	let after = sides(after);
+
+	let by_support = |(a_items, a): &(Box<[String]>, Side), (b_items, b): &(Box<[String]>, Side)| b.support
+		.total_cmp(&a.support)
+		.then_with(|| a_items.cmp(b_items));
+
+	let mut added: Vec<_> = after
+		.iter()
+		.filter(|(items, _)| !before.contains_key(*items))
+		.map(|(items, side)| (items.clone(), *side))
+		.collect();
+
+	added.sort_by(by_support);
+
+	let mut removed: Vec<_> = before
+		.iter()
+		.filter(|(items, _)| !after.contains_key(*items))
+		.map(|(items, side)| (items.clone(), *side))
+		.collect();
+
+	removed.sort_by(by_support);
+
+	let matched: Vec<Change> = before
+		.iter()
+		.filter_map(
+			|(items, &before)| after
+				.get(items)
+				.map(|&after| Change { items: items.clone(), before, after })
+		)
+		.collect();
+
+	let mut changed: Vec<Change> = matched
+		.iter()
+		.filter(|change| change.before.support != change.after.support || change.delta() != 0.0)
+		.cloned()
+		.collect();
+
+	changed.sort_by(
+		|a, b| b.delta()
+			.abs()
+			.total_cmp(&a.delta().abs())
+			.then_with(|| a.items.cmp(&b.items))
+	);
+
+	let mut reranked: Vec<Change> = matched
+		.into_iter()
+		.filter(|change| change.before.rank != change.after.rank)
+		.collect();
+
+	let rank_delta = |change: &Change| (change.after.rank as isize - change.before.rank as isize).abs();
+
+	reranked.sort_by(
+		|a, b| match rank_delta(b).cmp(&rank_delta(a)) {
+			Ordering::Equal => a.before.rank.cmp(&b.before.rank),
+			ordering => ordering,
+		}
+	);
+
+	Diff {
+		added: added.into(),
+		removed: removed.into(),
+		changed: changed.into(),
+		reranked: reranked.into(),
+	}
+}
+
+
+/// Display the itemset, localized and in the item order if its items are known.
+struct Items<'a>(&'a [String]);
+
+
+impl fmt::Display for Items<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let mut items: Vec<_> = self.0
+			.iter()
+			.map(|label| (ItemSet::item(label), label))
+			.collect();
+
+		items.sort();
+
+		let labels: Vec<_> = items
+			.into_iter()
+			.map(
+				|(item, label)| match item {
+					Some(item) => labels::item(item),
+					None => label.into(),
+				}
+			)
+			.collect();
+
+		write!(f, "{{{}}}", labels.join(", "))
+	}
+}
+
+
+/// Display a support, which is fractional if weighted.
+struct Support(f64);
+
+
+impl fmt::Display for Support {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		if self.0.fract() == 0.0 {
+			write!(f, "{}", self.0)
+		}
+		else {
+			write!(f, "{:.1}", self.0)
+		}
+	}
+}
+
+
+impl fmt::Display for Diff {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		writeln!(f, "Added ({}):", self.added.len())?;
+
+		for (items, side) in self.added.iter() {
+			writeln!(f, "	{} ({:.1}%): {}", Support(side.support), side.ratio * 100.0, Items(items))?;
+		}
+
+		writeln!(f, "Removed ({}):", self.removed.len())?;
+
+		for (items, side) in self.removed.iter() {
+			writeln!(f, "	{} ({:.1}%): {}", Support(side.support), side.ratio * 100.0, Items(items))?;
+		}
+
+		writeln!(f, "Changed ({}):", self.changed.len())?;
+
+		for change in self.changed.iter() {
+			writeln!(
+				f,
+				"	{:+.1}% ({} ({:.1}%) -> {} ({:.1}%)): {}",
+				change.delta() * 100.0,
+				Support(change.before.support),
+				change.before.ratio * 100.0,
+				Support(change.after.support),
+				change.after.ratio * 100.0,
+				Items(&change.items),
+			)?;
+		}
+
+		writeln!(f, "Rank changes ({}):", self.reranked.len())?;
+
+		for change in self.reranked.iter() {
+			writeln!(f, "	{} -> {}: {}", change.before.rank, change.after.rank, Items(&change.items))?;
+		}
+
+		Ok(())
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+
+	fn results(transactions: f64, itemsets: &[(&[&str], f64)]) -> Results {
+		Results {
+			transactions,
+			itemsets: itemsets
+				.iter()
+				.map(
+					|(items, support)| Entry {
+						items: items.iter().map(|&item| item.to_owned()).collect(),
+						support: *support,
+					}
+				)
+				.collect(),
+		}
+	}
+
+
+	fn items(items: &[&str]) -> Box<[String]> {
+		items.iter().map(|&item| item.to_owned()).collect()
+	}
+
+
+	#[test]
+	fn compare() {
+		// The ranks are A 1, B and AB 2, and C and E 4.
+		let before = results(
+			10.0,
+			&[(&["A"], 8.0), (&["B"], 6.0), (&["A", "B"], 6.0), (&["C"], 3.0), (&["E"], 3.0)],
+		);
+
+		// The ranks are A 1, E 2, B 3, D 4 and AB 5. The items are matched in any order.
+		let after = results(
+			20.0,
+			&[(&["A"], 16.0), (&["E"], 13.0), (&["B"], 12.0), (&["D"], 5.0), (&["B", "A"], 4.0)],
+		);
+
+		let diff = diff(&before, &after);
+
+		assert_eq!(&*diff.added, &[(items(&["D"]), Side { support: 5.0, ratio: 0.25, rank: 4 })]);
+		assert_eq!(&*diff.removed, &[(items(&["C"]), Side { support: 3.0, ratio: 0.3, rank: 4 })]);
+
+		// By the magnitude of the change of the relative support, and then by the items, as
+		// A and B changed only in absolute support.
+		let changed: Vec<_> = diff.changed
+			.iter()
+			.map(|change| (change.items.clone(), (change.delta() * 100.0).round()))
+			.collect();
+
+		assert_eq!(
+			changed,
+			[(items(&["A", "B"]), -40.0), (items(&["E"]), 35.0), (items(&["A"]), 0.0), (items(&["B"]), 0.0)],
+		);
+
+		let reranked: Vec<_> = diff.reranked
+			.iter()
+			.map(|change| (change.items.clone(), change.before.rank, change.after.rank))
+			.collect();
+
+		assert_eq!(
+			reranked,
+			[(items(&["A", "B"]), 2, 5), (items(&["E"]), 4, 2), (items(&["B"]), 2, 3)],
+		);
+
+		assert_eq!(super::diff(&before, &before), Diff::default());
+	}
+
+
+	#[test]
+	fn results_without_transactions() {
+		let empty = results(0.0, &[(&["A"], 0.0)]);
+		let after = results(10.0, &[(&["A"], 5.0)]);
+
+		let diff = diff(&empty, &after);
+
+		assert_eq!(diff.changed.len(), 1);
+		assert_eq!(diff.changed[0].before.ratio, 0.0);
+		assert_eq!(diff.changed[0].delta(), 0.5);
+	}
+}
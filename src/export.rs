@@ -0,0 +1,260 @@
+use std::{
+	fmt::Debug,
+	io::Write,
+	sync::Arc,
+};
+
+use arrow_array::{
+	types::UInt8Type,
+	ArrayRef,
+	DictionaryArray,
+	RecordBatch,
+	StringArray,
+	UInt16Array,
+	UInt8Array,
+};
+
+use arrow_schema::{DataType, Field, Schema};
+
+use parquet::{
+	arrow::ArrowWriter,
+	basic::Compression,
+	file::properties::WriterProperties,
+};
+
+use crate::data::*;
+
+
+/// The number of records in each batch.
+const BATCH_SIZE: usize = 1 << 20;
+
+
+/// The schema of the record table. Enum fields are dictionary encoded strings with the
+/// variant names, and years are integers.
+pub fn schema() -> Schema {
+	let dictionary = |name| Field::new_dictionary(name, DataType::UInt8, DataType::Utf8, false);
+	let year = |name| Field::new(name, DataType::UInt16, true);
+
+	Schema::new(
+		vec![
+			dictionary("sex"),
+			dictionary("admission_type"),
+			dictionary("offense_type"),
+			dictionary("education"),
+			year("admission_year"),
+			year("release_year"),
+			year("mandatory_release_year"),
+			year("projected_release_year"),
+			year("parole_eligibility_year"),
+			dictionary("sentence"),
+			dictionary("offense_detailed_type"),
+			dictionary("race"),
+			dictionary("age_admission"),
+			dictionary("age_release"),
+			dictionary("time_served"),
+			dictionary("release_type"),
+			dictionary("state"),
+		]
+	)
+}
+
+
+/// A dictionary encoded column, with all the variants of the enum in the dictionary, so that
+/// the encoding is the same across batches.
+fn dictionary<T>(codes: &[(&[u8], T)], values: impl Iterator<Item = T>) -> ArrayRef
+where
+	T: Copy + PartialEq + Debug,
+{
+	let variants = variants(codes);
+
+	let keys: UInt8Array = values
+		.map(
+			|value| variants
+				.iter()
+				.position(|&variant| variant == value)
+				.expect("all variants should have a code") as u8
+		)
+		.collect();
+
+	let names: StringArray = variants
+		.iter()
+		.map(|variant| Some(format!("{:?}", variant)))
+		.collect();
+
+	let array = DictionaryArray::<UInt8Type>::try_new(keys, Arc::new(names))
+		.expect("keys should be in the dictionary");
+
+	Arc::new(array)
+}
+
+
+/// A year column. Missing years are null.
+fn year(values: impl Iterator<Item = u16>) -> ArrayRef {
+	let array: UInt16Array = values
+		.map(|year| if year == 0 { None } else { Some(year) })
+		.collect();
+
+	Arc::new(array)
+}
+
+
+/// Build a batch with the given records.
+pub fn batch(records: &[Record]) -> anyhow::Result<RecordBatch> {
+	macro_rules! column {
+		(dictionary, $type: ident, $field: ident) => {
+			dictionary($type::CODES, records.iter().map(|record| record.$field))
+		};
+		(year, $field: ident) => {
+			year(records.iter().map(|record| record.$field))
+		};
+	}
+
+	let columns = vec![
+		column!(dictionary, Sex, sex),
+		column!(dictionary, AdmissionType, admission_type),
+		column!(dictionary, OffenseType, offense_type),
+		column!(dictionary, Education, education),
+		column!(year, admission_year),
+		column!(year, release_year),
+		column!(year, mandatory_release_year),
+		column!(year, projected_release_year),
+		column!(year, parole_eligibility_year),
+		column!(dictionary, Sentence, sentence),
+		column!(dictionary, OffenseDetailedType, offense_detailed_type),
+		column!(dictionary, Race, race),
+		column!(dictionary, Age, age_admission),
+		column!(dictionary, Age, age_release),
+		column!(dictionary, TimeServed, time_served),
+		column!(dictionary, ReleaseType, release_type),
+		column!(dictionary, State, state),
+	];
+
+	Ok(RecordBatch::try_new(Arc::new(schema()), columns)?)
+}
+
+
+/// Write the records as a Parquet file, compressed with Snappy.
+pub fn write_parquet<W: Write + Send>(writer: W, records: &[Record]) -> anyhow::Result<()> {
+	let properties = WriterProperties
+		::builder()
+		.set_compression(Compression::SNAPPY)
+		.build();
+
+	let mut writer = ArrowWriter::try_new(writer, Arc::new(schema()), Some(properties))?;
+
+	for chunk in records.chunks(BATCH_SIZE) {
+		writer.write(&batch(chunk)?)?;
+	}
+
+	writer.close()?;
+
+	Ok(())
+}
+
+
+/// Write the records as an Arrow IPC file.
+pub fn write_ipc<W: Write>(writer: W, records: &[Record]) -> anyhow::Result<()> {
+	let mut writer = arrow_ipc::writer::FileWriter::try_new(writer, &schema())?;
+
+	for chunk in records.chunks(BATCH_SIZE) {
+		writer.write(&batch(chunk)?)?;
+	}
+
+	writer.finish()?;
+
+	Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+	use std::io::Cursor;
+
+	use arrow_array::{cast::AsArray, types::UInt16Type, Array};
+
+	use arrow_ipc::reader::FileReader;
+
+	use super::*;
+
+
+	fn record(race: Race, release_year: u16) -> Record {
+		Record {
+			sex: Sex::Male,
+			admission_type: AdmissionType::New,
+			offense_type: OffenseType::Violent,
+			sentence: Sentence::Years_0_1,
+			race,
+			age_admission: Age::Age_18_24,
+			time_served: TimeServed::Years_0_1,
+			release_type: ReleaseType::Conditional,
+			education: Education::NoHighSchool,
+			admission_year: 2000,
+			release_year,
+			mandatory_release_year: 2001,
+			projected_release_year: 2001,
+			parole_eligibility_year: 2001,
+			offense_detailed_type: OffenseDetailedType::Murder,
+			age_release: Age::Age_18_24,
+			state: State::Alabama,
+		}
+	}
+
+
+	#[test]
+	fn ipc_round_trip() {
+		let records = [
+			record(Race::Hispanic, 2005),
+			record(Race::White, 0),
+			record(Race::Hispanic, 0),
+		];
+
+		let mut bytes = Vec::new();
+		write_ipc(&mut bytes, &records).unwrap();
+
+		let reader = FileReader::try_new(Cursor::new(bytes), None).unwrap();
+		assert_eq!(reader.schema().as_ref(), &schema());
+
+		let batches: Vec<RecordBatch> = reader.map(Result::unwrap).collect();
+		assert_eq!(batches.len(), 1);
+
+		let batch = &batches[0];
+		assert_eq!(batch.num_rows(), 3);
+
+		// The dictionary has all the variants, in the order of their codes.
+		let races = batch
+			.column_by_name("race")
+			.unwrap()
+			.as_dictionary::<UInt8Type>();
+
+		let variants = variants(Race::CODES);
+		let key = |race| variants.iter().position(|&variant| variant == race).unwrap() as u8;
+
+		assert_eq!(
+			races.keys().values().as_ref(),
+			&[key(Race::Hispanic), key(Race::White), key(Race::Hispanic)],
+		);
+
+		let names = races.values().as_string::<i32>();
+		assert_eq!(names.len(), variants.len());
+		assert_eq!(names.value(key(Race::Hispanic) as usize), "Hispanic");
+		assert_eq!(names.value(key(Race::White) as usize), "White");
+
+		// Missing years are null.
+		let release_years = batch
+			.column_by_name("release_year")
+			.unwrap()
+			.as_primitive::<UInt16Type>();
+
+		assert_eq!(release_years.null_count(), 2);
+		assert_eq!(release_years.value(0), 2005);
+		assert!(release_years.is_null(1));
+		assert!(release_years.is_null(2));
+
+		let admission_years = batch
+			.column_by_name("admission_year")
+			.unwrap()
+			.as_primitive::<UInt16Type>();
+
+		assert_eq!(admission_years.null_count(), 0);
+	}
+}
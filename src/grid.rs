@@ -0,0 +1,196 @@
+//! Mining of the subpopulations given by every combination of the values of some attributes
+//! of the filter, e.g. of every sex and race, each with the same relative support threshold.
+//! The records are read once, and the cells are mined in parallel.
+
+use std::{
+	collections::BTreeMap,
+	time,
+};
+
+use dci::DataSet;
+
+use rayon::prelude::*;
+
+use serde::Serialize;
+
+use crate::{
+	data::{AdmissionType, Race, Record, Sex},
+	dataset::{self, Dataset},
+	mining::{self, Constraints, Itemsets},
+	Filter,
+};
+
+
+/// An attribute of the filter, whose values split the records into the cells of the grid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Dimension {
+	Sex,
+	AdmissionType,
+	Race,
+}
+
+
+impl Dimension {
+	/// The filters of each value of the dimension, over the given filter.
+	fn split(self, filter: Filter) -> Vec<Filter> {
+		match self {
+			Dimension::Sex => [Sex::Male, Sex::Female]
+				.iter()
+				.map(|&sex| Filter { sex: Some(sex), ..filter })
+				.collect(),
+
+			Dimension::AdmissionType => [AdmissionType::New, AdmissionType::Parole, AdmissionType::Other]
+				.iter()
+				.map(|&admission_type| Filter { admission_type: Some(admission_type), ..filter })
+				.collect(),
+
+			Dimension::Race => [Race::White, Race::Black, Race::Hispanic, Race::Other]
+				.iter()
+				.map(|&race| Filter { race: Some(race), ..filter })
+				.collect(),
+		}
+	}
+
+
+	/// The name of the attribute, as in the args.
+	pub fn name(self) -> &'static str {
+		match self {
+			Dimension::Sex => "sex",
+			Dimension::AdmissionType => "admission_type",
+			Dimension::Race => "race",
+		}
+	}
+
+
+	/// The value of the attribute in the given filter, in lowercase as in the args.
+	pub fn value(self, filter: &Filter) -> Option<String> {
+		let value = match self {
+			Dimension::Sex => filter.sex.map(|sex| format!("{:?}", sex)),
+			Dimension::AdmissionType => filter.admission_type.map(|admission_type| format!("{:?}", admission_type)),
+			Dimension::Race => filter.race.map(|race| format!("{:?}", race)),
+		};
+
+		value.map(|value| value.to_lowercase())
+	}
+
+
+	/// Whether the given filter already restricts the attribute of the dimension.
+	pub fn is_filtered(self, filter: &Filter) -> bool {
+		match self {
+			Dimension::Sex => filter.sex.is_some(),
+			Dimension::AdmissionType => filter.admission_type.is_some(),
+			Dimension::Race => filter.race.is_some(),
+		}
+	}
+}
+
+
+/// The filters of the cells of the grid: every combination of the values of the dimensions,
+/// over the given filter.
+pub fn cells(filter: Filter, dimensions: &[Dimension]) -> Vec<Filter> {
+	dimensions
+		.iter()
+		.fold(
+			vec![filter],
+			|cells, dimension| cells
+				.into_iter()
+				.flat_map(|cell| dimension.split(cell))
+				.collect()
+		)
+}
+
+
+/// A cell of the grid, with its encoded records and mined itemsets.
+pub struct Cell {
+	pub filter: Filter,
+	pub dataset: Dataset,
+	/// The absolute support threshold of the cell.
+	pub min_sup: dci::Support,
+	pub itemsets: Itemsets,
+}
+
+
+/// The summary of a cell, for the index of the grid.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Summary {
+	/// The directory of the cell, relative to that of the grid, e.g. `sex=female/race=black`.
+	pub path: String,
+	/// The value of each dimension.
+	pub values: BTreeMap<&'static str, String>,
+	pub records: usize,
+	pub min_sup: dci::Support,
+	pub itemsets: usize,
+}
+
+
+impl Cell {
+	pub fn summary(&self, dimensions: &[Dimension]) -> Summary {
+		let values: Vec<_> = dimensions
+			.iter()
+			.map(
+				|dimension| (
+					dimension.name(),
+					dimension
+						.value(&self.filter)
+						.expect("cells should have a value for each dimension"),
+				)
+			)
+			.collect();
+
+		let path = values
+			.iter()
+			.map(|(name, value)| format!("{}={}", name, value))
+			.collect::<Vec<_>>()
+			.join("/");
+
+		Summary {
+			path,
+			values: values.into_iter().collect(),
+			records: self.dataset.transactions_count(),
+			min_sup: self.min_sup,
+			itemsets: self.itemsets.len(),
+		}
+	}
+}
+
+
+/// Mine the records accepted by each of the given filters, in parallel. Cells without records
+/// have no itemsets.
+pub fn mine(
+	records: &[Record],
+	cells: &[Filter],
+	min_sup_ratio: f64,
+	constraints: &Constraints,
+) -> Vec<Cell> {
+	let clock = time::Instant::now();
+
+	let cells = cells
+		.par_iter()
+		.map(
+			|&filter| {
+				let selected: Vec<Record> = records
+					.iter()
+					.filter(|record| filter.accepts(record))
+					.copied()
+					.collect();
+
+				let dataset = dataset::encode(&selected);
+
+				let min_sup = (dataset.transactions_count() as f64 * min_sup_ratio) as usize;
+
+				let itemsets = if selected.is_empty() {
+					Itemsets::default()
+				}
+				else {
+					mining::mine(&dataset, min_sup, constraints)
+				};
+
+				Cell { filter, dataset, min_sup, itemsets }
+			}
+		)
+		.collect();
+
+	log::info!("Mining the grid took {:.2?}", clock.elapsed());
+
+	cells
+}
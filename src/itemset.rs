@@ -9,10 +9,37 @@ use bitvec::{
 
 use onehot::OneHot;
 
-use super::data;
+use super::{data, labels};
 
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+lazy_static! {
+	static ref LABEL: Box<[&'static str]> = data::Record::labels().collect();
+
+	/// The record field of each item.
+	static ref ATTRIBUTE: Box<[&'static str]> = {
+		// The encoded fields of the record, in order.
+		let fields = [
+			("sex", data::Sex::ONEHOT_LEN),
+			("admission_type", data::AdmissionType::ONEHOT_LEN),
+			("offense_type", data::OffenseType::ONEHOT_LEN),
+			("sentence", data::Sentence::ONEHOT_LEN),
+			("race", data::Race::ONEHOT_LEN),
+			("age_admission", data::Age::ONEHOT_LEN),
+			("time_served", data::TimeServed::ONEHOT_LEN),
+			("release_type", data::ReleaseType::ONEHOT_LEN),
+		];
+
+		fields
+			.iter()
+			.flat_map(
+				|&(field, len)| std::iter::repeat_n(field, len)
+			)
+			.collect()
+	};
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct ItemSet(
 	BitArray<
 		Lsb0,
@@ -35,6 +62,132 @@ impl ItemSet {
 
 		itemset
 	}
+
+
+	/// Find the item with the given label, as given by `Record::labels()`.
+	pub fn item(label: &str) -> Option<usize> {
+		LABEL
+			.iter()
+			.position(|&item| item == label)
+	}
+
+
+	/// Find the item given either by its label, e.g. `Race::Black`, or by its record field
+	/// and value, e.g. `race=Black`. The attribute and value are case insensitive, and `age`
+	/// stands for `age_admission`.
+	pub fn find(term: &str) -> Option<usize> {
+		let (field, value) = match term.split_once('=') {
+			Some(pair) => pair,
+			None => return Self::item(term),
+		};
+
+		let field = match field.trim() {
+			field if field.eq_ignore_ascii_case("age") => "age_admission",
+			field => field,
+		};
+
+		(0 .. data::Record::ONEHOT_LEN).find(
+			|&item| ATTRIBUTE[item].eq_ignore_ascii_case(field)
+				&& LABEL[item]
+					.rsplit("::")
+					.next()
+					.is_some_and(|label| label.eq_ignore_ascii_case(value.trim()))
+		)
+	}
+
+
+	/// Parse an itemset from items given as in `find`, separated by whitespace or commas, and
+	/// optionally enclosed in braces, e.g. `{Race::Black, OffenseType::Drugs}`.
+	pub fn parse(text: &str) -> anyhow::Result<Self> {
+		let mut itemset = <Self as dci::ItemSet>::empty();
+
+		let terms = text
+			.trim()
+			.trim_start_matches('{')
+			.trim_end_matches('}')
+			.split(|c: char| c == ',' || c.is_whitespace())
+			.filter(|term| !term.is_empty());
+
+		for term in terms {
+			let item = Self
+				::find(term)
+				.ok_or_else(|| anyhow::anyhow!("invalid item: {}", term))?;
+
+			dci::ItemSet::add(&mut itemset, item);
+		}
+
+		Ok(itemset)
+	}
+
+
+	/// The label of the given item.
+	pub fn label(item: usize) -> &'static str {
+		LABEL[item]
+	}
+
+
+	/// The record field of the given item, e.g. `age_admission`.
+	pub fn attribute(item: usize) -> &'static str {
+		ATTRIBUTE[item]
+	}
+
+
+	/// How many items in the itemset.
+	pub fn len(&self) -> usize {
+		self.0.count_ones()
+	}
+
+
+	pub fn is_empty(&self) -> bool {
+		self.0.not_any()
+	}
+
+
+	/// Whether all items of this itemset are also in the other.
+	pub fn is_subset(&self, other: &Self) -> bool {
+		self.0
+			.as_slice()
+			.iter()
+			.zip(other.0.as_slice())
+			.all(
+				|(a, b)| a & !b == 0
+			)
+	}
+
+
+	/// Whether this itemset has no items in common with the other.
+	pub fn is_disjoint(&self, other: &Self) -> bool {
+		self.0
+			.as_slice()
+			.iter()
+			.zip(other.0.as_slice())
+			.all(
+				|(a, b)| a & b == 0
+			)
+	}
+
+
+	/// Remove all items of the other itemset from this one.
+	pub fn remove_all(&mut self, other: &Self) {
+		let other = other.0.as_slice();
+
+		for (a, b) in self.0.as_mut_slice().iter_mut().zip(other) {
+			*a &= !b;
+		}
+	}
+}
+
+
+impl From<&data::Record> for ItemSet {
+	fn from(record: &data::Record) -> Self {
+		let mut itemset = <Self as dci::ItemSet>::empty();
+
+		for (item, value) in record.onehot().enumerate() {
+			itemset.0.set(item, value);
+		}
+
+		itemset
+	}
 }
 
 
@@ -74,20 +227,16 @@ impl dci::ItemSet for ItemSet {
 
 impl fmt::Display for ItemSet {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-		lazy_static! {
-			static ref LABEL: Box<[&'static str]> = data::Record::labels().collect();
-		}
-
 		f.write_str("{")?;
 
 		let mut iter = self.into_iter();
 
 		if let Some(item) = iter.next() {
-			write!(f, "{}", LABEL[item])?;
+			write!(f, "{}", labels::item(item))?;
 		}
 
 		for item in iter {
-			write!(f, ", {}", LABEL[item])?;
+			write!(f, ", {}", labels::item(item))?;
 		}
 
 		f.write_str("}")?;
@@ -95,3 +244,67 @@ impl fmt::Display for ItemSet {
 		Ok(())
 	}
 }
+
+
+#[cfg(test)]
+mod tests {
+	use dci::ItemSet as _;
+
+	use super::*;
+
+
+	fn item(label: &str) -> usize {
+		ItemSet::item(label).unwrap()
+	}
+
+
+	#[test]
+	fn find() {
+		let black = item("Race::Black");
+
+		assert_eq!(ItemSet::find("Race::Black"), Some(black));
+		assert_eq!(ItemSet::find("race=black"), Some(black));
+		assert_eq!(ItemSet::find(" RACE = Black "), Some(black));
+
+		// The age stands for the age at admission, which is the only encoded age.
+		let age = item("Age::Age_18_24");
+
+		assert_eq!(ItemSet::attribute(age), "age_admission");
+		assert_eq!(ItemSet::find("age=Age_18_24"), Some(age));
+		assert_eq!(ItemSet::find("Age=age_18_24"), Some(age));
+		assert_eq!(ItemSet::find("age_admission=Age_18_24"), Some(age));
+
+		// Labels are case sensitive, and the age at release is not encoded.
+		let invalid = ["race::black", "Race::Purple", "race=purple", "color=black", "age_release=Age_18_24", "", "="];
+
+		for term in invalid.iter() {
+			assert_eq!(ItemSet::find(term), None, "{}", term);
+		}
+	}
+
+
+	#[test]
+	fn parse() {
+		let mut expected = ItemSet::empty();
+		expected.add(item("Race::Black"));
+		expected.add(item("Sex::Male"));
+
+		for text in [
+			"Race::Black Sex::Male",
+			"race=black,sex=male",
+			"{Race::Black, sex=Male}",
+			"  { Sex::Male ,, Race::Black }  ",
+		]
+		.iter() {
+			assert_eq!(ItemSet::parse(text).unwrap(), expected, "{}", text);
+		}
+
+		assert!(ItemSet::parse("").unwrap().is_empty());
+		assert!(ItemSet::parse("{}").unwrap().is_empty());
+
+		let error = ItemSet::parse("{Race::Black, Race::Purple}").unwrap_err();
+		assert_eq!(error.to_string(), "invalid item: Race::Purple");
+
+		assert!(ItemSet::parse("race = black").is_err());
+	}
+}
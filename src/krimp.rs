@@ -0,0 +1,346 @@
+use std::{
+	cmp,
+	collections::HashMap,
+};
+
+use dci::{DataSet, ItemSet as _};
+
+use crate::{
+	dataset::Dataset,
+	itemset::ItemSet,
+};
+
+
+/// An entry of the code table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+	pub itemset: ItemSet,
+	pub support: dci::Support,
+	/// How many transactions use this pattern in their cover.
+	pub usage: usize,
+}
+
+
+/// The result of the Krimp compression.
+#[derive(Debug, Clone)]
+pub struct CodeTable {
+	/// The selected non-singleton patterns that are used in the cover, sorted by usage.
+	pub patterns: Box<[Pattern]>,
+	/// The encoded size in bits, using only singletons.
+	pub standard_size: f64,
+	/// The encoded size in bits, using the selected code table.
+	pub compressed_size: f64,
+}
+
+
+impl CodeTable {
+	/// The ratio between the compressed and the standard encoded sizes, or 1 for an empty
+	/// dataset, which can't be compressed.
+	pub fn ratio(&self) -> f64 {
+		if self.standard_size > 0.0 {
+			self.compressed_size / self.standard_size
+		}
+		else {
+			1.0
+		}
+	}
+}
+
+
+/// The data required to compute the encoded size of a cover.
+struct Database {
+	/// The distinct transactions, with their multiplicity.
+	transactions: Box<[(ItemSet, usize)]>,
+	/// The code length of each item in the standard code table.
+	standard_lengths: Box<[f64]>,
+}
+
+
+impl Database {
+	fn new(dataset: &Dataset) -> Self {
+		let mut transactions = vec![ItemSet::empty(); dataset.transactions_count()];
+
+		for item in 0 .. dataset.items_count() {
+			for (transaction, &value) in dataset.row(item).iter().enumerate() {
+				if value {
+					transactions[transaction].add(item);
+				}
+			}
+		}
+
+		let mut distinct: HashMap<ItemSet, usize> = HashMap::new();
+
+		for transaction in transactions {
+			*distinct.entry(transaction).or_insert(0) += 1;
+		}
+
+		let supports: Box<[usize]> = (0 .. dataset.items_count())
+			.map(|item| dataset.item_support(item))
+			.collect();
+
+		let total: usize = supports.iter().sum();
+
+		let standard_lengths = supports
+			.iter()
+			.map(|&support| code_length(support, total))
+			.collect();
+
+		Self {
+			transactions: distinct.into_iter().collect(),
+			standard_lengths,
+		}
+	}
+
+
+	/// The encoded size of the given code table, which must be in standard cover order.
+	/// Returns the size and the usage of each pattern.
+	fn size(&self, table: &[(ItemSet, dci::Support)]) -> (f64, Box<[usize]>) {
+		let mut usages = vec![0; table.len()].into_boxed_slice();
+
+		for (transaction, count) in self.transactions.iter() {
+			let mut rest = transaction.clone();
+
+			for ((itemset, _), usage) in table.iter().zip(usages.iter_mut()) {
+				if rest.is_empty() {
+					break;
+				}
+
+				if itemset.is_subset(&rest) {
+					rest.remove_all(itemset);
+					*usage += count;
+				}
+			}
+		}
+
+		let total: usize = usages.iter().sum();
+
+		let size = table
+			.iter()
+			.zip(usages.iter())
+			.filter(|(_, &usage)| usage > 0)
+			.map(
+				|((itemset, _), &usage)| {
+					let length = code_length(usage, total);
+
+					// The pattern's code, its description in the standard code table, and its
+					// occurrences in the data.
+					let description: f64 = itemset
+						.into_iter()
+						.map(|item| self.standard_lengths[item])
+						.sum();
+
+					length + description + usage as f64 * length
+				}
+			)
+			// The sum of no sizes would be negative zero.
+			.fold(0.0, |size, pattern| size + pattern);
+
+		(size, usages)
+	}
+}
+
+
+/// Shannon code length for a given usage.
+fn code_length(usage: usize, total: usize) -> f64 {
+	-(usage as f64 / total as f64).log2()
+}
+
+
+/// The standard cover order: length descending, support descending, lexicographically.
+fn cover_order(a: &(ItemSet, dci::Support), b: &(ItemSet, dci::Support)) -> cmp::Ordering {
+	b.0.len()
+		.cmp(&a.0.len())
+		.then(b.1.cmp(&a.1))
+		.then(a.0.cmp(&b.0))
+}
+
+
+/// Select a characteristic set of patterns from the given candidates using Krimp, i.e. the
+/// patterns that best compress the dataset according to the MDL principle.
+///
+/// Candidates are considered in the standard candidate order (support descending, length
+/// descending), and a candidate is kept in the code table only if it reduces the total
+/// encoded size. Singleton candidates are ignored, as they are always part of the code
+/// table.
+pub fn compress(
+	dataset: &Dataset,
+	candidates: &[(ItemSet, dci::Support)]
+) -> CodeTable {
+	let database = Database::new(dataset);
+
+	let mut table: Vec<(ItemSet, dci::Support)> = (0 .. dataset.items_count())
+		.map(|item| (item, dataset.item_support(item)))
+		.filter(|&(_, support)| support > 0)
+		.map(
+			|(item, support)| {
+				let mut itemset = ItemSet::empty();
+				itemset.add(item);
+				(itemset, support)
+			}
+		)
+		.collect();
+
+	table.sort_unstable_by(cover_order);
+
+	let (standard_size, _) = database.size(&table);
+
+	let mut candidates: Vec<&(ItemSet, dci::Support)> = candidates
+		.iter()
+		.filter(|(itemset, _)| itemset.len() > 1)
+		.collect();
+
+	candidates.sort_unstable_by(
+		|a, b| b.1
+			.cmp(&a.1)
+			.then(b.0.len().cmp(&a.0.len()))
+			.then(a.0.cmp(&b.0))
+	);
+
+	let mut best_size = standard_size;
+
+	for candidate in candidates {
+		let position = table
+			.binary_search_by(|entry| cover_order(entry, candidate))
+			.unwrap_or_else(|position| position);
+
+		table.insert(position, candidate.clone());
+
+		let (size, _) = database.size(&table);
+
+		if size < best_size {
+			best_size = size;
+		}
+		else {
+			table.remove(position);
+		}
+	}
+
+	let (compressed_size, usages) = database.size(&table);
+
+	let mut patterns: Box<[Pattern]> = table
+		.into_iter()
+		.zip(usages.iter())
+		.filter(|((itemset, _), &usage)| itemset.len() > 1 && usage > 0)
+		.map(
+			|((itemset, support), &usage)| Pattern { itemset, support, usage }
+		)
+		.collect();
+
+	patterns.sort_by_key(
+		|pattern| cmp::Reverse(pattern.usage)
+	);
+
+	CodeTable {
+		patterns,
+		standard_size,
+		compressed_size,
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use bitmatrix::BitMatrix;
+
+	use super::*;
+
+
+	fn itemset(items: &[usize]) -> ItemSet {
+		let mut itemset = ItemSet::empty();
+
+		for &item in items {
+			itemset.add(item);
+		}
+
+		itemset
+	}
+
+
+	/// Four transactions of items 0 and 1, and two of item 2.
+	fn dataset() -> Dataset {
+		let mut matrix = BitMatrix::new(3, 6);
+
+		for transaction in 0 .. 4 {
+			matrix.set((0, transaction), true);
+			matrix.set((1, transaction), true);
+		}
+
+		for transaction in 4 .. 6 {
+			matrix.set((2, transaction), true);
+		}
+
+		Dataset::from(matrix)
+	}
+
+
+	fn assert_close(a: f64, b: f64) {
+		assert!((a - b).abs() < 1e-9, "{} != {}", a, b);
+	}
+
+
+	#[test]
+	fn size() {
+		let database = Database::new(&dataset());
+
+		// The standard code lengths of the items, with supports 4, 4 and 2 of 10.
+		let (a, b) = (10f64 / 4.0, 10f64 / 2.0);
+
+		assert_close(database.standard_lengths[0], a.log2());
+		assert_close(database.standard_lengths[2], b.log2());
+
+		let singletons = [(itemset(&[0]), 4), (itemset(&[1]), 4), (itemset(&[2]), 2)];
+		let (size, usages) = database.size(&singletons);
+
+		// Each code takes its length, in the table and in each occurrence, plus its
+		// description in the standard code table.
+		assert_eq!(&*usages, &[4, 4, 2]);
+		assert_close(size, 2.0 * (6.0 * a.log2()) + 4.0 * b.log2());
+
+		let table = [(itemset(&[0, 1]), 4), (itemset(&[0]), 4), (itemset(&[1]), 4), (itemset(&[2]), 2)];
+		let (size, usages) = database.size(&table);
+
+		// The pair covers its four transactions, leaving the singletons 0 and 1 unused.
+		assert_eq!(&*usages, &[4, 0, 0, 2]);
+		assert_close(
+			size,
+			5.0 * (6f64 / 4.0).log2() + 2.0 * a.log2()
+				+ 3.0 * (6f64 / 2.0).log2() + b.log2(),
+		);
+	}
+
+
+	#[test]
+	fn compress() {
+		let dataset = dataset();
+
+		let candidates = [
+			(itemset(&[0]), 4),
+			(itemset(&[0, 1]), 4),
+			(itemset(&[1, 2]), 0),
+		];
+
+		let code_table = super::compress(&dataset, &candidates);
+
+		// The pair compresses the dataset, while the unused candidate does not.
+		assert_eq!(
+			&*code_table.patterns,
+			&[Pattern { itemset: itemset(&[0, 1]), support: 4, usage: 4 }],
+		);
+
+		let (a, b) = (10f64 / 4.0, 10f64 / 2.0);
+
+		assert_close(code_table.standard_size, 12.0 * a.log2() + 4.0 * b.log2());
+		assert_close(
+			code_table.compressed_size,
+			5.0 * (6f64 / 4.0).log2() + 2.0 * a.log2()
+				+ 3.0 * (6f64 / 2.0).log2() + b.log2(),
+		);
+		assert!(code_table.ratio() < 1.0);
+
+		// An empty dataset can't be compressed.
+		let empty = super::compress(&Dataset::from(BitMatrix::new(3, 0)), &candidates);
+
+		assert!(empty.patterns.is_empty());
+		assert_eq!(empty.ratio(), 1.0);
+	}
+}
@@ -0,0 +1,126 @@
+use std::{
+	borrow::Cow,
+	collections::HashMap,
+	fs,
+	path::Path,
+	sync::OnceLock,
+};
+
+use serde::Deserialize;
+
+use crate::itemset::ItemSet;
+
+
+/// The built-in catalog, with all supported locales.
+const BUILTIN: &str = include_str!("../labels.toml");
+
+
+/// The catalog in use. If unset, the raw labels are used.
+static CATALOG: OnceLock<Catalog> = OnceLock::new();
+
+
+/// Human-readable labels for a single locale. Missing entries fall back to the raw labels.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Catalog {
+	/// Attribute names, keyed by the `Record` field name, e.g. `age_admission`.
+	#[serde(default)]
+	attributes: HashMap<Box<str>, Box<str>>,
+	/// Value names, keyed by the `Record::labels()` label, e.g. `Age::Age_18_24`.
+	#[serde(default)]
+	values: HashMap<Box<str>, Box<str>>,
+	/// Table headers, e.g. `support`.
+	#[serde(default)]
+	headers: HashMap<Box<str>, Box<str>>,
+}
+
+
+impl Catalog {
+	/// Load the catalog for the given locale from a file with one table per locale. The file
+	/// may be in TOML or JSON format, according to its extension. If no file is given, the
+	/// built-in catalog is used.
+	pub fn load(path: Option<&Path>, lang: &str) -> anyhow::Result<Self> {
+		let mut locales: HashMap<Box<str>, Catalog> = match path {
+			Some(path) => {
+				let contents = fs::read_to_string(path)?;
+
+				match path.extension().and_then(|extension| extension.to_str()) {
+					Some("json") => serde_json::from_str(&contents)?,
+					_ => toml::from_str(&contents)?,
+				}
+			},
+			None => toml::from_str(BUILTIN)?,
+		};
+
+		locales
+			.remove(lang)
+			.ok_or_else(
+				|| anyhow::anyhow!("missing locale in label catalog: {}", lang)
+			)
+	}
+
+
+	/// Use this catalog for all output. This can be called only once.
+	pub fn install(self) {
+		if CATALOG.set(self).is_err() {
+			log::warn!("label catalog already installed");
+		}
+	}
+}
+
+
+/// The name of the given attribute.
+pub fn attribute(field: &str) -> &str {
+	CATALOG
+		.get()
+		.and_then(|catalog| catalog.attributes.get(field))
+		.map(AsRef::as_ref)
+		.unwrap_or(field)
+}
+
+
+/// The name of the given value, labeled as in `Record::labels()`. Without a catalog, this
+/// is the variant name.
+pub fn value(label: &str) -> &str {
+	CATALOG
+		.get()
+		.and_then(|catalog| catalog.values.get(label))
+		.map(AsRef::as_ref)
+		.unwrap_or_else(
+			|| label
+				.rsplit("::")
+				.next()
+				.unwrap_or(label)
+		)
+}
+
+
+/// The name of the given table header.
+pub fn header(key: &'static str, default: &'static str) -> &'static str {
+	CATALOG
+		.get()
+		.and_then(|catalog| catalog.headers.get(key))
+		.map(AsRef::as_ref)
+		.unwrap_or(default)
+}
+
+
+/// The name of the given item, e.g. `Idade na admissão: 18–24`. Without a catalog, this
+/// is the raw label, e.g. `Age::Age_18_24`.
+pub fn item(item: usize) -> Cow<'static, str> {
+	labeled(ItemSet::attribute(item), ItemSet::label(item))
+}
+
+
+/// The name of an item given by its attribute and raw label.
+pub fn labeled<'a>(attribute: &'a str, label: &'a str) -> Cow<'a, str> {
+	match CATALOG.get() {
+		Some(_) => Cow::Owned(
+			format!(
+				"{}: {}",
+				self::attribute(attribute),
+				value(label)
+			)
+		),
+		None => Cow::Borrowed(label),
+	}
+}
@@ -0,0 +1,261 @@
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+use crate::{
+	itemset::ItemSet,
+	labels,
+};
+
+
+/// The lattice of closed itemsets, given by the cover relation: an edge from `a` to `b`
+/// means that `a` is an immediate subset of `b`, i.e. there is no other closed itemset
+/// between them.
+#[derive(Debug, Clone)]
+pub struct Lattice<'a> {
+	nodes: &'a [(ItemSet, dci::Support)],
+	edges: Box<[(usize, usize)]>,
+}
+
+
+#[derive(Serialize)]
+struct JsonNode {
+	id: usize,
+	label: String,
+	/// The raw item labels, as given by `Record::labels()`.
+	items: Box<[&'static str]>,
+	support: dci::Support,
+	size: f64,
+}
+
+
+#[derive(Serialize)]
+struct JsonEdge {
+	source: usize,
+	target: usize,
+}
+
+
+#[derive(Serialize)]
+struct JsonGraph {
+	transactions: usize,
+	nodes: Box<[JsonNode]>,
+	edges: Box<[JsonEdge]>,
+}
+
+
+impl<'a> Lattice<'a> {
+	/// Compute the cover relation among the given closed itemsets.
+	pub fn new(nodes: &'a [(ItemSet, dci::Support)]) -> Self {
+		let mut edges = Vec::new();
+
+		for (ix, (itemset, _)) in nodes.iter().enumerate() {
+			let subsets: Box<[usize]> = nodes
+				.iter()
+				.enumerate()
+				.filter(
+					|(_, (other, _))| other != itemset && other.is_subset(itemset)
+				)
+				.map(|(jx, _)| jx)
+				.collect();
+
+			// A subset is immediate if it is not a subset of any other subset.
+			for &jx in subsets.iter() {
+				let immediate = !subsets
+					.iter()
+					.any(
+						|&kx| kx != jx && nodes[jx].0.is_subset(&nodes[kx].0)
+					);
+
+				if immediate {
+					edges.push((jx, ix));
+				}
+			}
+		}
+
+		Self {
+			nodes,
+			edges: edges.into_boxed_slice(),
+		}
+	}
+
+
+	/// The relative size of a node, proportional to the square root of its support, so that
+	/// the area is proportional to the support.
+	fn size(&self, support: dci::Support) -> f64 {
+		let max_support = self.nodes
+			.iter()
+			.map(|&(_, support)| support)
+			.max()
+			.unwrap_or(1)
+			.max(1);
+
+		(support as f64 / max_support as f64).sqrt()
+	}
+
+
+	/// Write the lattice as a Graphviz DOT graph.
+	pub fn write_dot<W: Write>(&self, mut writer: W, transactions: usize) -> io::Result<()> {
+		writeln!(writer, "digraph lattice {{")?;
+		writeln!(writer, "	rankdir=BT;")?;
+		writeln!(writer, "	node [shape=box, style=rounded];")?;
+
+		for (ix, (itemset, support)) in self.nodes.iter().enumerate() {
+			// The labels are escaped before joining them by the DOT line breaks.
+			let label = if itemset.is_empty() {
+				escape(&itemset.to_string())
+			}
+			else {
+				itemset
+					.into_iter()
+					.map(|item| escape(&labels::item(item)))
+					.collect::<Vec<_>>()
+					.join("\\n")
+			};
+
+			let size = self.size(*support);
+
+			writeln!(
+				writer,
+				"	n{} [label=\"{}\\n{} ({:.1}%)\", width={:.2}, fontsize={:.1}];",
+				ix,
+				label,
+				support,
+				(*support as f64 * 100.0) / transactions as f64,
+				0.75 + 2.25 * size,
+				8.0 + 8.0 * size,
+			)?;
+		}
+
+		for (source, target) in self.edges.iter() {
+			writeln!(writer, "	n{} -> n{};", source, target)?;
+		}
+
+		writeln!(writer, "}}")?;
+
+		writer.flush()
+	}
+
+
+	/// Write the lattice as a JSON node/edge list.
+	pub fn write_json<W: Write>(&self, mut writer: W, transactions: usize) -> anyhow::Result<()> {
+		let nodes = self.nodes
+			.iter()
+			.enumerate()
+			.map(
+				|(id, (itemset, support))| JsonNode {
+					id,
+					label: itemset.to_string(),
+					items: itemset
+						.into_iter()
+						.map(ItemSet::label)
+						.collect(),
+					support: *support,
+					size: self.size(*support),
+				}
+			)
+			.collect();
+
+		let edges = self.edges
+			.iter()
+			.map(
+				|&(source, target)| JsonEdge { source, target }
+			)
+			.collect();
+
+		let graph = JsonGraph {
+			transactions,
+			nodes,
+			edges,
+		};
+
+		serde_json::to_writer_pretty(&mut writer, &graph)?;
+
+		writer.flush()?;
+
+		Ok(())
+	}
+}
+
+
+/// Escape a DOT string: its backslashes and double quotes.
+fn escape(text: &str) -> String {
+	text
+		.replace('\\', "\\\\")
+		.replace('"', "\\\"")
+}
+
+
+#[cfg(test)]
+mod tests {
+	use dci::ItemSet as _;
+
+	use super::*;
+
+
+	fn itemset(items: &[usize]) -> ItemSet {
+		let mut itemset = ItemSet::empty();
+
+		for &item in items {
+			itemset.add(item);
+		}
+
+		itemset
+	}
+
+
+	fn nodes() -> Vec<(ItemSet, dci::Support)> {
+		vec![
+			(itemset(&[]), 10),
+			(itemset(&[0]), 6),
+			(itemset(&[1]), 5),
+			(itemset(&[0, 1]), 4),
+			(itemset(&[0, 1, 2]), 2),
+			(itemset(&[3]), 3),
+		]
+	}
+
+
+	#[test]
+	fn cover_relation() {
+		let nodes = nodes();
+		let lattice = Lattice::new(&nodes);
+
+		let mut edges = lattice.edges.to_vec();
+		edges.sort_unstable();
+
+		// The empty itemset covers only the singletons, and {0} and {1} are not covered by
+		// {0, 1, 2}, as {0, 1} is between them.
+		assert_eq!(edges, [(0, 1), (0, 2), (0, 5), (1, 3), (2, 3), (3, 4)]);
+	}
+
+
+	#[test]
+	fn writers() {
+		let nodes = nodes();
+		let lattice = Lattice::new(&nodes);
+
+		let mut json = Vec::new();
+		let mut writer = io::BufWriter::new(&mut json);
+
+		lattice.write_json(&mut writer, 10).unwrap();
+
+		// The writer is flushed.
+		assert!(writer.buffer().is_empty());
+		drop(writer);
+
+		let graph: serde_json::Value = serde_json::from_slice(&json).unwrap();
+
+		assert_eq!(graph["nodes"].as_array().unwrap().len(), 6);
+		assert_eq!(graph["edges"].as_array().unwrap().len(), 6);
+		assert_eq!(graph["nodes"][3]["support"], 4);
+
+		let mut dot = Vec::new();
+		lattice.write_dot(&mut dot, 10).unwrap();
+		let dot = String::from_utf8(dot).unwrap();
+
+		assert!(dot.contains("	n3 -> n4;"));
+
+		assert_eq!(escape(r#"a\b "c""#), r#"a\\b \"c\""#);
+	}
+}
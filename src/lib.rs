@@ -0,0 +1,61 @@
+/*!
+Analysis of the [ICPSR 36404](https://www.icpsr.umich.edu/web/ICPSR/studies/36404) dataset
+using descriptive machine learning.
+
+The analysis pipeline is composed of the following steps:
+- Reading and filtering the records: [`read_records`](data/fn.read_records.html), or
+  [`read_file`](data/fn.read_file.html) for the SPSS and Stata distributions.
+- Encoding the records as transactions: [`dataset::encode`](dataset/fn.encode.html), or
+  [`dataset::Encoder`](dataset/struct.Encoder.html) to encode the records streamed by
+  [`stream_records`](data/fn.stream_records.html) as they are read.
+- Mining the closed frequent itemsets: [`mining::mine`](mining/fn.mine.html).
+
+The filtered records can be exported to Parquet and Arrow IPC files with the
+[`export`](export/index.html) module. The encoded dataset can be persisted with
+[`dataset::save`](dataset/fn.save.html) and [`dataset::load`](dataset/fn.load.html), or
+in an aligned layout with [`dataset::save_aligned`](dataset/fn.save_aligned.html) to be
+memory-mapped by [`dataset::map`](dataset/fn.map.html). The robustness of the mined itemsets
+to the choice of records can be assessed by resampling with the
+[`stability`](stability/index.html) module,
+every combination of the values of some attributes can be mined with [`grid`](grid/index.html),
+association rules can be generated from the itemsets with [`rules`](rules/index.html), and
+the itemsets can be further analysed through the remaining modules. Results saved as JSON can
+be compared with [`diff`](diff/index.html), and an analysis can be summarized in an HTML
+[`report`](report/index.html).
+
+Other NCRP releases can be described by a [`schema::Schema`](schema/struct.Schema.html),
+which reads the delimited dataset directly into transactions.
+*/
+
+pub mod classifier;
+pub mod data;
+pub mod dataset;
+pub mod diff;
+pub mod export;
+pub mod grid;
+pub mod itemset;
+pub mod krimp;
+pub mod labels;
+pub mod lattice;
+pub mod mining;
+pub mod report;
+pub mod rules;
+pub mod schema;
+pub mod stability;
+pub mod table;
+mod util;
+
+pub use crate::{
+	data::{
+		Filter,
+		Record,
+		distribution::Distribution,
+		read_file,
+		read_records,
+		stream_file,
+		stream_records,
+	},
+	dataset::Dataset,
+	itemset::ItemSet,
+	mining::{Constraints, Itemsets},
+};
@@ -1,12 +1,13 @@
 mod args;
-mod data;
-mod util;
-mod itemset;
+mod config;
+mod manifest;
+mod repl;
+mod serve;
 
 use std::{
-	cmp,
-	collections::HashMap,
-	io::{self, Write},
+	fs,
+	io::{self, BufRead, Write},
+	path::{Path, PathBuf},
 	time
 };
 
@@ -14,162 +15,421 @@ use dci::DataSet;
 
 use onehot::OneHot;
 
-use crate::{
-	args::Command,
+use icpsr_36404_analysis::{
+	classifier,
 	data::{
-		Record,
-		distribution::Distribution as DataDistribution
+		cache::Cache,
+		codebook::Codebook,
+		sample::Sampling,
+		weight::{Weighting, Weights},
+		Grouping,
 	},
-	itemset::ItemSet,
+	dataset::{self, Dataset},
+	diff,
+	export,
+	grid,
+	krimp,
+	labels,
+	lattice,
+	mining,
+	report::Report,
+	rules,
+	schema::{self, Schema},
+	stability,
+	table,
+	Distribution,
+	Filter,
+	ItemSet,
+	Record,
+	stream_file,
+	stream_records,
 };
 
+use crate::args::{Command, Mining};
 
-fn read_records<R: io::BufRead>(
-	reader: R,
-	options: args::Options
-) -> io::Result<(Vec<Record>, DataDistribution)> {
+
+/// The given output path with the given extension appended, e.g. `out/v1.2.dot`, as the
+/// output paths are given without extension, and may contain dots.
+fn with_extension(path: &Path, extension: &str) -> PathBuf {
+	let mut path = path.as_os_str().to_owned();
+	path.push(".");
+	path.push(extension);
+	path.into()
+}
+
+
+fn export_lattice(
+	result: &[(ItemSet, dci::Support)],
+	transactions: usize,
+	path: &Path
+) -> anyhow::Result<()> {
 	let clock = time::Instant::now();
 
-	let mut records = Vec::with_capacity(8_000_000); // number of expected records
-	let mut earliest_records: HashMap<Box<[u8]>, Record> = HashMap::new();
-	let mut data_distribution = DataDistribution::new();
+	let lattice = lattice::Lattice::new(result);
 
-	let mut line_splitter = util::RawLineSplitter::new(reader);
+	let dot = fs::File::create(with_extension(path, "dot"))?;
+	lattice.write_dot(io::BufWriter::new(dot), transactions)?;
 
-	line_splitter.read_line()?; // skip header
+	let json = fs::File::create(with_extension(path, "json"))?;
+	lattice.write_json(io::BufWriter::new(json), transactions)?;
 
-	let mut line_count: usize = 0;
+	log::info!("Exporting lattice took {:.2?}", clock.elapsed());
 
-	while let Some(line) = line_splitter.read_line()? {
-		line_count += 1;
+	Ok(())
+}
 
-		let mut fields = line.split(|&c| c == b'\t');
 
-		let id = fields.next().unwrap();
+fn run_krimp(
+	dataset: &Dataset,
+	candidates: &[(ItemSet, dci::Support)]
+) -> krimp::CodeTable {
+	let clock = time::Instant::now();
 
-		match data::Record::parse(fields) {
-			Ok(mut record) => {
-				let mut valid = true;
+	let code_table = krimp::compress(dataset, candidates);
 
-				if options.recidivists {
-					match earliest_records.get_mut(id) {
-						// Found an earlier record. We can swap *before* the validity check.
-						Some(early_record) => if early_record.admission_year > record.admission_year {
-							std::mem::swap(early_record, &mut record);
-						},
-						None => { // The current record is the earliest for now.
-							earliest_records.insert(id.into(), record);
-							valid = false;
-						},
-					};
-				}
+	log::info!("Krimp took {:.2?}", clock.elapsed());
 
-				valid &= record.admission_type != data::AdmissionType::Missing
-				      && record.offense_type   != data::OffenseType::Missing
-				      && record.sentence       != data::Sentence::Missing
-				      && record.race           != data::Race::Missing
-				      && record.age_admission  != data::Age::Missing
-				      && record.time_served    != data::TimeServed::Missing
-				      && record.release_type   != data::ReleaseType::Missing;
+	code_table
+}
 
-				if let Some(sex) = options.sex {
-					valid &= record.sex == sex;
-				}
 
-				if let Some(admission_type) = options.admission_type {
-					valid &= record.admission_type == admission_type;
-				}
+/// Stream the records from the input file, or from stdin.
+fn stream_input(
+	input: Option<&Path>,
+	filter: Filter,
+	grouping: Grouping,
+	sampling: Option<Sampling>,
+	weighting: Option<&Weighting>,
+	sink: impl FnMut(&[u8], &Record),
+) -> anyhow::Result<Distribution> {
+	let distribution = match input {
+		Some(path) => stream_file(path, filter, grouping, sampling, weighting, sink)?,
+		None => stream_records(io::stdin().lock(), filter, grouping, sampling, weighting, sink)?,
+	};
 
-				if let Some(race) = options.race {
-					valid &= record.race == race;
-				}
+	manifest::count(&distribution);
 
-				if valid {
-					records.push(record);
-					data_distribution.insert(&record);
-				}
-			},
+	Ok(distribution)
+}
 
-			Err(err) => {
-				log::warn!(
-					"invalid record at line {}: {}\n{:?}",
-					line_count,
-					err,
-					String::from_utf8_lossy(line)
-				);
-			}
-		};
+
+/// Read the records from the input file, or from stdin.
+fn read_input(
+	input: Option<&Path>,
+	filter: Filter,
+	grouping: Grouping,
+	sampling: Option<Sampling>,
+) -> anyhow::Result<(Vec<Record>, Distribution)> {
+	let mut records = Vec::new();
+
+	let distribution = stream_input(input, filter, grouping, sampling, None, |_, record| records.push(*record))?;
+
+	Ok((records, distribution))
+}
+
+
+/// Encode the records from the input file, or from stdin, as they are read.
+fn encode_input(
+	input: Option<&Path>,
+	filter: Filter,
+	grouping: Grouping,
+	sampling: Option<Sampling>,
+	weighting: Option<&Weighting>,
+) -> anyhow::Result<(Dataset, Distribution)> {
+	let mut encoder = dataset::Encoder::new();
+
+	let distribution = stream_input(input, filter, grouping, sampling, weighting, |_, record| encoder.push(record))?;
+
+	Ok((encoder.finish(), distribution))
+}
+
+
+/// Read a schema-described dataset from the input file, in any supported format, or from
+/// stdin in the delimited format.
+fn read_schema_input(schema: &Schema, input: Option<&Path>) -> anyhow::Result<(schema::Dataset, schema::Distribution)> {
+	match input {
+		Some(path) => schema.read_file(path),
+		None => schema.read(io::stdin().lock()),
+	}
+}
+
+
+/// Compute the support of the itemset by intersecting the rows of its items, and output
+/// the ids or the distribution of the covered records, if given.
+fn run_query(
+	mut out: impl Write,
+	dataset: &Dataset,
+	itemset: &ItemSet,
+	ids: Option<&[Box<[u8]>]>,
+	records: Option<&[Record]>,
+) -> anyhow::Result<()> {
+	let transactions = dataset.transactions_count();
+
+	let cover = dataset.cover(itemset);
+	let support = cover.count_ones();
+
+	writeln!(out, "Itemset: {}", itemset)?;
+	writeln!(out, "Transactions: {}", transactions)?;
+	writeln!(out, "Support: {} ({:.1}%)", support, support as f64 * 100.0 / transactions.max(1) as f64)?;
+
+	let covered = cover
+		.iter()
+		.enumerate()
+		.filter_map(|(ix, &covered)| if covered { Some(ix) } else { None });
+
+	if let Some(records) = records {
+		let mut distribution = Distribution::new();
+
+		for ix in covered.clone() {
+			distribution.insert(&records[ix]);
+		}
+
+		writeln!(out, "Distribution:")?;
+		write!(out, "{}", distribution)?;
+	}
+
+	if let Some(ids) = ids {
+		writeln!(out, "Records:")?;
+
+		for ix in covered {
+			writeln!(out, "{}", String::from_utf8_lossy(&ids[ix]))?;
+		}
 	}
 
-	log::info!("Importing dataset took {:.2?}", clock.elapsed());
+	out.flush()?;
 
-	Ok((records, data_distribution))
+	Ok(())
 }
 
 
-fn encode_records(records: &[Record]) -> dci::Matrix<ItemSet> {
-	let clock = time::Instant::now();
+/// Mine the itemsets by their weighted support.
+fn run_weighted(out: &mut impl Write, dataset: &Dataset, weights: &Weights, mining: &Mining) -> anyhow::Result<()> {
+	let total_weight = weights.total();
+
+	let min_sup = total_weight * mining.min_sup_ratio;
 
-	let dataset: dci::Matrix<ItemSet> = onehot::vertical(&records).into();
+	if mining.table.format == table::Format::Text {
+		writeln!(out, "Transactions: {}", dataset.transactions_count())?;
+		writeln!(out, "Weight: {:.1}", total_weight)?;
+		writeln!(out, "Items ({}): {}", Record::ONEHOT_LEN, ItemSet::full())?;
+		writeln!(out, "minsup: {:.1} ({:.1}%)", min_sup, 100.0 * mining.min_sup_ratio)?;
+	}
+	else {
+		log::info!("Transactions: {}", dataset.transactions_count());
+		log::info!("Weight: {:.1}", total_weight);
+		log::info!("minsup: {:.1} ({:.1}%)", min_sup, 100.0 * mining.min_sup_ratio);
+	}
 
-	log::info!("Encoding dataset took {:.2?}", clock.elapsed());
+	let result = mining::mine_weighted(dataset, weights, min_sup, &mining.constraints);
 
-	dataset
+	mining.table.weighted_itemsets(&mut *out, &result, dataset, weights)?;
+
+	Ok(())
 }
 
 
-fn save_dataset(dataset: &dci::Matrix<ItemSet>) -> anyhow::Result<()> {
-	let stdout = io::stdout();
-	let stdout = stdout.lock();
-	let mut stdout = io::BufWriter::with_capacity(
-		8 * 1024 * 1204,
-		stdout,
-	);
+/// Mine the dataset, generate the rules and output the report in HTML.
+fn run_report(
+	out: &mut impl Write,
+	dataset: &Dataset,
+	data_distribution: &Distribution,
+	mining: &Mining,
+	min_confidence: f64,
+	options: Filter,
+	input: Option<&Path>,
+) -> anyhow::Result<()> {
+	let transactions = dataset.transactions_count();
 
-	let dataset: &bitmatrix::BitMatrix = dataset.into();
+	let min_sup = (transactions as f64 * mining.min_sup_ratio) as usize;
 
-	let clock = time::Instant::now();
+	let result = mining::mine(dataset, min_sup, &mining.constraints);
+
+	let rules = rules::generate(dataset, &result, min_confidence);
 
-	rmp_serde::encode::write(&mut stdout, &dataset)?;
-	stdout.flush()?;
+	let or_all = |value: Option<String>| value.unwrap_or_else(|| "all".into());
 
-	log::info!("Saving dataset took {:.2?}", clock.elapsed());
+	let mut configuration = vec![
+		("Version", clap::crate_version!().to_owned()),
+		("Input", input.map_or_else(|| "stdin".into(), |path| path.display().to_string())),
+		("Recidivists", if options.recidivists { "yes" } else { "no" }.into()),
+		("Sex", or_all(options.sex.map(|sex| format!("{:?}", sex)))),
+		("Admission type", or_all(options.admission_type.map(|admission_type| format!("{:?}", admission_type)))),
+		("Race", or_all(options.race.map(|race| format!("{:?}", race)))),
+	];
+
+	if let Some(frame) = &data_distribution.frame {
+		configuration.push(("Sample", frame.to_string().trim_end().to_owned()));
+	}
+
+	configuration.push(("Transactions", transactions.to_string()));
+	configuration.push(("Minimum support", format!("{} ({:.1}%)", min_sup, 100.0 * mining.min_sup_ratio)));
+	configuration.push(("Minimum confidence", format!("{:.1}%", 100.0 * min_confidence)));
+
+	if !mining.constraints.must_contain.is_empty() {
+		configuration.push(("Must contain", mining.constraints.must_contain.to_string()));
+	}
+
+	if !mining.constraints.must_not_contain.is_empty() {
+		configuration.push(("Must not contain", mining.constraints.must_not_contain.to_string()));
+	}
+
+	if let Some(max_length) = mining.constraints.max_length {
+		configuration.push(("Maximum length", max_length.to_string()));
+	}
+
+	let report = Report {
+		title: "ICPSR 36404 analysis",
+		configuration: &configuration,
+		distribution: data_distribution,
+		dataset,
+		itemsets: &result,
+		rules: &rules,
+		limit: mining.table.limit.unwrap_or(result.len()),
+	};
+
+	report.write(io::BufWriter::new(&mut *out))?;
 
 	Ok(())
 }
 
 
-fn load_dataset<R: io::BufRead>(reader: R) -> anyhow::Result<dci::Matrix<ItemSet>> {
+/// Write the itemsets of each cell of the grid to its directory, in the format of the table,
+/// and the index of the cells.
+fn write_grid(
+	out: &mut impl Write,
+	cells: &[grid::Cell],
+	dimensions: &[grid::Dimension],
+	mining: &Mining,
+	output: &Path,
+) -> anyhow::Result<()> {
 	let clock = time::Instant::now();
 
-	let dataset: bitmatrix::BitMatrix = rmp_serde::decode::from_read(reader)?;
+	let extension = match mining.table.format {
+		table::Format::Text     => "txt",
+		table::Format::Latex    => "tex",
+		table::Format::Markdown => "md",
+		table::Format::Json     => "json",
+	};
+
+	let mut index = Vec::with_capacity(cells.len());
+
+	writeln!(out, "Cells: {}", cells.len())?;
 
-	log::info!("Restoring dataset took {:.2?}", clock.elapsed());
+	for cell in cells {
+		let summary = cell.summary(dimensions);
 
-	log::info!("Restored {}x{} matrix.", dataset.height(), dataset.width());
+		let directory = output.join(&summary.path);
+		fs::create_dir_all(&directory)?;
 
-	Ok(dataset.into())
+		let file = fs::File::create(directory.join("itemsets").with_extension(extension))?;
+		mining.table.itemsets(io::BufWriter::new(file), &cell.itemsets, &cell.dataset, None)?;
+
+		writeln!(
+			out,
+			"{}: {} records, minsup {}, {} itemsets",
+			summary.path,
+			summary.records,
+			summary.min_sup,
+			summary.itemsets,
+		)?;
+
+		index.push(summary);
+	}
+
+	let file = fs::File::create(output.join("index.json"))?;
+	serde_json::to_writer_pretty(io::BufWriter::new(file), &index)?;
+
+	log::info!("Writing the grid took {:.2?}", clock.elapsed());
+
+	Ok(())
 }
 
 
-fn run_dci(dataset: &dci::Matrix<ItemSet>, min_sup: dci::Support) -> Box<[(ItemSet, dci::Support)]> {
-	let clock = time::Instant::now();
+/// Run the pipeline over a schema-described dataset.
+fn run_schema(out: &mut impl Write, input: Option<&Path>, path: &Path, mining: &Mining) -> anyhow::Result<()> {
+	let schema = Schema::load(path)?;
 
-	let mut result = dci::parallel::closed(dataset, min_sup);
+	let (dataset, data_distribution) = read_schema_input(&schema, input)?;
 
-	log::info!("Dci took {:.2?}", clock.elapsed());
+	log::info!("{}", data_distribution);
 
-	result.sort_unstable_by_key(
-		|(_itemset, support)| cmp::Reverse(*support)
-	);
+	let transactions = dataset.transactions_count();
+
+	let min_sup = (transactions as f64 * mining.min_sup_ratio) as usize;
+
+	if mining.table.format == table::Format::Text {
+		let items = (0 .. schema.items_count())
+			.map(|item| schema.item_name(item))
+			.collect::<Vec<_>>()
+			.join(", ");
+
+		writeln!(out, "Study: {}", schema.name)?;
+		writeln!(out, "Transactions: {}", transactions)?;
+		writeln!(out, "Items ({}): {{{}}}", schema.items_count(), items)?;
+		writeln!(out, "minsup: {} ({:.1}%)", min_sup, 100.0 * mining.min_sup_ratio)?;
+	}
+	else {
+		log::info!("Transactions: {}", transactions);
+		log::info!("minsup: {} ({:.1}%)", min_sup, 100.0 * mining.min_sup_ratio);
+	}
 
-	result
+	let result = schema::mine(&dataset, min_sup);
+
+	mining.table.schema_itemsets(&mut *out, &result, &dataset, &schema)?;
+
+	Ok(())
+}
+
+
+/// Import and validate the value labels of a setup file.
+fn run_codebook<R: BufRead>(out: &mut impl Write, reader: R, setup: &Path, data: bool, schema: bool) -> anyhow::Result<()> {
+	let codebook = Codebook::load(setup)?;
+
+	if schema {
+		let name = setup
+			.file_stem()
+			.map(|stem| stem.to_string_lossy())
+			.unwrap_or_default();
+
+		write!(out, "{}", codebook.schema(&name)?.to_toml()?)?;
+
+		return Ok(());
+	}
+
+	writeln!(out, "Variables ({}):", codebook.variables.len())?;
+	write!(out, "{}", codebook)?;
+
+	let mismatches = codebook.validate();
+
+	writeln!(out, "Mismatches with the built-in codes ({}):", mismatches.len())?;
+
+	for mismatch in mismatches {
+		writeln!(out, "	{}", mismatch)?;
+	}
+
+	if data {
+		let clock = time::Instant::now();
+
+		let undocumented = codebook.check(reader)?;
+
+		log::info!("Checking dataset took {:.2?}", clock.elapsed());
+
+		writeln!(out, "Codes absent from the codebook ({}):", undocumented.len())?;
+
+		for code in undocumented {
+			writeln!(out, "	{}", code)?;
+		}
+	}
+
+	Ok(())
 }
 
 
 fn main() -> anyhow::Result<()> {
-	let command = args::parse(
-		std::env::args()
+	let arguments: Vec<String> = std::env::args().collect();
+
+	let (command, localization, manifest) = args::parse(
+		arguments.iter().cloned()
 	)?;
 
 	match command {
@@ -180,52 +440,353 @@ fn main() -> anyhow::Result<()> {
 		_ => (),
 	};
 
-	simplelog::TermLogger
-		::init(
-			log::LevelFilter::Trace,
-			simplelog::ConfigBuilder
-				::new()
-				.set_time_level(log::LevelFilter::Off)
-				.build(),
-			simplelog::TerminalMode::Stderr
-		)
-		.unwrap();
+	let logger = simplelog::TermLogger::new(
+		log::LevelFilter::Trace,
+		simplelog::ConfigBuilder
+			::new()
+			.set_time_level(log::LevelFilter::Off)
+			// The line editor of the repl logs every key.
+			.add_filter_ignore_str("rustyline")
+			.build(),
+		simplelog::TerminalMode::Stderr
+	);
+
+	log::set_boxed_logger(Box::new(manifest::Recorder::new(logger))).unwrap();
+	log::set_max_level(log::LevelFilter::Trace);
+
+	if let Some(lang) = &localization.lang {
+		labels::Catalog
+			::load(localization.labels.as_deref(), lang)?
+			.install();
+	}
+
+	let input = command.input().map(Path::to_path_buf);
+	let description = format!("{:?}", command);
 
-	let stdin = io::stdin();
-	let stdin = stdin.lock();
+	let mut output = manifest::Output::new();
+
+	execute(command, &mut output)?;
+
+	output.flush()?;
+
+	if let Some(path) = manifest {
+		manifest::write(&path, arguments, description, input.as_deref(), output)?;
+	}
+
+	Ok(())
+}
 
-	let (dataset, min_sup_ratio) = match command {
-		Command::Distribution { options } => {
-			let (_, data_distribution) = read_records(stdin, options)?;
 
-			print!("{}", data_distribution);
+/// Run the given command, writing its results to the given output.
+fn execute(command: Command, out: &mut manifest::Output) -> anyhow::Result<()> {
+	let (dataset, mining, frame, weights) = match command {
+		Command::Distribution { table, schema: Some(path), input, .. } => {
+			let schema = Schema::load(&path)?;
+
+			let (_, data_distribution) = read_schema_input(&schema, input.as_deref())?;
+
+			table.schema_distribution(&mut *out, &data_distribution)?;
 
 			return Ok(());
 		},
 
-		Command::Load { min_sup_ratio } => (
-			load_dataset(stdin)?,
-			min_sup_ratio
-		),
+		Command::Distribution { options, grouping, sampling, weighting, table, schema: None, input } => {
+			let data_distribution = stream_input(input.as_deref(), options, grouping, sampling, weighting.as_ref(), |_, _| ())?;
+
+			table.distribution(&mut *out, &data_distribution)?;
+
+			return Ok(());
+		},
+
+		Command::Classify { parameters, folds, options, grouping, sampling, input } => {
+			let (records, data_distribution) = read_input(input.as_deref(), options, grouping, sampling)?;
+
+			log::info!("{}", data_distribution);
+
+			let clock = time::Instant::now();
+
+			let classifier = classifier::Classifier::train(&records, &parameters);
+
+			log::info!("Training took {:.2?}", clock.elapsed());
+
+			writeln!(out, "Rules ({}):", classifier.rules.len())?;
+			write!(out, "{}", classifier)?;
+
+			let clock = time::Instant::now();
+
+			let evaluation = classifier::cross_validate(&records, &parameters, folds);
+
+			log::info!("Cross-validation took {:.2?}", clock.elapsed());
+
+			writeln!(out, "Cross-validation ({} folds):", folds)?;
+			write!(out, "{}", evaluation)?;
+
+			return Ok(());
+		},
+
+		Command::Stability { mining, parameters, options, grouping, input } => {
+			let (dataset, data_distribution) = encode_input(input.as_deref(), options, grouping, None, None)?;
+
+			log::info!("{}", data_distribution);
+
+			let transactions = dataset.transactions_count();
+
+			let min_sup = (transactions as f64 * mining.min_sup_ratio) as usize;
+
+			let result = mining::mine(&dataset, min_sup, &mining.constraints);
+
+			let analysis = stability::analyze(
+				&dataset,
+				&result,
+				mining.min_sup_ratio,
+				&mining.constraints,
+				&parameters,
+			);
+
+			if mining.table.format == table::Format::Text {
+				writeln!(out, "Transactions: {}", transactions)?;
+				writeln!(out, "minsup: {} ({:.1}%)", min_sup, 100.0 * mining.min_sup_ratio)?;
+				writeln!(out, "Resamples: {} ({:?}), seed {}", parameters.resamples, parameters.resampling, analysis.seed)?;
+			}
+			else {
+				log::info!("Transactions: {}", transactions);
+				log::info!("minsup: {} ({:.1}%)", min_sup, 100.0 * mining.min_sup_ratio);
+				log::info!("Resamples: {} ({:?}), seed {}", parameters.resamples, parameters.resampling, analysis.seed);
+			}
+
+			mining.table.stability(&mut *out, &analysis.itemsets, &dataset)?;
+
+			return Ok(());
+		},
+
+		Command::Report { mining, min_confidence, options, grouping, sampling, input } => {
+			let (dataset, data_distribution) = encode_input(input.as_deref(), options, grouping, sampling, None)?;
+
+			log::info!("{}", data_distribution);
+
+			return run_report(out, &dataset, &data_distribution, &mining, min_confidence, options, input.as_deref());
+		},
+
+		Command::Grid { mining, dimensions, options, grouping, output, input } => {
+			let (records, data_distribution) = read_input(input.as_deref(), options, grouping, None)?;
+
+			log::info!("{}", data_distribution);
+
+			let cells = grid::mine(
+				&records,
+				&grid::cells(options, &dimensions),
+				mining.min_sup_ratio,
+				&mining.constraints,
+			);
+
+			return write_grid(out, &cells, &dimensions, &mining, &output);
+		},
+
+		Command::Export { path, options, grouping, sampling, input } => {
+			let (records, data_distribution) = read_input(input.as_deref(), options, grouping, sampling)?;
+
+			log::info!("{}", data_distribution);
+
+			let clock = time::Instant::now();
+
+			let parquet = fs::File::create(with_extension(&path, "parquet"))?;
+			export::write_parquet(io::BufWriter::new(parquet), &records)?;
+
+			let arrow = fs::File::create(with_extension(&path, "arrow"))?;
+			export::write_ipc(io::BufWriter::new(arrow), &records)?;
+
+			log::info!("Exporting records took {:.2?}", clock.elapsed());
+
+			return Ok(());
+		},
+
+		Command::Cache { input } => {
+			let cache = match input {
+				Some(path) => Cache::read_file(&path)?,
+				None => Cache::read_records(io::stdin().lock())?,
+			};
+
+			log::info!("Cached {} records.", cache.len());
+
+			cache.save(&mut *out)?;
 
-		Command::Save { options } => {
-			let (records, data_distribution) = read_records(stdin, options)?;
+			return Ok(());
+		},
+
+		Command::Query { itemset, ids, distribution, options, grouping, sampling, input } => {
+			let mut encoder = dataset::Encoder::new();
+			let mut record_ids = Vec::new();
+			let mut records = Vec::new();
+
+			let data_distribution = stream_input(
+				input.as_deref(),
+				options,
+				grouping,
+				sampling,
+				None,
+				|id, record| {
+					encoder.push(record);
+
+					if ids {
+						record_ids.push(id.into());
+					}
+
+					if distribution {
+						records.push(*record);
+					}
+				},
+			)?;
 
 			log::info!("{}", data_distribution);
 
-			let dataset = encode_records(&records);
+			let clock = time::Instant::now();
+
+			let dataset = encoder.finish();
 
-			save_dataset(&dataset)?;
+			run_query(
+				&mut *out,
+				&dataset,
+				&itemset,
+				if ids { Some(&record_ids) } else { None },
+				if distribution { Some(&records) } else { None },
+			)?;
+
+			log::info!("Query took {:.2?}", clock.elapsed());
 
 			return Ok(());
 		},
 
-		Command::Run { min_sup_ratio, options } => {
-			let (records, data_distribution) = read_records(stdin, options)?;
+		Command::Repl { options, grouping, sampling, input } => {
+			let (records, data_distribution) = read_input(Some(&input), options, grouping, sampling)?;
 
 			log::info!("{}", data_distribution);
 
-			(encode_records(&records), min_sup_ratio)
+			return repl::run(records);
+		},
+
+		Command::Serve { input, settings } => {
+			let dataset = match input {
+				Some(path) => dataset::open(&path)?,
+				None => dataset::load(io::stdin().lock())?,
+			};
+
+			return serve::run(dataset, settings);
+		},
+
+		Command::Diff { before, after } => {
+			let before_results = diff::Results::load(&before)?;
+			let after_results = diff::Results::load(&after)?;
+
+			writeln!(
+				out,
+				"Before: {} itemsets, {} transactions ({})",
+				before_results.itemsets.len(),
+				before_results.transactions,
+				before.display(),
+			)?;
+			writeln!(
+				out,
+				"After: {} itemsets, {} transactions ({})",
+				after_results.itemsets.len(),
+				after_results.transactions,
+				after.display(),
+			)?;
+
+			write!(out, "{}", diff::diff(&before_results, &after_results))?;
+
+			return Ok(());
+		},
+
+		Command::Codebook { setup, data, schema } => {
+			return run_codebook(out, io::stdin().lock(), &setup, data, schema);
+		},
+
+		Command::Batch { commands } => {
+			let format = |command: &Command| match command {
+				Command::Run { mining, .. } => mining.table.format,
+				_ => table::Format::Text,
+			};
+
+			// The documents of the profiles are output as a JSON array, so that they can be
+			// parsed as a whole.
+			let json = commands
+				.iter()
+				.all(|(_, command)| format(command) == table::Format::Json);
+
+			if json {
+				write!(out, "[")?;
+			}
+
+			for (ix, (profile, command)) in commands.into_iter().enumerate() {
+				if json {
+					if ix > 0 {
+						write!(out, ",")?;
+					}
+
+					write!(out, "{{\"profile\":{},\"output\":", serde_json::to_string(&profile)?)?;
+				}
+				else if format(&command) == table::Format::Text {
+					writeln!(out, "Profile: {}", profile)?;
+				}
+				else {
+					log::info!("Profile: {}", profile);
+				}
+
+				execute(command, out)?;
+
+				if json {
+					write!(out, "}}")?;
+				}
+			}
+
+			if json {
+				writeln!(out, "]")?;
+			}
+
+			return Ok(());
+		},
+
+		Command::Load { mining, input } => (
+			match input {
+				Some(path) => dataset::open(&path)?,
+				None => dataset::load(io::stdin().lock())?,
+			},
+			mining,
+			None,
+			None,
+		),
+
+		Command::Save { options, grouping, sampling, input, aligned } => {
+			let (dataset, data_distribution) = encode_input(input.as_deref(), options, grouping, sampling, None)?;
+
+			log::info!("{}", data_distribution);
+
+			if aligned {
+				dataset::save_aligned(&dataset, &mut *out)?;
+			}
+			else {
+				dataset::save(&dataset, &mut *out)?;
+			}
+
+			return Ok(());
+		},
+
+		Command::Run { mining, schema: Some(path), input, .. } => {
+			return run_schema(out, input.as_deref(), &path, &mining);
+		},
+
+		Command::Run { mining, options, grouping, sampling, weighting, schema: None, input } => {
+			let (dataset, data_distribution) = encode_input(
+				input.as_deref(),
+				options,
+				grouping,
+				sampling,
+				weighting.as_ref(),
+			)?;
+
+			log::info!("{}", data_distribution);
+
+			(dataset, mining, data_distribution.frame, data_distribution.weights)
 		},
 
 		_ => unreachable!(),
@@ -233,23 +794,61 @@ fn main() -> anyhow::Result<()> {
 
 	let transactions = dataset.transactions_count();
 
-	println!("Transactions: {}", transactions);
-	println!("Items ({}): {}", data::Record::ONEHOT_LEN, ItemSet::full());
+	if let Some(weights) = &weights {
+		return run_weighted(out, &dataset, weights, &mining);
+	}
+
+	let min_sup = (transactions as f64 * mining.min_sup_ratio) as usize;
+
+	// Tables must contain nothing but the table, so they can be included in documents.
+	if mining.table.format == table::Format::Text {
+		writeln!(out, "Transactions: {}", transactions)?;
 
-	let min_sup = (transactions as f64 * min_sup_ratio) as usize;
+		if let Some(frame) = &frame {
+			write!(out, "Sample: {}", frame)?;
+		}
 
-	println!("minsup: {} ({:.1}%)", min_sup, 100.0 * min_sup_ratio);
+		writeln!(out, "Items ({}): {}", Record::ONEHOT_LEN, ItemSet::full())?;
+		writeln!(out, "minsup: {} ({:.1}%)", min_sup, 100.0 * mining.min_sup_ratio)?;
+	}
+	else {
+		log::info!("Transactions: {}", transactions);
+		log::info!("minsup: {} ({:.1}%)", min_sup, 100.0 * mining.min_sup_ratio);
+	}
 
-	let result = run_dci(&dataset, min_sup);
+	let result = mining::mine(&dataset, min_sup, &mining.constraints);
 
-	for (closed_itemset, support) in result.into_vec() { // Boxed slice has no owned iterator.
-		println!(
-			"{} ({:.1}%): {}",
-			support,
-			(support as f64 * 100.0) / transactions as f64,
-			closed_itemset
-		);
+	if let Some(path) = &mining.export_lattice {
+		export_lattice(&result, transactions, path)?;
 	}
 
+	if mining.compress {
+		let code_table = run_krimp(&dataset, &result);
+
+		writeln!(
+			out,
+			"Compression ratio: {:.1}% ({:.0} / {:.0} bits)",
+			100.0 * code_table.ratio(),
+			code_table.compressed_size,
+			code_table.standard_size,
+		)?;
+		writeln!(out, "Patterns: {}", code_table.patterns.len())?;
+
+		for pattern in code_table.patterns.iter() {
+			writeln!(
+				out,
+				"{} ({:.1}%), usage {}: {}",
+				pattern.support,
+				(pattern.support as f64 * 100.0) / transactions as f64,
+				pattern.usage,
+				pattern.itemset
+			)?;
+		}
+
+		return Ok(());
+	}
+
+	mining.table.itemsets(&mut *out, &result, &dataset, frame.as_ref())?;
+
 	Ok(())
 }
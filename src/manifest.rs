@@ -0,0 +1,219 @@
+//! The manifest of a run, which traces its output to the binary, the command and the input
+//! that produced it. The timings are those logged by the pipeline, as `<step> took <time>`.
+
+use std::{
+	fs,
+	io::{self, Read, Write},
+	path::Path,
+	sync::Mutex,
+};
+
+use serde::Serialize;
+
+use sha2::{Digest, Sha256};
+
+use icpsr_36404_analysis::{
+	data::Funnel,
+	Distribution,
+};
+
+
+/// The timings logged so far.
+static TIMINGS: Mutex<Vec<Timing>> = Mutex::new(Vec::new());
+
+/// The record counts of the last dataset read.
+static RECORDS: Mutex<Option<Records>> = Mutex::new(None);
+
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct Timing {
+	step: String,
+	duration: String,
+}
+
+
+/// The number of records after each filter, and after sampling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+struct Records {
+	#[serde(flatten)]
+	funnel: Funnel,
+	accepted: usize,
+}
+
+
+/// Record the counts of the records of the given distribution.
+pub fn count(distribution: &Distribution) {
+	*RECORDS.lock().expect("records lock should not be poisoned") = Some(
+		Records {
+			funnel: distribution.funnel,
+			accepted: distribution.total_records,
+		}
+	);
+}
+
+
+/// A logger that records the timings, and forwards everything to the given logger.
+pub struct Recorder(Box<dyn log::Log>);
+
+
+impl Recorder {
+	pub fn new(logger: Box<dyn log::Log>) -> Self {
+		Self(logger)
+	}
+}
+
+
+impl log::Log for Recorder {
+	fn enabled(&self, metadata: &log::Metadata) -> bool {
+		self.0.enabled(metadata)
+	}
+
+
+	fn log(&self, record: &log::Record) {
+		if record.level() == log::Level::Info {
+			let message = record.args().to_string();
+
+			if let Some((step, duration)) = message.split_once(" took ") {
+				TIMINGS
+					.lock()
+					.expect("timings lock should not be poisoned")
+					.push(Timing { step: step.into(), duration: duration.into() });
+			}
+		}
+
+		self.0.log(record);
+	}
+
+
+	fn flush(&self) {
+		self.0.flush();
+	}
+}
+
+
+/// The standard output, hashed as it is written.
+pub struct Output {
+	stdout: io::Stdout,
+	hasher: Sha256,
+	size: u64,
+}
+
+
+impl Output {
+	pub fn new() -> Self {
+		Self {
+			stdout: io::stdout(),
+			hasher: Sha256::new(),
+			size: 0,
+		}
+	}
+}
+
+
+impl Write for Output {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		let written = self.stdout.write(buf)?;
+
+		self.hasher.update(&buf[.. written]);
+		self.size += written as u64;
+
+		Ok(written)
+	}
+
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.stdout.flush()
+	}
+}
+
+
+/// The size and hash of a file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct Contents {
+	#[serde(skip_serializing_if = "Option::is_none")]
+	path: Option<String>,
+	size: u64,
+	sha256: String,
+}
+
+
+impl Contents {
+	fn new(path: Option<&Path>, size: u64, hasher: Sha256) -> Self {
+		let sha256 = hasher
+			.finalize()
+			.iter()
+			.map(|byte| format!("{:02x}", byte))
+			.collect();
+
+		Self {
+			path: path.map(|path| path.display().to_string()),
+			size,
+			sha256,
+		}
+	}
+
+
+	fn hash_file(path: &Path) -> io::Result<Self> {
+		let mut file = fs::File::open(path)?;
+		let mut hasher = Sha256::new();
+		let mut buffer = vec![0; 1 << 20];
+		let mut size = 0;
+
+		loop {
+			let read = file.read(&mut buffer)?;
+
+			if read == 0 {
+				break;
+			}
+
+			hasher.update(&buffer[.. read]);
+			size += read as u64;
+		}
+
+		Ok(Self::new(Some(path), size, hasher))
+	}
+}
+
+
+#[derive(Debug, Serialize)]
+struct Manifest {
+	version: &'static str,
+	/// The git revision of the build.
+	revision: &'static str,
+	arguments: Vec<String>,
+	/// The parsed command.
+	command: String,
+	/// The input file, or none for stdin.
+	input: Option<Contents>,
+	records: Option<Records>,
+	timings: Vec<Timing>,
+	output: Contents,
+}
+
+
+/// Write the manifest of the run of the given command, over the given input file, to the
+/// given path.
+pub fn write(
+	path: &Path,
+	arguments: Vec<String>,
+	command: String,
+	input: Option<&Path>,
+	output: Output,
+) -> anyhow::Result<()> {
+	let manifest = Manifest {
+		version: env!("CARGO_PKG_VERSION"),
+		revision: env!("GIT_REVISION"),
+		arguments,
+		command,
+		input: input.map(Contents::hash_file).transpose()?,
+		records: *RECORDS.lock().expect("records lock should not be poisoned"),
+		timings: TIMINGS.lock().expect("timings lock should not be poisoned").clone(),
+		output: Contents::new(None, output.size, output.hasher),
+	};
+
+	let file = io::BufWriter::new(fs::File::create(path)?);
+
+	serde_json::to_writer_pretty(file, &manifest)?;
+
+	Ok(())
+}
@@ -0,0 +1,555 @@
+use std::{
+	cmp,
+	time,
+};
+
+use bitmatrix::BitMatrix;
+
+use dci::{DataSet, ItemSet as _};
+
+use crate::{
+	data::weight::Weights,
+	dataset::Dataset,
+	itemset::ItemSet,
+};
+
+
+/// Constraints on the mined itemsets. These are pushed into the mining process, instead
+/// of filtering the results.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Constraints {
+	/// Items that must be present in every itemset.
+	pub must_contain: ItemSet,
+	/// Items that must not be present in any itemset.
+	pub must_not_contain: ItemSet,
+	/// The maximum length of the itemsets.
+	pub max_length: Option<usize>,
+}
+
+
+impl Default for Constraints {
+	fn default() -> Self {
+		Self {
+			must_contain: ItemSet::empty(),
+			must_not_contain: ItemSet::empty(),
+			max_length: None,
+		}
+	}
+}
+
+
+impl Constraints {
+	/// Whether there are no constraints at all.
+	pub fn is_empty(&self) -> bool {
+		self.must_contain.is_empty()
+			&& self.must_not_contain.is_empty()
+			&& self.max_length.is_none()
+	}
+}
+
+
+/// Restrict the dataset according to the item constraints. The transactions that do not
+/// contain the required items are dropped, and the forbidden items are cleared, so that
+/// they never become frequent.
+fn project(dataset: &Dataset, constraints: &Constraints) -> Dataset {
+	let cover = dataset.cover(&constraints.must_contain);
+
+	let transactions: Box<[usize]> = cover
+		.iter()
+		.enumerate()
+		.filter_map(
+			|(ix, &covered)| if covered { Some(ix) } else { None }
+		)
+		.collect();
+
+	let mut matrix = BitMatrix::new(dataset.items_count(), transactions.len());
+
+	for item in 0 .. dataset.items_count() {
+		let row = &dataset.row(item);
+
+		for (ix, &transaction) in transactions.iter().enumerate() {
+			if row[transaction] {
+				matrix.set((item, ix), true);
+			}
+		}
+	}
+
+	for item in &constraints.must_not_contain {
+		matrix[item].set_all(false);
+	}
+
+	matrix.into()
+}
+
+
+/// The closed frequent itemsets, with their support.
+pub type Itemsets = Box<[(ItemSet, dci::Support)]>;
+
+
+/// Mine the closed frequent itemsets that satisfy the given constraints, sorted by support
+/// in descending order.
+pub fn mine(
+	dataset: &Dataset,
+	min_sup: dci::Support,
+	constraints: &Constraints,
+) -> Itemsets {
+	let clock = time::Instant::now();
+
+	let mut result = closed(dataset, min_sup, constraints);
+
+	log::info!("Dci took {:.2?}", clock.elapsed());
+
+	result.sort_unstable_by_key(
+		|(_itemset, support)| cmp::Reverse(*support)
+	);
+
+	result
+}
+
+
+/// The closed frequent itemsets, with their weighted support.
+pub type WeightedItemsets = Box<[(ItemSet, f64)]>;
+
+
+/// Mine the closed itemsets whose weighted support is at least `min_sup`, and that satisfy
+/// the given constraints, sorted by weighted support in descending order.
+///
+/// The closure of an itemset depends only on its cover, not on the weights, so the closed
+/// itemsets are mined with the least count of transactions that may reach the weighted
+/// threshold, and then filtered by their weighted support.
+pub fn mine_weighted(
+	dataset: &Dataset,
+	weights: &Weights,
+	min_sup: f64,
+	constraints: &Constraints,
+) -> WeightedItemsets {
+	let clock = time::Instant::now();
+
+	let max_weight = weights.max();
+
+	let min_count = if max_weight > 0.0 {
+		(min_sup / max_weight).floor() as dci::Support
+	}
+	else {
+		0
+	};
+
+	let mut result: Vec<(ItemSet, f64)> = closed(dataset, min_count, constraints)
+		.into_vec()
+		.into_iter()
+		.map(
+			|(itemset, _)| {
+				let support = weights.support(&dataset.cover(&itemset));
+				(itemset, support)
+			}
+		)
+		.filter(|(_, support)| *support >= min_sup)
+		.collect();
+
+	log::info!("Dci took {:.2?}", clock.elapsed());
+
+	result.sort_unstable_by(
+		|(_, a), (_, b)| b.total_cmp(a)
+	);
+
+	result.into()
+}
+
+
+/// Mine the closed frequent itemsets that satisfy the given constraints. The support
+/// threshold is absolute, i.e. relative to the whole dataset.
+pub fn closed(
+	dataset: &Dataset,
+	min_sup: dci::Support,
+	constraints: &Constraints,
+) -> Itemsets {
+	if constraints.is_empty() {
+		return dci::parallel::closed(dataset, min_sup);
+	}
+
+	let dataset = project(dataset, constraints);
+
+	let result = match constraints.max_length {
+		Some(max_length) => bounded::closed(&dataset, min_sup, max_length),
+		None => dci::parallel::closed(&dataset, min_sup),
+	};
+
+	// The first result is always the closure of the empty set, which may be infrequent. If no
+	// transaction contains the required items, it is every item, forbidden ones included.
+	result
+		.into_vec()
+		.into_iter()
+		.filter(
+			|(itemset, support)| *support > 0
+				&& *support >= min_sup
+				&& constraints.must_contain.is_subset(itemset)
+				&& constraints.must_not_contain.is_disjoint(itemset)
+		)
+		.collect()
+}
+
+
+/// A variant of the DCI-Closed algorithm which prunes the search space by the itemsets'
+/// length. As the closed itemsets only grow when descending in the search tree, a branch
+/// can be discarded as soon as its closure exceeds the maximum length.
+mod bounded {
+	use std::sync::mpsc;
+
+	use super::*;
+
+
+	pub fn closed(
+		dataset: &Dataset,
+		min_sup: dci::Support,
+		max_length: usize,
+	) -> Box<[(ItemSet, dci::Support)]> {
+		let transactions_count = dataset.transactions_count();
+
+		let mut closed = ItemSet::empty();
+		let mut post = ItemSet::empty();
+
+		for item in 0 .. dataset.items_count() {
+			let support = dataset.item_support(item);
+
+			if support == transactions_count {
+				closed.add(item);
+			}
+			else if support >= min_sup {
+				post.add(item);
+			}
+		}
+
+		if closed.len() > max_length {
+			return Box::new([]);
+		}
+
+		let (tx, rx) = mpsc::channel();
+
+		tx
+			.send((closed.clone(), transactions_count))
+			.expect("Failed to send result");
+
+		rayon::scope(
+			|scope| expand(
+				dataset,
+				min_sup,
+				max_length,
+				&closed,
+				ItemSet::empty(),
+				&post,
+				&tx,
+				scope,
+			)
+		);
+
+		drop(tx);
+
+		rx.iter().collect()
+	}
+
+
+	#[allow(clippy::too_many_arguments)]
+	fn expand<'a>(
+		dataset: &'a Dataset,
+		min_sup: dci::Support,
+		max_length: usize,
+		closed_set: &ItemSet,
+		mut pre_set: ItemSet,
+		post_set: &ItemSet,
+		out: &mpsc::Sender<(ItemSet, dci::Support)>,
+		scope: &rayon::Scope<'a>,
+	) {
+		for i in post_set {
+			let mut new_gen = closed_set.clone();
+			new_gen.add(i);
+
+			let new_gen_cover = dataset.cover(&new_gen);
+
+			if new_gen_cover.count_ones() < min_sup {
+				continue;
+			}
+
+			let is_dup = pre_set
+				.into_iter()
+				.any(|item| dataset.supports(item, &new_gen_cover));
+
+			if is_dup {
+				continue;
+			}
+
+			let mut closed_set_new = new_gen;
+			let mut post_set_new = ItemSet::empty();
+
+			for j in post_set.into_iter().skip_while(|&j| i >= j) {
+				if dataset.supports(j, &new_gen_cover) {
+					closed_set_new.add(j);
+				}
+				else {
+					post_set_new.add(j);
+				}
+			}
+
+			// Pre set must be updated regardless of the pruning, as the closure is a valid
+			// generator for the duplicate check.
+			let pre_set_new = pre_set.clone();
+			pre_set.add(i);
+
+			if closed_set_new.len() > max_length {
+				continue;
+			}
+
+			out
+				.send((closed_set_new.clone(), new_gen_cover.count_ones()))
+				.expect("Failed to send result");
+
+			let out = out.clone();
+
+			scope.spawn(
+				move |scope| expand(
+					dataset,
+					min_sup,
+					max_length,
+					&closed_set_new,
+					pre_set_new,
+					&post_set_new,
+					&out,
+					scope,
+				)
+			);
+		}
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+
+	const ITEMS: usize = 5;
+
+	const TRANSACTIONS: &[&[usize]] = &[
+		&[0, 1, 2],
+		&[0, 1, 2, 3],
+		&[0, 1, 3],
+		&[0, 2, 4],
+		&[1, 2, 3, 4],
+		&[0, 1, 2, 3, 4],
+		&[2, 3],
+		&[0, 4],
+	];
+
+	/// The weight of each transaction, as exact binary fractions so that the sums don't
+	/// depend on their order.
+	const WEIGHTS: &[f64] = &[0.5, 2.0, 1.25, 0.0, 3.0, 0.75, 1.5, 2.5];
+
+
+	fn itemset(items: &[usize]) -> ItemSet {
+		let mut itemset = ItemSet::empty();
+
+		for &item in items {
+			itemset.add(item);
+		}
+
+		itemset
+	}
+
+
+	fn dataset(transactions: &[&[usize]]) -> Dataset {
+		let mut matrix = BitMatrix::new(ITEMS, transactions.len());
+
+		for (ix, transaction) in transactions.iter().enumerate() {
+			for &item in transaction.iter() {
+				matrix.set((item, ix), true);
+			}
+		}
+
+		matrix.into()
+	}
+
+
+	fn sorted(itemsets: Itemsets) -> Vec<(ItemSet, dci::Support)> {
+		let mut itemsets = itemsets.into_vec();
+		itemsets.sort();
+		itemsets
+	}
+
+
+	/// The closed itemsets that satisfy the constraints, by enumerating every combination of
+	/// items over the projected transactions.
+	fn brute_force(min_sup: dci::Support, constraints: &Constraints) -> Vec<(ItemSet, dci::Support)> {
+		let transactions: Vec<ItemSet> = TRANSACTIONS
+			.iter()
+			.map(|transaction| itemset(transaction))
+			.filter(|transaction| constraints.must_contain.is_subset(transaction))
+			.map(
+				|mut transaction| {
+					transaction.remove_all(&constraints.must_not_contain);
+					transaction
+				}
+			)
+			.collect();
+
+		let mut result = Vec::new();
+
+		for combination in 0_usize .. 1 << ITEMS {
+			let items: Vec<usize> = (0 .. ITEMS).filter(|item| combination & 1 << item != 0).collect();
+			let candidate = itemset(&items);
+
+			let cover: Vec<&ItemSet> = transactions
+				.iter()
+				.filter(|transaction| candidate.is_subset(transaction))
+				.collect();
+
+			let closure = cover
+				.iter()
+				.fold(
+					itemset(&(0 .. ITEMS).collect::<Vec<_>>()),
+					|mut closure, transaction| {
+						let mut absent = closure.clone();
+						absent.remove_all(transaction);
+						closure.remove_all(&absent);
+						closure
+					}
+				);
+
+			let frequent = !cover.is_empty() && cover.len() >= min_sup;
+			let short = constraints.max_length.is_none_or(|max_length| candidate.len() <= max_length);
+
+			if closure == candidate && frequent && short && constraints.must_contain.is_subset(&candidate) {
+				result.push((candidate, cover.len()));
+			}
+		}
+
+		result.sort();
+		result
+	}
+
+
+	#[test]
+	fn bounded_matches_filtered_dci() {
+		let dataset = dataset(TRANSACTIONS);
+
+		for min_sup in 0 ..= TRANSACTIONS.len() {
+			for max_length in 0 ..= ITEMS {
+				let expected: Vec<_> = sorted(dci::sequential::closed(&dataset, min_sup))
+					.into_iter()
+					.filter(|(itemset, _)| itemset.len() <= max_length)
+					.collect();
+
+				assert_eq!(
+					sorted(bounded::closed(&dataset, min_sup, max_length)),
+					expected,
+					"min_sup {}, max_length {}",
+					min_sup,
+					max_length,
+				);
+			}
+		}
+	}
+
+
+	#[test]
+	fn constraints_match_brute_force() {
+		let dataset = dataset(TRANSACTIONS);
+
+		let constraints = [
+			(&[][..], &[][..], Some(2)),
+			(&[0], &[], None),
+			(&[0], &[4], None),
+			(&[1, 2], &[0], Some(3)),
+			(&[], &[2, 3], None),
+			(&[3, 4], &[], Some(2)),
+		];
+
+		for (must_contain, must_not_contain, max_length) in constraints.iter() {
+			let constraints = Constraints {
+				must_contain: itemset(must_contain),
+				must_not_contain: itemset(must_not_contain),
+				max_length: *max_length,
+			};
+
+			for min_sup in 0 ..= TRANSACTIONS.len() {
+				assert_eq!(
+					sorted(closed(&dataset, min_sup, &constraints)),
+					brute_force(min_sup, &constraints),
+					"min_sup {}, {:?}",
+					min_sup,
+					constraints,
+				);
+			}
+		}
+	}
+
+
+	#[test]
+	fn required_items_never_together() {
+		// Only the fifth transaction contains both 1 and 4, and none contains 0 as well.
+		let dataset = dataset(&TRANSACTIONS[.. 5]);
+
+		let constraints = Constraints {
+			must_contain: itemset(&[1, 4]),
+			must_not_contain: itemset(&[3]),
+			max_length: None,
+		};
+
+		assert_eq!(sorted(closed(&dataset, 0, &constraints)), vec![(itemset(&[1, 2, 4]), 1)]);
+
+		let constraints = Constraints {
+			must_contain: itemset(&[0, 1, 4]),
+			..constraints
+		};
+
+		assert!(closed(&dataset, 0, &constraints).is_empty());
+	}
+
+
+	#[test]
+	fn weighted_matches_brute_force() {
+		let dataset = dataset(TRANSACTIONS);
+		let weights = Weights::from(WEIGHTS.to_vec());
+
+		let constraints = [
+			Constraints::default(),
+			Constraints {
+				must_contain: itemset(&[0]),
+				must_not_contain: itemset(&[4]),
+				max_length: Some(3),
+			},
+		];
+
+		for constraints in constraints.iter() {
+			// The closure doesn't depend on the weights, so the weighted itemsets are the
+			// closed ones whose weighted support reaches the threshold.
+			let closed = brute_force(0, constraints);
+
+			for step in 0 ..= 48 {
+				let min_sup = step as f64 * 0.25;
+
+				let mut expected: Vec<(ItemSet, f64)> = closed
+					.iter()
+					.map(
+						|(candidate, _)| {
+							let support = TRANSACTIONS
+								.iter()
+								.zip(WEIGHTS)
+								.filter(|(transaction, _)| candidate.is_subset(&itemset(transaction)))
+								.map(|(_, weight)| weight)
+								.sum();
+
+							(candidate.clone(), support)
+						}
+					)
+					.filter(|(_, support)| *support >= min_sup)
+					.collect();
+
+				expected.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+				let mut result = mine_weighted(&dataset, &weights, min_sup, constraints).into_vec();
+				result.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+				assert_eq!(result, expected, "min_sup {}, {:?}", min_sup, constraints);
+			}
+		}
+	}
+}
@@ -0,0 +1,329 @@
+//! An interactive session, which keeps the records and their encoded matrix in memory
+//! between commands, so that the dataset is read only once.
+
+use std::{
+	borrow::Cow,
+	fs,
+	io::{self, Write},
+	path::{Path, PathBuf},
+	time,
+};
+
+use dci::{DataSet, ItemSet as _};
+
+use rustyline::error::ReadlineError;
+
+use icpsr_36404_analysis::{
+	dataset::{self, Dataset},
+	export,
+	mining::{self, Constraints},
+	rules,
+	table::Table,
+	Distribution,
+	ItemSet,
+	Itemsets,
+	Record,
+};
+
+
+const HELP: &str = "\
+Commands:
+	filter [items]     keep only the records with the given items, or all the records if none
+	distribution       display the distribution of the records
+	mine <min_sup>     mine the closed frequent itemsets with the given minimum support ratio
+	rules <min_conf>   generate the rules with the given minimum confidence from the mined itemsets
+	query <items>      compute the support of the given itemset
+	explain <items>    explain the support of the given itemset by the supports of its items
+	export <path>      write the records to <path>.parquet and <path>.arrow
+	help               display this message
+	quit               end the session
+Items are given by label (e.g. Race::Black) or by field and value (e.g. race=Black).";
+
+
+/// The history file, in the home directory.
+const HISTORY: &str = ".icpsr-36404-analysis_history";
+
+
+struct Session {
+	/// The loaded records.
+	records: Vec<Record>,
+	/// The items of the filter, which the current records contain.
+	filter: ItemSet,
+	/// The indices of the records that pass the filter, or none if all of them pass.
+	selected: Option<Vec<usize>>,
+	/// The encoded matrix of the records that pass the filter.
+	dataset: Dataset,
+	/// The itemsets of the last mining, if not filtered since.
+	itemsets: Option<Itemsets>,
+}
+
+
+impl Session {
+	fn new(records: Vec<Record>) -> Self {
+		let dataset = dataset::encode(&records);
+
+		Self {
+			records,
+			selected: None,
+			filter: ItemSet::empty(),
+			dataset,
+			itemsets: None,
+		}
+	}
+
+
+	/// Run a command line, returning whether the session should continue.
+	fn execute(&mut self, line: &str) -> anyhow::Result<bool> {
+		let line = line.trim();
+
+		let (command, args) = match line.split_once(char::is_whitespace) {
+			Some((command, args)) => (command, args.trim()),
+			None => (line, ""),
+		};
+
+		match command {
+			"" => (),
+			"filter" => self.filter(ItemSet::parse(args)?),
+			"distribution" => self.distribution()?,
+			"mine" => self.mine(parse_ratio(args, "minimum support")?)?,
+			"rules" => self.rules(parse_ratio(args, "minimum confidence")?)?,
+			"query" => crate::run_query(io::stdout().lock(), &self.dataset, &ItemSet::parse(args)?, None, None)?,
+			"explain" => self.explain(&ItemSet::parse(args)?),
+			"export" => self.export(args)?,
+			"help" => println!("{}", HELP),
+			"quit" | "exit" => return Ok(false),
+			_ => anyhow::bail!("unknown command: {} (try help)", command),
+		}
+
+		Ok(true)
+	}
+
+
+	fn filter(&mut self, filter: ItemSet) {
+		let clock = time::Instant::now();
+
+		self.selected = if filter.is_empty() {
+			None
+		}
+		else {
+			let selected = self.records
+				.iter()
+				.enumerate()
+				.filter(|(_, record)| filter.is_subset(&ItemSet::from(*record)))
+				.map(|(index, _)| index)
+				.collect();
+
+			Some(selected)
+		};
+
+		log::info!("Filtering took {:.2?}", clock.elapsed());
+
+		let selected = self.selected();
+		let dataset = dataset::encode(&selected);
+
+		println!("Filter: {}", filter);
+		println!("Records: {} of {}", selected.len(), self.records.len());
+
+		self.dataset = dataset;
+		self.filter = filter;
+		self.itemsets = None;
+	}
+
+
+	/// The records that pass the filter.
+	fn selected(&self) -> Cow<'_, [Record]> {
+		match &self.selected {
+			Some(selected) => Cow::Owned(selected.iter().map(|&index| self.records[index]).collect()),
+			None => Cow::Borrowed(&self.records),
+		}
+	}
+
+
+	fn distribution(&self) -> io::Result<()> {
+		let mut distribution = Distribution::new();
+
+		for record in self.selected().iter() {
+			distribution.insert(record);
+		}
+
+		Table::default().distribution(io::stdout().lock(), &distribution)
+	}
+
+
+	fn mine(&mut self, min_sup_ratio: f64) -> anyhow::Result<()> {
+		let transactions = self.dataset.transactions_count();
+
+		let min_sup = (transactions as f64 * min_sup_ratio) as usize;
+
+		if min_sup == 0 {
+			anyhow::bail!("invalid minimum support: {} (less than one transaction)", min_sup_ratio);
+		}
+
+		println!("Transactions: {}", transactions);
+		println!("minsup: {} ({:.1}%)", min_sup, 100.0 * min_sup_ratio);
+
+		let result = mining::mine(&self.dataset, min_sup, &Constraints::default());
+
+		Table::default().itemsets(io::stdout().lock(), &result, &self.dataset, None)?;
+
+		self.itemsets = Some(result);
+
+		Ok(())
+	}
+
+
+	fn rules(&self, min_confidence: f64) -> anyhow::Result<()> {
+		let itemsets = self.itemsets
+			.as_ref()
+			.ok_or_else(|| anyhow::anyhow!("no mined itemsets, run mine first"))?;
+
+		let rules = rules::generate(&self.dataset, itemsets, min_confidence);
+
+		let mut stdout = io::BufWriter::new(io::stdout().lock());
+
+		writeln!(stdout, "Rules ({}):", rules.len())?;
+
+		for rule in rules.iter() {
+			writeln!(stdout, "{}", rule)?;
+		}
+
+		stdout.flush()?;
+
+		Ok(())
+	}
+
+
+	/// Display the support of the itemset against the support expected if its items were
+	/// independent, its closure, and the support of each item.
+	fn explain(&self, itemset: &ItemSet) {
+		let transactions = self.dataset.transactions_count().max(1) as f64;
+
+		let cover = self.dataset.cover(itemset);
+		let support = cover.count_ones();
+
+		let expected = itemset
+			.into_iter()
+			.map(|item| self.dataset.item_support(item) as f64 / transactions)
+			.product::<f64>()
+			* transactions;
+
+		let mut closure = ItemSet::empty();
+
+		if support > 0 {
+			for item in 0 .. self.dataset.items_count() {
+				if self.dataset.supports(item, &cover) {
+					closure.add(item);
+				}
+			}
+		}
+
+		println!("Itemset: {}", itemset);
+		println!("Support: {} ({:.1}%)", support, support as f64 * 100.0 / transactions);
+		println!("Expected if independent: {:.1} ({:.1}%)", expected, expected * 100.0 / transactions);
+		println!("Lift: {:.2}", support as f64 / expected);
+		println!("Closure: {}", closure);
+
+		if let Some(itemsets) = &self.itemsets {
+			let mined = itemsets
+				.iter()
+				.any(|(mined, _)| *mined == closure);
+
+			println!("Closure mined: {}", if mined { "yes" } else { "no" });
+		}
+
+		println!("Items:");
+
+		for item in itemset {
+			let item_support = self.dataset.item_support(item);
+
+			println!(
+				"	{} ({:.1}%): {}",
+				item_support,
+				item_support as f64 * 100.0 / transactions,
+				ItemSet::label(item),
+			);
+		}
+	}
+
+
+	fn export(&self, path: &str) -> anyhow::Result<()> {
+		if path.is_empty() {
+			anyhow::bail!("missing output path");
+		}
+
+		let path = Path::new(path);
+
+		let clock = time::Instant::now();
+
+		let selected = self.selected();
+
+		let parquet = fs::File::create(crate::with_extension(path, "parquet"))?;
+		export::write_parquet(io::BufWriter::new(parquet), &selected)?;
+
+		let arrow = fs::File::create(crate::with_extension(path, "arrow"))?;
+		export::write_ipc(io::BufWriter::new(arrow), &selected)?;
+
+		log::info!("Exporting records took {:.2?}", clock.elapsed());
+
+		Ok(())
+	}
+}
+
+
+fn parse_ratio(arg: &str, name: &str) -> anyhow::Result<f64> {
+	let ratio: f64 = arg
+		.parse()
+		.map_err(|_| anyhow::anyhow!("invalid {}: {:?}", name, arg))?;
+
+	if (0.0 ..= 1.0).contains(&ratio) {
+		Ok(ratio)
+	}
+	else {
+		Err(anyhow::anyhow!("invalid {}: {}", name, ratio))
+	}
+}
+
+
+/// Run an interactive session over the given records, with the command history saved in the
+/// home directory.
+pub fn run(records: Vec<Record>) -> anyhow::Result<()> {
+	let mut session = Session::new(records);
+
+	let mut editor = rustyline::DefaultEditor::new()?;
+
+	let history = std::env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY));
+
+	if let Some(history) = &history {
+		// The history file does not exist on the first session.
+		let _ = editor.load_history(history);
+	}
+
+	println!("Records: {} (type help for the commands)", session.records.len());
+
+	loop {
+		let line = match editor.readline("> ") {
+			Ok(line) => line,
+			Err(ReadlineError::Interrupted) => continue,
+			Err(ReadlineError::Eof) => break,
+			Err(error) => return Err(error.into()),
+		};
+
+		if !line.trim().is_empty() {
+			editor.add_history_entry(line.as_str())?;
+		}
+
+		match session.execute(&line) {
+			Ok(true) => (),
+			Ok(false) => break,
+			Err(error) => log::error!("{}", error),
+		}
+	}
+
+	if let Some(history) = &history {
+		if let Err(error) = editor.save_history(history) {
+			log::warn!("failed to save the history: {}", error);
+		}
+	}
+
+	Ok(())
+}
@@ -0,0 +1,354 @@
+//! A static HTML report of an analysis: its configuration, the distribution of the records,
+//! the top itemsets and rules in sortable tables, and histograms of their supports. The
+//! report is a single file, with the styles and scripts inline, so that it can be sent or
+//! archived as is.
+
+use std::io::{self, Write};
+
+use dci::DataSet;
+
+use crate::{
+	dataset::Dataset,
+	itemset::ItemSet,
+	labels,
+	rules::Rule,
+	Distribution,
+};
+
+
+/// The number of bins of the histograms.
+const BINS: usize = 20;
+
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em auto; max-width: 60em; color: #222; }
+h2 { border-bottom: 1px solid #ccc; padding-bottom: 0.2em; margin-top: 2em; }
+table { border-collapse: collapse; margin: 1em 0; }
+th, td { padding: 0.2em 0.6em; text-align: left; }
+td.number { text-align: right; font-variant-numeric: tabular-nums; }
+table.sortable th { cursor: pointer; background: #eee; }
+table.sortable tr:nth-child(even) { background: #f7f7f7; }
+.attribute { display: inline-block; vertical-align: top; margin: 0 2em 1em 0; }
+.bar { background: #4a7ab5; height: 0.9em; }
+td.chart { width: 12em; }
+svg rect { fill: #4a7ab5; }
+svg text { font-size: 11px; }
+";
+
+
+const SCRIPT: &str = "
+document.querySelectorAll('table.sortable').forEach(function (table) {
+	table.querySelectorAll('th').forEach(function (header, column) {
+		header.addEventListener('click', function () {
+			var body = table.tBodies[0];
+			var descending = header.dataset.order !== 'descending';
+			header.dataset.order = descending ? 'descending' : 'ascending';
+			var key = function (row) {
+				var cell = row.cells[column];
+				return cell.dataset.value !== undefined ? parseFloat(cell.dataset.value) : cell.textContent;
+			};
+			Array.from(body.rows)
+				.sort(function (a, b) {
+					var x = key(a), y = key(b);
+					var order = x < y ? -1 : (x > y ? 1 : 0);
+					return descending ? -order : order;
+				})
+				.forEach(function (row) { body.appendChild(row); });
+		});
+	});
+});
+";
+
+
+/// The contents of a report.
+pub struct Report<'a> {
+	pub title: &'a str,
+	/// The labeled settings of the analysis, e.g. the input and filters.
+	pub configuration: &'a [(&'a str, String)],
+	pub distribution: &'a Distribution,
+	pub dataset: &'a Dataset,
+	/// The mined itemsets, sorted by support.
+	pub itemsets: &'a [(ItemSet, dci::Support)],
+	/// The rules, sorted by confidence.
+	pub rules: &'a [Rule],
+	/// The maximum number of rows of the itemset and rule tables.
+	pub limit: usize,
+}
+
+
+/// Escape the given text for HTML.
+fn escape(text: &str) -> String {
+	let mut escaped = String::with_capacity(text.len());
+
+	for c in text.chars() {
+		match c {
+			'&' => escaped.push_str("&amp;"),
+			'<' => escaped.push_str("&lt;"),
+			'>' => escaped.push_str("&gt;"),
+			'"' => escaped.push_str("&quot;"),
+			'\'' => escaped.push_str("&#39;"),
+			c => escaped.push(c),
+		}
+	}
+
+	escaped
+}
+
+
+/// The localized names of the items of the itemset.
+fn items(itemset: &ItemSet) -> String {
+	let labels: Vec<_> = itemset
+		.into_iter()
+		.map(labels::item)
+		.collect();
+
+	escape(&labels.join(", "))
+}
+
+
+impl Report<'_> {
+	pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+		writeln!(writer, "<!DOCTYPE html>")?;
+		writeln!(writer, "<html>")?;
+		writeln!(writer, "<head>")?;
+		writeln!(writer, "<meta charset=\"utf-8\">")?;
+		writeln!(writer, "<title>{}</title>", escape(self.title))?;
+		writeln!(writer, "<style>{}</style>", STYLE)?;
+		writeln!(writer, "</head>")?;
+		writeln!(writer, "<body>")?;
+		writeln!(writer, "<h1>{}</h1>", escape(self.title))?;
+
+		self.write_configuration(&mut writer)?;
+		self.write_distribution(&mut writer)?;
+		self.write_itemsets(&mut writer)?;
+		self.write_rules(&mut writer)?;
+
+		writeln!(writer, "<script>{}</script>", SCRIPT)?;
+		writeln!(writer, "</body>")?;
+		writeln!(writer, "</html>")?;
+
+		writer.flush()
+	}
+
+
+	fn write_configuration<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+		writeln!(writer, "<h2>{}</h2>", escape(labels::header("configuration", "Configuration")))?;
+		writeln!(writer, "<table>")?;
+
+		for (name, value) in self.configuration {
+			writeln!(writer, "<tr><th>{}</th><td>{}</td></tr>", escape(name), escape(value))?;
+		}
+
+		writeln!(writer, "</table>")
+	}
+
+
+	/// A bar chart of the counts of the values of each attribute.
+	fn write_distribution<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+		let total = self.distribution.total_records.max(1) as f64;
+
+		writeln!(writer, "<h2>{}</h2>", escape(labels::header("distribution", "Distribution")))?;
+		writeln!(
+			writer,
+			"<p>{}: {}</p>",
+			escape(labels::header("records", "Records")),
+			self.distribution.total_records,
+		)?;
+
+		for (attribute, values) in self.distribution.attributes().iter() {
+			let max = values
+				.iter()
+				.map(|&(_, count)| count)
+				.max()
+				.unwrap_or(0)
+				.max(1) as f64;
+
+			writeln!(writer, "<div class=\"attribute\">")?;
+			writeln!(writer, "<h3>{}</h3>", escape(labels::attribute(attribute)))?;
+			writeln!(writer, "<table>")?;
+
+			for (label, count) in values.iter() {
+				writeln!(
+					writer,
+					"<tr><td>{}</td><td class=\"chart\"><div class=\"bar\" style=\"width: {:.1}%\"></div></td><td class=\"number\">{}</td><td class=\"number\">{:.1}%</td></tr>",
+					escape(labels::value(label)),
+					*count as f64 * 100.0 / max,
+					count,
+					*count as f64 * 100.0 / total,
+				)?;
+			}
+
+			writeln!(writer, "</table>")?;
+			writeln!(writer, "</div>")?;
+		}
+
+		Ok(())
+	}
+
+
+	fn write_itemsets<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+		let transactions = self.dataset.transactions_count().max(1) as f64;
+
+		writeln!(
+			writer,
+			"<h2>{} ({})</h2>",
+			escape(labels::header("itemsets", "Itemsets")),
+			self.itemsets.len(),
+		)?;
+
+		let supports: Vec<f64> = self.itemsets
+			.iter()
+			.map(|(_, support)| *support as f64 / transactions)
+			.collect();
+
+		write_histogram(writer, &supports)?;
+
+		writeln!(writer, "<table class=\"sortable\">")?;
+		writeln!(
+			writer,
+			"<thead><tr><th>{}</th><th>{}</th><th>%</th><th>{}</th></tr></thead>",
+			escape(labels::header("itemset", "Itemset")),
+			escape(labels::header("support", "Support")),
+			escape(labels::header("lift", "Lift")),
+		)?;
+		writeln!(writer, "<tbody>")?;
+
+		for (itemset, support) in self.itemsets.iter().take(self.limit) {
+			let ratio = *support as f64 / transactions;
+
+			let expected: f64 = itemset
+				.into_iter()
+				.map(|item| self.dataset.item_support(item) as f64 / transactions)
+				.product();
+
+			writeln!(
+				writer,
+				"<tr><td>{{{}}}</td><td class=\"number\" data-value=\"{}\">{}</td><td class=\"number\" data-value=\"{}\">{:.1}%</td><td class=\"number\" data-value=\"{}\">{:.2}</td></tr>",
+				items(itemset),
+				support,
+				support,
+				ratio,
+				ratio * 100.0,
+				ratio / expected,
+				ratio / expected,
+			)?;
+		}
+
+		writeln!(writer, "</tbody>")?;
+		writeln!(writer, "</table>")
+	}
+
+
+	fn write_rules<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+		let transactions = self.dataset.transactions_count().max(1) as f64;
+
+		writeln!(
+			writer,
+			"<h2>{} ({})</h2>",
+			escape(labels::header("rules", "Rules")),
+			self.rules.len(),
+		)?;
+
+		let supports: Vec<f64> = self.rules
+			.iter()
+			.map(|rule| rule.support as f64 / transactions)
+			.collect();
+
+		write_histogram(writer, &supports)?;
+
+		writeln!(writer, "<table class=\"sortable\">")?;
+		writeln!(
+			writer,
+			"<thead><tr><th>{}</th><th>{}</th><th>{}</th><th>{}</th><th>{}</th></tr></thead>",
+			escape(labels::header("antecedent", "Antecedent")),
+			escape(labels::header("consequent", "Consequent")),
+			escape(labels::header("support", "Support")),
+			escape(labels::header("confidence", "Confidence")),
+			escape(labels::header("lift", "Lift")),
+		)?;
+		writeln!(writer, "<tbody>")?;
+
+		for rule in self.rules.iter().take(self.limit) {
+			writeln!(
+				writer,
+				"<tr><td>{{{}}}</td><td>{{{}}}</td><td class=\"number\" data-value=\"{}\">{}</td><td class=\"number\" data-value=\"{}\">{:.1}%</td><td class=\"number\" data-value=\"{}\">{:.2}</td></tr>",
+				items(&rule.antecedent),
+				items(&rule.consequent),
+				rule.support,
+				rule.support,
+				rule.confidence,
+				rule.confidence * 100.0,
+				rule.lift,
+				rule.lift,
+			)?;
+		}
+
+		writeln!(writer, "</tbody>")?;
+		writeln!(writer, "</table>")
+	}
+}
+
+
+/// Write a histogram of the given relative supports, as an inline SVG.
+fn write_histogram<W: Write>(writer: &mut W, supports: &[f64]) -> io::Result<()> {
+	const WIDTH: f64 = 600.0;
+	const HEIGHT: f64 = 150.0;
+	const MARGIN: f64 = 20.0;
+
+	if supports.is_empty() {
+		return Ok(());
+	}
+
+	let min = supports.iter().copied().fold(f64::INFINITY, f64::min);
+	let max = supports.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+	let width = (max - min) / BINS as f64;
+
+	let mut counts = [0_usize; BINS];
+
+	for &support in supports {
+		let bin = if width > 0.0 { ((support - min) / width) as usize } else { 0 };
+		counts[bin.min(BINS - 1)] += 1;
+	}
+
+	let highest = counts.iter().copied().max().unwrap_or(0).max(1) as f64;
+	let bar_width = WIDTH / BINS as f64;
+
+	writeln!(
+		writer,
+		"<p>{}</p>",
+		escape(labels::header("support_histogram", "Support histogram")),
+	)?;
+	writeln!(
+		writer,
+		"<svg width=\"{}\" height=\"{}\" xmlns=\"http://www.w3.org/2000/svg\">",
+		WIDTH,
+		HEIGHT + MARGIN,
+	)?;
+
+	for (bin, &count) in counts.iter().enumerate() {
+		let height = count as f64 / highest * HEIGHT;
+		let low = min + bin as f64 * width;
+
+		writeln!(
+			writer,
+			"<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\"><title>{:.1}% – {:.1}%: {}</title></rect>",
+			bin as f64 * bar_width + 1.0,
+			HEIGHT - height,
+			bar_width - 2.0,
+			height,
+			low * 100.0,
+			(low + width) * 100.0,
+			count,
+		)?;
+	}
+
+	writeln!(writer, "<text x=\"0\" y=\"{}\">{:.1}%</text>", HEIGHT + MARGIN - 5.0, min * 100.0)?;
+	writeln!(
+		writer,
+		"<text x=\"{}\" y=\"{}\" text-anchor=\"end\">{:.1}%</text>",
+		WIDTH,
+		HEIGHT + MARGIN - 5.0,
+		max * 100.0,
+	)?;
+	writeln!(writer, "</svg>")
+}
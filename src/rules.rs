@@ -0,0 +1,101 @@
+//! Association rules between the closed frequent itemsets. A rule `X -> Z \ X` is generated
+//! for each pair of mined closed itemsets `X ⊂ Z`. As both are closed, the supports are those
+//! of the mining result, and the rules with closed antecedents are a lossless summary of all
+//! the rules.
+
+use std::{
+	cmp,
+	fmt,
+	time,
+};
+
+use dci::DataSet;
+
+use crate::{
+	dataset::Dataset,
+	itemset::ItemSet,
+};
+
+
+/// An association rule: `antecedent -> consequent`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+	pub antecedent: ItemSet,
+	pub consequent: ItemSet,
+	/// The support of the union of the antecedent and the consequent.
+	pub support: dci::Support,
+	pub confidence: f64,
+	/// The confidence over the relative support of the consequent.
+	pub lift: f64,
+}
+
+
+impl fmt::Display for Rule {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"{} -> {} (support {}, confidence {:.1}%, lift {:.2})",
+			self.antecedent,
+			self.consequent,
+			self.support,
+			100.0 * self.confidence,
+			self.lift,
+		)
+	}
+}
+
+
+/// Generate the rules with at least the given confidence from the closed itemsets mined from
+/// the dataset, sorted by confidence, support and antecedent length.
+pub fn generate(
+	dataset: &Dataset,
+	itemsets: &[(ItemSet, dci::Support)],
+	min_confidence: f64,
+) -> Box<[Rule]> {
+	let clock = time::Instant::now();
+
+	let transactions = dataset.transactions_count().max(1) as f64;
+
+	let mut rules = Vec::new();
+
+	for (itemset, support) in itemsets {
+		for (antecedent, antecedent_support) in itemsets {
+			if antecedent.is_empty() || antecedent == itemset || !antecedent.is_subset(itemset) {
+				continue;
+			}
+
+			let confidence = *support as f64 / *antecedent_support as f64;
+
+			if confidence < min_confidence {
+				continue;
+			}
+
+			let mut consequent = itemset.clone();
+			consequent.remove_all(antecedent);
+
+			let consequent_ratio = dataset.support(&consequent) as f64 / transactions;
+
+			rules.push(
+				Rule {
+					antecedent: antecedent.clone(),
+					consequent,
+					support: *support,
+					confidence,
+					lift: confidence / consequent_ratio,
+				}
+			);
+		}
+	}
+
+	rules.sort_by(
+		|a, b| b.confidence
+			.partial_cmp(&a.confidence)
+			.unwrap_or(cmp::Ordering::Equal)
+			.then(b.support.cmp(&a.support))
+			.then(a.antecedent.len().cmp(&b.antecedent.len()))
+	);
+
+	log::info!("Generating rules took {:.2?}", clock.elapsed());
+
+	rules.into_boxed_slice()
+}
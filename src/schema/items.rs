@@ -0,0 +1,115 @@
+use std::hash::{Hash, Hasher};
+
+use bitvec::{
+	order::Lsb0,
+	vec::BitVec,
+};
+
+
+/// A growable itemset, for datasets whose number of items is only known at runtime. The
+/// length of the bit vector is irrelevant: itemsets are compared and hashed by their items.
+#[derive(Debug, Clone, Default)]
+pub struct Items(BitVec<Lsb0, usize>);
+
+
+impl Items {
+	/// How many items in the itemset.
+	pub fn len(&self) -> usize {
+		self.0.count_ones()
+	}
+
+
+	pub fn is_empty(&self) -> bool {
+		self.0.not_any()
+	}
+}
+
+
+impl PartialEq for Items {
+	fn eq(&self, other: &Self) -> bool {
+		self.into_iter().eq(other)
+	}
+}
+
+
+impl Eq for Items {}
+
+
+impl Hash for Items {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		for item in self {
+			item.hash(state);
+		}
+	}
+}
+
+
+impl<'a> IntoIterator for &'a Items {
+	type Item = usize;
+
+	type IntoIter = std::iter::FilterMap<
+		std::iter::Enumerate<
+			bitvec::slice::Iter<'a, Lsb0, usize>
+		>,
+		fn((usize, &bool)) -> Option<usize>
+	>;
+
+	fn into_iter(self) -> Self::IntoIter {
+		self.0
+			.iter()
+			.enumerate()
+			.filter_map(
+				|(ix, item)| if *item { Some(ix) } else { None }
+			)
+	}
+}
+
+
+impl dci::ItemSet for Items {
+	fn empty() -> Self {
+		Self(BitVec::new())
+	}
+
+	fn add(&mut self, item: usize) {
+		if item >= self.0.len() {
+			self.0.resize(item + 1, false);
+		}
+
+		self.0.set(item, true);
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use std::collections::hash_map::DefaultHasher;
+
+	use dci::ItemSet;
+
+	use super::*;
+
+
+	fn hash(items: &Items) -> u64 {
+		let mut hasher = DefaultHasher::new();
+		items.hash(&mut hasher);
+		hasher.finish()
+	}
+
+
+	#[test]
+	fn equality_ignores_length() {
+		let mut short = Items::empty();
+		short.add(3);
+
+		let mut long = Items::empty();
+		long.add(3);
+		long.0.resize(200, false);
+
+		assert_eq!(short, long);
+		assert_eq!(hash(&short), hash(&long));
+
+		long.add(150);
+
+		assert_ne!(short, long);
+	}
+}
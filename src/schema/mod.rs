@@ -0,0 +1,537 @@
+//! A data-driven description of an NCRP study, for releases other than ICPSR 36404. The
+//! schema describes the columns of the delimited dataset, the label of each code, the
+//! missing codes, and which columns are encoded as items. The typed [`Record`] remains the
+//! built-in schema for ICPSR 36404.
+//!
+//! [`Record`]: ../data/struct.Record.html
+
+mod items;
+
+pub use items::Items;
+
+use std::{
+	borrow::Cow,
+	cmp,
+	collections::{HashMap, HashSet},
+	fs,
+	io,
+	path::Path,
+	time,
+};
+
+use bitmatrix::BitMatrix;
+
+use serde::{Serialize, Deserialize};
+
+use crate::{
+	data::{distribution::Attribute, package},
+	labels,
+	util,
+};
+
+
+/// The transactions of a schema-described dataset.
+pub type Dataset = dci::Matrix<Items>;
+
+
+/// A column of the delimited dataset.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Column {
+	/// The column name, as in the dataset header, e.g. `SEX`.
+	pub name: String,
+	/// The attribute name, used as the prefix of the item labels. Defaults to the column name.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub attribute: Option<String>,
+	/// Whether the column is encoded as items. Item columns must have codes.
+	#[serde(default)]
+	pub item: bool,
+	/// The code and label of each value, in the codebook order. Columns without codes, e.g.
+	/// years, are counted by their raw values.
+	#[serde(default)]
+	pub codes: Vec<(String, String)>,
+	/// The codes for missing values.
+	#[serde(default)]
+	pub missing: Vec<String>,
+}
+
+
+impl Column {
+	pub fn attribute(&self) -> &str {
+		self.attribute
+			.as_deref()
+			.unwrap_or(&self.name)
+	}
+}
+
+
+/// An item, i.e. a code of an item column.
+#[derive(Debug, Clone)]
+struct Item {
+	column: usize,
+	/// The item label, e.g. `Sex::Male`.
+	label: String,
+}
+
+
+/// The description of a study.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Schema {
+	/// The study name, e.g. `ICPSR 37021`.
+	pub name: String,
+	pub columns: Box<[Column]>,
+	#[serde(skip)]
+	items: Box<[Item]>,
+}
+
+
+impl Schema {
+	/// Validate the columns and build the schema.
+	pub fn new(name: String, columns: Box<[Column]>) -> anyhow::Result<Self> {
+		let mut names = HashSet::new();
+
+		for column in columns.iter() {
+			if !names.insert(&column.name) {
+				anyhow::bail!("duplicate column in schema: {}", column.name);
+			}
+
+			let mut codes = HashSet::new();
+
+			for code in column.codes.iter().map(|(code, _)| code).chain(&column.missing) {
+				if !codes.insert(code) {
+					anyhow::bail!("duplicate code in column {}: {:?}", column.name, code);
+				}
+			}
+
+			if column.item && column.codes.is_empty() {
+				anyhow::bail!("item column without codes: {}", column.name);
+			}
+		}
+
+		let items = columns
+			.iter()
+			.enumerate()
+			.filter(|(_, column)| column.item)
+			.flat_map(
+				|(ix, column)| column.codes
+					.iter()
+					.map(
+						move |(_, label)| Item {
+							column: ix,
+							label: format!("{}::{}", column.attribute(), label),
+						}
+					)
+			)
+			.collect();
+
+		Ok(
+			Self {
+				name,
+				columns,
+				items,
+			}
+		)
+	}
+
+
+	/// Parse a schema in TOML format.
+	pub fn parse(contents: &str) -> anyhow::Result<Self> {
+		let schema: Schema = toml::from_str(contents)?;
+
+		Self::new(schema.name, schema.columns)
+	}
+
+
+	/// The schema in TOML format.
+	pub fn to_toml(&self) -> anyhow::Result<String> {
+		Ok(toml::to_string(self)?)
+	}
+
+
+	/// Load a schema from a TOML file.
+	pub fn load(path: &Path) -> anyhow::Result<Self> {
+		let contents = fs::read_to_string(path)?;
+
+		Self::parse(&contents)
+			.map_err(
+				|error| anyhow::anyhow!("invalid schema {}: {}", path.display(), error)
+			)
+	}
+
+
+	/// The number of items.
+	pub fn items_count(&self) -> usize {
+		self.items.len()
+	}
+
+
+	/// The raw label of the given item, e.g. `Sex::Male`.
+	pub fn label(&self, item: usize) -> &str {
+		&self.items[item].label
+	}
+
+
+	/// The item with the given raw label.
+	pub fn item(&self, label: &str) -> Option<usize> {
+		self.items
+			.iter()
+			.position(|item| item.label == label)
+	}
+
+
+	/// The name of the given item, according to the installed label catalog.
+	pub fn item_name(&self, item: usize) -> Cow<'_, str> {
+		let column = &self.columns[self.items[item].column];
+
+		labels::labeled(column.attribute(), self.label(item))
+	}
+
+
+	/// The given itemset, formatted as `{a, b}`.
+	pub fn itemset(&self, itemset: &Items) -> String {
+		let items: Vec<_> = itemset
+			.into_iter()
+			.map(|item| self.item_name(item))
+			.collect();
+
+		format!("{{{}}}", items.join(", "))
+	}
+
+
+	/// Read the transactions from a delimited (tsv) dataset, including the header. Columns
+	/// are matched by name, so their order is irrelevant. Records that have missing values in
+	/// any of the item columns are discarded. Invalid records are logged and discarded.
+	///
+	/// Returns the transactions and their distribution.
+	pub fn read<R: io::BufRead>(&self, reader: R) -> anyhow::Result<(Dataset, Distribution)> {
+		let clock = time::Instant::now();
+
+		let mut line_splitter = util::RawLineSplitter::new(reader);
+
+		let mut transactions = {
+			let header = line_splitter
+				.read_line()?
+				.ok_or_else(|| anyhow::anyhow!("empty dataset"))?;
+
+			let header: Vec<&[u8]> = header
+				.split(|&c| c == b'\t')
+				.map(|name| name.strip_suffix(b"\r").unwrap_or(name))
+				.collect();
+
+			Transactions::new(self, &header)?
+		};
+
+		let mut line_count: usize = 0;
+
+		while let Some(line) = line_splitter.read_line()? {
+			line_count += 1;
+
+			let line = line.strip_suffix(b"\r").unwrap_or(line);
+			let row: Vec<&[u8]> = line.split(|&c| c == b'\t').collect();
+
+			transactions.insert(&row, line_count);
+		}
+
+		let result = transactions.finish();
+
+		log::info!("Importing dataset took {:.2?}", clock.elapsed());
+
+		Ok(result)
+	}
+
+
+	/// Read the transactions from the given file: the SPSS (`.sav`) or Stata (`.dta`)
+	/// distribution, or otherwise a delimited dataset, as in `read`.
+	pub fn read_file(&self, path: &Path) -> anyhow::Result<(Dataset, Distribution)> {
+		let mut source = match package::open(path)? {
+			Some(source) => source,
+			None => return self.read(io::BufReader::new(fs::File::open(path)?)),
+		};
+
+		let clock = time::Instant::now();
+
+		let mut transactions = {
+			let header: Vec<&[u8]> = source
+				.columns()
+				.iter()
+				.map(|name| name.as_bytes())
+				.collect();
+
+			Transactions::new(self, &header)?
+		};
+
+		let mut row = Vec::new();
+		let mut row_count: usize = 0;
+
+		while source.read_row(&mut row)? {
+			row_count += 1;
+
+			let fields: Vec<&[u8]> = row
+				.iter()
+				.map(Vec::as_slice)
+				.collect();
+
+			transactions.insert(&fields, row_count);
+		}
+
+		let result = transactions.finish();
+
+		log::info!("Importing dataset took {:.2?}", clock.elapsed());
+
+		Ok(result)
+	}
+}
+
+
+/// The transactions of a dataset being read, one row at a time.
+struct Transactions<'a> {
+	schema: &'a Schema,
+	/// The position of each schema column in the rows.
+	positions: Box<[usize]>,
+	/// The item of each code, or none for non-item columns.
+	codes: Box<[HashMap<&'a [u8], Option<usize>>]>,
+	item_columns: usize,
+	/// The items of each transaction, in a flat buffer.
+	items: Vec<usize>,
+	distribution: Distribution,
+}
+
+
+impl<'a> Transactions<'a> {
+	/// Match the schema columns with the given header, ignoring case, as the statistical
+	/// packages may change the case of the names.
+	fn new(schema: &'a Schema, header: &[&[u8]]) -> anyhow::Result<Self> {
+		let positions = schema.columns
+			.iter()
+			.map(
+				|column| header
+					.iter()
+					.position(|name| name.eq_ignore_ascii_case(column.name.as_bytes()))
+					.ok_or_else(|| anyhow::anyhow!("missing column in dataset: {}", column.name))
+			)
+			.collect::<anyhow::Result<_>>()?;
+
+		// Items are numbered in the column order, as in `parse`.
+		let mut next_item = 0;
+
+		let codes = schema.columns
+			.iter()
+			.map(
+				|column| column.codes
+					.iter()
+					.map(
+						|(code, _)| {
+							let item = if column.item { Some(next_item) } else { None };
+							next_item += item.is_some() as usize;
+							(code.as_bytes(), item)
+						}
+					)
+					.collect()
+			)
+			.collect();
+
+		let item_columns = schema.columns
+			.iter()
+			.filter(|column| column.item)
+			.count();
+
+		Ok(
+			Self {
+				schema,
+				positions,
+				codes,
+				item_columns,
+				items: Vec::new(),
+				distribution: Distribution::new(schema),
+			}
+		)
+	}
+
+
+	/// Add the given row, unless it is invalid or has missing items. The row number is used
+	/// for logging.
+	fn insert(&mut self, row: &[&[u8]], row_number: usize) {
+		let mut fields: Vec<&[u8]> = Vec::with_capacity(self.positions.len());
+
+		let columns = self.schema.columns
+			.iter()
+			.zip(self.codes.iter())
+			.zip(self.positions.iter());
+
+		let mut missing = false;
+
+		for ((column, codes), &position) in columns {
+			let value = row.get(position).copied().unwrap_or(b"");
+
+			if column.missing.iter().any(|code| code.as_bytes() == value) {
+				missing |= column.item;
+			}
+			else if !codes.is_empty() && !codes.contains_key(value) {
+				log::warn!(
+					"invalid record at line {}: invalid {}: {}\n{:?}",
+					row_number,
+					column.name,
+					String::from_utf8_lossy(value),
+					String::from_utf8_lossy(&row.join(&b'\t'))
+				);
+
+				return;
+			}
+
+			fields.push(value);
+		}
+
+		if missing {
+			return;
+		}
+
+		for (field, codes) in fields.iter().zip(self.codes.iter()) {
+			if let Some(&Some(item)) = codes.get(field) {
+				self.items.push(item);
+			}
+		}
+
+		self.distribution.insert(self.schema, &fields);
+	}
+
+
+	fn finish(self) -> (Dataset, Distribution) {
+		let transactions_count = self.distribution.total_records;
+
+		let mut matrix = BitMatrix::new(self.schema.items.len(), transactions_count);
+
+		if self.item_columns > 0 {
+			for (ix, transaction) in self.items.chunks(self.item_columns).enumerate() {
+				for &item in transaction {
+					matrix.set((item, ix), true);
+				}
+			}
+		}
+
+		(matrix.into(), self.distribution)
+	}
+}
+
+
+/// Mine the closed frequent itemsets, sorted by support in descending order.
+pub fn mine(dataset: &Dataset, min_sup: dci::Support) -> Box<[(Items, dci::Support)]> {
+	let clock = time::Instant::now();
+
+	let mut result = dci::parallel::closed(dataset, min_sup);
+
+	log::info!("Dci took {:.2?}", clock.elapsed());
+
+	result.sort_unstable_by_key(
+		|(_itemset, support)| cmp::Reverse(*support)
+	);
+
+	result
+}
+
+
+/// The count of each value of each column, over a set of records.
+#[derive(Debug, Clone)]
+pub struct Distribution {
+	pub total_records: usize,
+	/// The attribute name and the count of each labeled value, for each column.
+	columns: Box<[(String, HashMap<String, usize>)]>,
+	/// The label of each code, for each column.
+	labels: Box<[HashMap<Vec<u8>, String>]>,
+}
+
+
+impl Distribution {
+	fn new(schema: &Schema) -> Self {
+		Self {
+			total_records: 0,
+			columns: schema.columns
+				.iter()
+				.map(|column| (column.attribute().to_owned(), HashMap::new()))
+				.collect(),
+			labels: schema.columns
+				.iter()
+				.map(
+					|column| column.codes
+						.iter()
+						.map(
+							|(code, label)| (
+								code.clone().into_bytes(),
+								format!("{}::{}", column.attribute(), label)
+							)
+						)
+						.chain(
+							column.missing
+								.iter()
+								.map(
+									|code| (
+										code.clone().into_bytes(),
+										format!("{}::Missing", column.attribute())
+									)
+								)
+						)
+						.collect()
+				)
+				.collect(),
+		}
+	}
+
+
+	/// Count the values of the given record, one field per schema column.
+	fn insert(&mut self, schema: &Schema, fields: &[&[u8]]) {
+		for (ix, field) in fields.iter().enumerate() {
+			let label = match self.labels[ix].get(*field) {
+				Some(label) => label.clone(),
+				None if schema.columns[ix].codes.is_empty() => String::from_utf8_lossy(field).into_owned(),
+				None => unreachable!("invalid codes are discarded"),
+			};
+
+			*self.columns[ix].1.entry(label).or_insert(0) += 1;
+		}
+
+		self.total_records += 1;
+	}
+
+
+	/// The counts of each attribute, sorted by count.
+	pub fn attributes(&self) -> Box<[Attribute<'_>]> {
+		self.columns
+			.iter()
+			.map(
+				|(attribute, values)| {
+					let mut values: Vec<(String, usize)> = values
+						.iter()
+						.map(|(label, &count)| (label.clone(), count))
+						.collect();
+
+					values.sort_by(
+						|(a_label, a_count), (b_label, b_count)| b_count
+							.cmp(a_count)
+							.then(a_label.cmp(b_label))
+					);
+
+					(attribute.as_str(), values.into_boxed_slice())
+				}
+			)
+			.collect()
+	}
+}
+
+
+impl std::fmt::Display for Distribution {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let total_records = self.total_records as f64;
+
+		writeln!(f, "records: {}", self.total_records)?;
+
+		for (attribute, values) in self.attributes().iter() {
+			writeln!(f, "{}:", labels::attribute(attribute))?;
+
+			for (label, count) in values.iter() {
+				let percentage = (*count as f64 * 100.0) / total_records;
+
+				writeln!(f, "	{}: {} ({:.1}%)", labels::value(label), count, percentage,)?;
+			}
+		}
+
+		Ok(())
+	}
+}
@@ -0,0 +1,682 @@
+//! A local HTTP server over a saved dataset, which answers queries in JSON. The dataset is
+//! loaded once, and the itemsets mined with each set of parameters are cached, so that
+//! the rules of a previous mining are generated without mining again.
+//!
+//! The endpoints, all `GET`, are:
+//! - `/distribution`: the support of each item, by attribute.
+//! - `/mine?min_sup=<ratio>`: the closed frequent itemsets, as in `run --format json`.
+//! - `/query?items=<items>`: the support of an itemset.
+//! - `/rules?min_sup=<ratio>&min_conf=<ratio>`: the association rules.
+//!
+//! `/mine` and `/rules` also accept `max_length`, `must_contain` and `must_not_contain`,
+//! and `limit`.
+
+use std::{
+	collections::{HashMap, VecDeque},
+	fmt,
+	sync::{mpsc, Arc, Condvar, Mutex},
+	thread,
+	time,
+};
+
+use dci::DataSet;
+
+use serde::Serialize;
+
+use icpsr_36404_analysis::{
+	dataset::Dataset,
+	mining::{self, Constraints},
+	rules,
+	table::{self, Table},
+	ItemSet,
+	Itemsets,
+	Record,
+};
+
+use onehot::OneHot;
+
+
+/// The server settings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Settings {
+	/// The address to listen on, e.g. `127.0.0.1:8080`.
+	pub address: Box<str>,
+	/// The maximum time to compute a response. Mining that times out still completes in the
+	/// background, and its result is cached.
+	pub timeout: time::Duration,
+	/// The maximum number of cached mining results.
+	pub cache_size: usize,
+	/// The maximum number of responses computed at once, or none for the number of cores.
+	/// Further requests wait for a worker.
+	pub workers: Option<usize>,
+	/// The origin allowed to query the server from a browser, if any.
+	pub allow_origin: Option<Box<str>>,
+}
+
+
+/// The parameters of a mining: the absolute minimum support and the constraints.
+type Key = (dci::Support, Constraints);
+
+
+/// The number of threads that handle the connections. Further connections wait for one.
+const CONNECTIONS: usize = 64;
+
+
+/// A mining in progress, whose result is shared by every request with the same parameters.
+#[derive(Default)]
+struct Pending {
+	/// The result, once mined, or none if the mining failed.
+	result: Mutex<Option<Option<Arc<Itemsets>>>>,
+	done: Condvar,
+}
+
+
+impl Pending {
+	fn finish(&self, itemsets: Option<Arc<Itemsets>>) {
+		*self.result.lock().expect("pending lock should not be poisoned") = Some(itemsets);
+		self.done.notify_all();
+	}
+
+
+	/// Wait for the result, or none if the mining failed.
+	fn wait(&self) -> Option<Arc<Itemsets>> {
+		let result = self.done
+			.wait_while(
+				self.result.lock().expect("pending lock should not be poisoned"),
+				|result| result.is_none()
+			)
+			.expect("pending lock should not be poisoned");
+
+		result.clone().flatten()
+	}
+}
+
+
+/// Removes a mining from those in progress when dropped, failing it if unfinished, e.g.
+/// when the mining panics.
+struct InFlight<'a> {
+	state: &'a State,
+	key: &'a Key,
+	pending: Arc<Pending>,
+}
+
+
+impl Drop for InFlight<'_> {
+	fn drop(&mut self) {
+		self.state.in_flight
+			.lock()
+			.expect("in flight lock should not be poisoned")
+			.remove(self.key);
+
+		let unfinished = self.pending.result
+			.lock()
+			.expect("pending lock should not be poisoned")
+			.is_none();
+
+		if unfinished {
+			self.pending.finish(None);
+		}
+	}
+}
+
+
+struct State {
+	dataset: Dataset,
+	settings: Settings,
+	/// The cached mining results, oldest first.
+	cache: Mutex<VecDeque<(Key, Arc<Itemsets>)>>,
+	/// The minings in progress.
+	in_flight: Mutex<HashMap<Key, Arc<Pending>>>,
+	/// The threads that compute the responses.
+	workers: rayon::ThreadPool,
+}
+
+
+impl State {
+	/// Mine the itemsets with the given parameters, or get them from the cache. Requests with
+	/// the parameters of a mining in progress wait for its result instead of mining again.
+	fn mine(&self, key: Key) -> Result<Arc<Itemsets>, Error> {
+		let cached = self.cache
+			.lock()
+			.expect("cache lock should not be poisoned")
+			.iter()
+			.find(|(cached, _)| *cached == key)
+			.map(|(_, itemsets)| Arc::clone(itemsets));
+
+		if let Some(itemsets) = cached {
+			log::info!("Using cached itemsets");
+			return Ok(itemsets);
+		}
+
+		let pending = {
+			let mut in_flight = self.in_flight
+				.lock()
+				.expect("in flight lock should not be poisoned");
+
+			match in_flight.get(&key) {
+				Some(pending) => Err(Arc::clone(pending)),
+				None => {
+					let pending = Arc::new(Pending::default());
+					in_flight.insert(key.clone(), Arc::clone(&pending));
+					Ok(pending)
+				},
+			}
+		};
+
+		let pending = match pending {
+			Ok(pending) => pending,
+			Err(pending) => {
+				log::info!("Waiting for the itemsets in progress");
+
+				return pending
+					.wait()
+					.ok_or_else(|| Error::internal("mining failed"));
+			},
+		};
+
+		let in_flight = InFlight { state: self, key: &key, pending };
+
+		let itemsets = Arc::new(mining::mine(&self.dataset, key.0, &key.1));
+
+		{
+			let mut cache = self.cache
+				.lock()
+				.expect("cache lock should not be poisoned");
+
+			if !cache.iter().any(|(cached, _)| *cached == key) {
+				if cache.len() == self.settings.cache_size {
+					cache.pop_front();
+				}
+
+				if self.settings.cache_size > 0 {
+					cache.push_back((key.clone(), Arc::clone(&itemsets)));
+				}
+			}
+		}
+
+		in_flight.pending.finish(Some(Arc::clone(&itemsets)));
+
+		Ok(itemsets)
+	}
+}
+
+
+/// An error response.
+#[derive(Debug)]
+struct Error {
+	status: u16,
+	message: String,
+}
+
+
+impl Error {
+	fn bad_request(message: impl fmt::Display) -> Self {
+		Self { status: 400, message: message.to_string() }
+	}
+
+
+	fn internal(message: impl fmt::Display) -> Self {
+		Self { status: 500, message: message.to_string() }
+	}
+}
+
+
+/// The query parameters of a request, decoded.
+struct Params(HashMap<String, String>);
+
+
+impl Params {
+	fn parse(query: &str) -> Self {
+		Self(
+			query
+				.split('&')
+				.filter(|pair| !pair.is_empty())
+				.map(
+					|pair| match pair.split_once('=') {
+						Some((name, value)) => (decode(name), decode(value)),
+						None => (decode(pair), String::new()),
+					}
+				)
+				.collect()
+		)
+	}
+
+
+	fn get(&self, name: &str) -> Option<&str> {
+		self.0.get(name).map(String::as_str)
+	}
+
+
+	fn required(&self, name: &str) -> Result<&str, Error> {
+		self
+			.get(name)
+			.ok_or_else(|| Error::bad_request(format!("missing parameter: {}", name)))
+	}
+
+
+	/// A ratio in `[0, 1]`.
+	fn ratio(&self, name: &str) -> Result<f64, Error> {
+		let value = self.required(name)?;
+
+		match value.parse::<f64>() {
+			Ok(ratio) if (0.0 ..= 1.0).contains(&ratio) => Ok(ratio),
+			_ => Err(Error::bad_request(format!("invalid {}: {}", name, value))),
+		}
+	}
+
+
+	fn count(&self, name: &str) -> Result<Option<usize>, Error> {
+		self
+			.get(name)
+			.map(
+				|value| value
+					.parse()
+					.map_err(|_| Error::bad_request(format!("invalid {}: {}", name, value)))
+			)
+			.transpose()
+	}
+
+
+	fn itemset(&self, name: &str) -> Result<ItemSet, Error> {
+		ItemSet::parse(self.get(name).unwrap_or_default()).map_err(Error::bad_request)
+	}
+
+
+	/// The mining parameters, as the absolute minimum support and the constraints. The
+	/// minimum support must be at least one transaction, as mining every itemset would
+	/// exhaust the server.
+	fn mining(&self, dataset: &Dataset) -> Result<Key, Error> {
+		let ratio = self.ratio("min_sup")?;
+		let min_sup = (dataset.transactions_count() as f64 * ratio) as usize;
+
+		if min_sup == 0 {
+			return Err(Error::bad_request(format!("invalid min_sup: {} (less than one transaction)", ratio)));
+		}
+
+		let constraints = Constraints {
+			must_contain: self.itemset("must_contain")?,
+			must_not_contain: self.itemset("must_not_contain")?,
+			max_length: self.count("max_length")?,
+		};
+
+		let mut conflicts = constraints.must_contain.clone();
+		conflicts.remove_all(&constraints.must_not_contain);
+
+		if conflicts != constraints.must_contain {
+			return Err(Error::bad_request("items can't be both required and forbidden"));
+		}
+
+		Ok((min_sup, constraints))
+	}
+}
+
+
+/// Decode a component of a query string, where spaces may be given as `+`.
+fn decode(text: &str) -> String {
+	let mut bytes = Vec::with_capacity(text.len());
+	let mut ix = 0;
+
+	while ix < text.len() {
+		let byte = text.as_bytes()[ix];
+
+		let escaped = text
+			.get(ix + 1 .. ix + 3)
+			.filter(|_| byte == b'%')
+			.and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+		match escaped {
+			Some(escaped) => {
+				bytes.push(escaped);
+				ix += 3;
+			},
+			None => {
+				bytes.push(if byte == b'+' { b' ' } else { byte });
+				ix += 1;
+			},
+		}
+	}
+
+	String::from_utf8_lossy(&bytes).into_owned()
+}
+
+
+#[derive(Serialize)]
+struct ItemSupport {
+	item: &'static str,
+	support: dci::Support,
+	percentage: f64,
+}
+
+
+#[derive(Serialize)]
+struct AttributeSupports {
+	attribute: &'static str,
+	items: Vec<ItemSupport>,
+}
+
+
+#[derive(Serialize)]
+struct DistributionResponse {
+	transactions: usize,
+	attributes: Vec<AttributeSupports>,
+}
+
+
+#[derive(Serialize)]
+struct QueryResponse {
+	items: Vec<&'static str>,
+	transactions: usize,
+	support: dci::Support,
+	percentage: f64,
+}
+
+
+#[derive(Serialize)]
+struct RuleResponse {
+	antecedent: Vec<&'static str>,
+	consequent: Vec<&'static str>,
+	support: dci::Support,
+	confidence: f64,
+	lift: f64,
+}
+
+
+#[derive(Serialize)]
+struct RulesResponse {
+	transactions: usize,
+	rules: Vec<RuleResponse>,
+}
+
+
+fn labels(itemset: &ItemSet) -> Vec<&'static str> {
+	itemset
+		.into_iter()
+		.map(ItemSet::label)
+		.collect()
+}
+
+
+fn to_json(value: &impl Serialize) -> Result<Vec<u8>, Error> {
+	serde_json::to_vec_pretty(value).map_err(|error| Error { status: 500, message: error.to_string() })
+}
+
+
+fn distribution(state: &State) -> Result<Vec<u8>, Error> {
+	let transactions = state.dataset.transactions_count();
+
+	let mut attributes: Vec<AttributeSupports> = Vec::new();
+
+	for item in 0 .. Record::ONEHOT_LEN {
+		let attribute = ItemSet::attribute(item);
+		let support = state.dataset.item_support(item);
+
+		let item = ItemSupport {
+			item: ItemSet::label(item),
+			support,
+			percentage: support as f64 * 100.0 / transactions.max(1) as f64,
+		};
+
+		match attributes.last_mut() {
+			Some(last) if last.attribute == attribute => last.items.push(item),
+			_ => attributes.push(AttributeSupports { attribute, items: vec![item] }),
+		}
+	}
+
+	to_json(&DistributionResponse { transactions, attributes })
+}
+
+
+fn mine(state: &State, params: &Params) -> Result<Vec<u8>, Error> {
+	let itemsets = state.mine(params.mining(&state.dataset)?)?;
+
+	let table = Table {
+		format: table::Format::Json,
+		limit: params.count("limit")?,
+		..Table::default()
+	};
+
+	let mut body = Vec::new();
+
+	table
+		.itemsets(&mut body, &itemsets, &state.dataset, None)
+		.map_err(|error| Error { status: 500, message: error.to_string() })?;
+
+	Ok(body)
+}
+
+
+fn query(state: &State, params: &Params) -> Result<Vec<u8>, Error> {
+	let itemset = ItemSet::parse(params.required("items")?).map_err(Error::bad_request)?;
+
+	let transactions = state.dataset.transactions_count();
+	let support = state.dataset.support(&itemset);
+
+	to_json(
+		&QueryResponse {
+			items: labels(&itemset),
+			transactions,
+			support,
+			percentage: support as f64 * 100.0 / transactions.max(1) as f64,
+		}
+	)
+}
+
+
+fn rules(state: &State, params: &Params) -> Result<Vec<u8>, Error> {
+	let min_confidence = params.ratio("min_conf")?;
+
+	let itemsets = state.mine(params.mining(&state.dataset)?)?;
+
+	let rules = rules::generate(&state.dataset, &itemsets, min_confidence);
+	let limit = params.count("limit")?.unwrap_or(rules.len());
+
+	to_json(
+		&RulesResponse {
+			transactions: state.dataset.transactions_count(),
+			rules: rules
+				.iter()
+				.take(limit)
+				.map(
+					|rule| RuleResponse {
+						antecedent: labels(&rule.antecedent),
+						consequent: labels(&rule.consequent),
+						support: rule.support,
+						confidence: rule.confidence,
+						lift: rule.lift,
+					}
+				)
+				.collect(),
+		}
+	)
+}
+
+
+/// Compute the response to a request in a worker thread, so that it can time out.
+fn respond(state: &Arc<State>, path: &str, params: Params) -> Result<Vec<u8>, Error> {
+	let (tx, rx) = mpsc::channel();
+
+	let worker = Arc::clone(state);
+	let path = path.to_owned();
+
+	state.workers.spawn(
+		move || {
+			let response = match path.as_str() {
+				"/distribution" => distribution(&worker),
+				"/mine" => mine(&worker, &params),
+				"/query" => query(&worker, &params),
+				"/rules" => rules(&worker, &params),
+				_ => Err(Error { status: 404, message: format!("not found: {}", path) }),
+			};
+
+			// The request may have timed out.
+			let _ = tx.send(response);
+		}
+	);
+
+	match rx.recv_timeout(state.settings.timeout) {
+		Ok(response) => response,
+		Err(mpsc::RecvTimeoutError::Timeout) => Err(
+			Error { status: 504, message: format!("timed out after {:.0?}", state.settings.timeout) }
+		),
+		// The worker panicked.
+		Err(mpsc::RecvTimeoutError::Disconnected) => Err(Error::internal("internal error")),
+	}
+}
+
+
+fn handle(state: &Arc<State>, request: tiny_http::Request) {
+	let clock = time::Instant::now();
+
+	let url = request.url().to_owned();
+	let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+
+	let response = if *request.method() == tiny_http::Method::Get {
+		respond(state, path, Params::parse(query))
+	}
+	else {
+		Err(Error { status: 405, message: format!("method not allowed: {}", request.method()) })
+	};
+
+	let (status, body) = match response {
+		Ok(body) => (200, body),
+		Err(error) => (error.status, format!("{{\"error\":{}}}", serde_json::Value::from(error.message)).into_bytes()),
+	};
+
+	log::info!("{} {} {} took {:.2?}", request.method(), url, status, clock.elapsed());
+
+	let mut response = tiny_http::Response
+		::from_data(body)
+		.with_status_code(status)
+		.with_header(
+			tiny_http::Header
+				::from_bytes("Content-Type", "application/json")
+				.expect("header should be valid")
+		);
+
+	if let Some(origin) = &state.settings.allow_origin {
+		if let Ok(header) = tiny_http::Header::from_bytes("Access-Control-Allow-Origin", origin.as_bytes()) {
+			response.add_header(header);
+		}
+	}
+
+	if let Err(error) = request.respond(response) {
+		log::warn!("failed to respond: {}", error);
+	}
+}
+
+
+/// Serve the given dataset until the process is terminated. The requests are handled by a
+/// fixed number of threads, and their responses computed by the workers.
+pub fn run(dataset: Dataset, settings: Settings) -> anyhow::Result<()> {
+	let server = tiny_http::Server
+		::http(&*settings.address)
+		.map_err(|error| anyhow::anyhow!("failed to listen on {}: {}", settings.address, error))?;
+
+	let server = Arc::new(server);
+
+	let mut workers = rayon::ThreadPoolBuilder
+		::new()
+		.thread_name(|ix| format!("worker-{}", ix))
+		.panic_handler(|_| log::error!("worker panicked"));
+
+	if let Some(count) = settings.workers {
+		workers = workers.num_threads(count);
+	}
+
+	let workers = workers.build()?;
+
+	log::info!("Listening on http://{}", settings.address);
+
+	let state = Arc::new(
+		State {
+			dataset,
+			settings,
+			cache: Mutex::new(VecDeque::new()),
+			in_flight: Mutex::new(HashMap::new()),
+			workers,
+		}
+	);
+
+	let connections = (0 .. CONNECTIONS)
+		.map(
+			|ix| {
+				let server = Arc::clone(&server);
+				let state = Arc::clone(&state);
+
+				thread::Builder
+					::new()
+					.name(format!("connection-{}", ix))
+					.spawn(
+						move || for request in server.incoming_requests() {
+							handle(&state, request);
+						}
+					)
+			}
+		)
+		.collect::<std::io::Result<Vec<_>>>()?;
+
+	for connection in connections {
+		if connection.join().is_err() {
+			log::error!("connection thread panicked");
+		}
+	}
+
+	Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+	use bitmatrix::BitMatrix;
+
+	use super::*;
+
+
+	#[test]
+	fn decode_query_components() {
+		assert_eq!(decode("race%3DBlack+Sex%3A%3AMale"), "race=Black Sex::Male");
+		assert_eq!(decode("a%2Bb"), "a+b");
+		assert_eq!(decode("%C3%A9"), "é");
+
+		// Invalid or truncated escapes are kept as they are.
+		assert_eq!(decode("%zz%4"), "%zz%4");
+		assert_eq!(decode("100%"), "100%");
+		assert_eq!(decode("%ff"), "\u{fffd}");
+	}
+
+
+	#[test]
+	fn mining_parameters() {
+		let dataset = Dataset::from(BitMatrix::new(<Record as OneHot>::ONEHOT_LEN, 10));
+
+		let mining = |query: &str| Params::parse(query).mining(&dataset);
+
+		let (min_sup, constraints) = mining("min_sup=0.25&max_length=2&must_contain=Race::Black").unwrap();
+
+		assert_eq!(min_sup, 2);
+		assert_eq!(constraints.max_length, Some(2));
+		assert_eq!(constraints.must_contain, ItemSet::parse("Race::Black").unwrap());
+		assert!(constraints.must_not_contain.is_empty());
+
+		let (min_sup, constraints) = mining("min_sup=1").unwrap();
+
+		assert_eq!(min_sup, 10);
+		assert_eq!(constraints, Constraints::default());
+
+		for query in [
+			"",
+			"min_sup=0",
+			// Less than one transaction.
+			"min_sup=0.05",
+			"min_sup=1.5",
+			"min_sup=-0.1",
+			"min_sup=x",
+			"min_sup=0.5&max_length=x",
+			"min_sup=0.5&must_contain=Race::Unknown",
+			"min_sup=0.5&must_contain=Race::Black&must_not_contain=race%3Dblack",
+		]
+		.iter() {
+			assert_eq!(mining(query).map_err(|error| error.status).unwrap_err(), 400, "{}", query);
+		}
+	}
+}
@@ -0,0 +1,240 @@
+//! Stability of the closed frequent itemsets under resampling of the transactions. Each
+//! resample is mined with the same relative support threshold, and the itemsets of the
+//! original dataset are tracked across the resamples.
+
+use std::{
+	collections::HashSet,
+	time,
+};
+
+use dci::DataSet;
+
+use rand::{
+	rngs::StdRng,
+	seq::SliceRandom,
+	RngExt,
+	SeedableRng,
+};
+
+use crate::{
+	dataset::Dataset,
+	itemset::ItemSet,
+	mining::{self, Constraints},
+};
+
+
+/// How to draw the resamples of the transactions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Resampling {
+	/// Draw as many transactions as the dataset has, with replacement.
+	Bootstrap,
+	/// Draw the given ratio of the transactions, in `(0, 1]`, without replacement.
+	Subsample(f64),
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Parameters {
+	/// The number of resamples.
+	pub resamples: usize,
+	pub resampling: Resampling,
+	/// The seed of the random generator, or none for a random seed.
+	pub seed: Option<u64>,
+}
+
+
+/// The stability of a closed frequent itemset of the original dataset.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stability {
+	pub itemset: ItemSet,
+	/// The support in the original dataset.
+	pub support: dci::Support,
+	/// The fraction of the resamples in which the itemset was closed and frequent.
+	pub frequency: f64,
+	/// The 95% percentile interval of the relative support over the resamples.
+	pub interval: (f64, f64),
+}
+
+
+/// The stability of the itemsets, and the seed of the resamples.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Analysis {
+	pub itemsets: Box<[Stability]>,
+	pub seed: u64,
+}
+
+
+/// Mine resamples of the dataset with the given relative support threshold and
+/// constraints, and compute the stability of the given itemsets, which were mined from
+/// the original dataset.
+pub fn analyze(
+	dataset: &Dataset,
+	itemsets: &[(ItemSet, dci::Support)],
+	min_sup_ratio: f64,
+	constraints: &Constraints,
+	parameters: &Parameters,
+) -> Analysis {
+	let clock = time::Instant::now();
+
+	let seed = parameters.seed.unwrap_or_else(rand::random);
+	let mut rng = StdRng::seed_from_u64(seed);
+
+	let transactions = dataset.transactions_count();
+	let mut indices: Vec<usize> = (0 .. transactions).collect();
+
+	let mut found = vec![0_usize; itemsets.len()];
+	let mut supports = vec![Vec::with_capacity(parameters.resamples); itemsets.len()];
+
+	for _ in 0 .. parameters.resamples {
+		let resample = match parameters.resampling {
+			Resampling::Bootstrap => {
+				let drawn: Vec<usize> = (0 .. transactions)
+					.map(|_| rng.random_range(0 .. transactions))
+					.collect();
+
+				dataset.select(&drawn)
+			},
+
+			Resampling::Subsample(ratio) => {
+				let size = ((transactions as f64 * ratio).round() as usize).clamp(1, transactions.max(1));
+				let (drawn, _) = indices.partial_shuffle(&mut rng, size);
+
+				dataset.select(drawn)
+			},
+		};
+
+		let size = resample.transactions_count().max(1) as f64;
+		let min_sup = (size * min_sup_ratio) as usize;
+
+		let closed: HashSet<ItemSet> = mining::closed(&resample, min_sup, constraints)
+			.into_vec()
+			.into_iter()
+			.map(|(itemset, _)| itemset)
+			.collect();
+
+		for (ix, (itemset, _)) in itemsets.iter().enumerate() {
+			if closed.contains(itemset) {
+				found[ix] += 1;
+			}
+
+			supports[ix].push(resample.support(itemset) as f64 / size);
+		}
+	}
+
+	log::info!("Resampling took {:.2?}", clock.elapsed());
+
+	let resamples = parameters.resamples.max(1) as f64;
+
+	let itemsets = itemsets
+		.iter()
+		.zip(found)
+		.zip(supports)
+		.map(
+			|(((itemset, support), found), mut supports)| {
+				supports.sort_unstable_by(f64::total_cmp);
+
+				Stability {
+					itemset: itemset.clone(),
+					support: *support,
+					frequency: found as f64 / resamples,
+					interval: (percentile(&supports, 0.025), percentile(&supports, 0.975)),
+				}
+			}
+		)
+		.collect();
+
+	Analysis { itemsets, seed }
+}
+
+
+/// The given percentile of the sorted values, by the nearest rank.
+fn percentile(values: &[f64], p: f64) -> f64 {
+	if values.is_empty() {
+		return 0.0;
+	}
+
+	let rank = (p * values.len() as f64).ceil() as usize;
+
+	values[rank.clamp(1, values.len()) - 1]
+}
+
+
+#[cfg(test)]
+mod tests {
+	use bitmatrix::BitMatrix;
+
+	use super::*;
+
+
+	#[test]
+	fn percentile_by_nearest_rank() {
+		let values: Vec<f64> = (1 ..= 100).map(f64::from).collect();
+
+		assert_eq!(percentile(&values, 0.025), 3.0);
+		assert_eq!(percentile(&values, 0.5), 50.0);
+		assert_eq!(percentile(&values, 0.975), 98.0);
+		assert_eq!(percentile(&values, 0.0), 1.0);
+		assert_eq!(percentile(&values, 1.0), 100.0);
+
+		assert_eq!(percentile(&[5.0], 0.025), 5.0);
+		assert_eq!(percentile(&[1.0, 2.0, 3.0], 0.975), 3.0);
+		assert_eq!(percentile(&[], 0.5), 0.0);
+	}
+
+
+	/// Item 0 in every transaction but the last, item 1 in the first half, and item 2 in
+	/// every third transaction.
+	fn dataset() -> Dataset {
+		let mut matrix = BitMatrix::new(3, 12);
+
+		for transaction in 0 .. 12 {
+			matrix.set((0, transaction), transaction < 11);
+			matrix.set((1, transaction), transaction < 6);
+			matrix.set((2, transaction), transaction % 3 == 0);
+		}
+
+		Dataset::from(matrix)
+	}
+
+
+	#[test]
+	fn analyze_is_reproducible() {
+		let dataset = dataset();
+		let constraints = Constraints::default();
+		let itemsets = mining::closed(&dataset, 3, &constraints);
+
+		let parameters = Parameters {
+			resamples: 20,
+			resampling: Resampling::Bootstrap,
+			seed: Some(7),
+		};
+
+		let analysis = analyze(&dataset, &itemsets, 0.25, &constraints, &parameters);
+
+		assert_eq!(analysis.seed, 7);
+		assert_eq!(analysis, analyze(&dataset, &itemsets, 0.25, &constraints, &parameters));
+		assert_eq!(analysis.itemsets.len(), itemsets.len());
+
+		for stability in analysis.itemsets.iter() {
+			assert!((0.0 ..= 1.0).contains(&stability.frequency));
+			assert!(stability.interval.0 <= stability.interval.1);
+		}
+
+		// Subsamples of every transaction are the dataset itself.
+		let parameters = Parameters {
+			resamples: 3,
+			resampling: Resampling::Subsample(1.0),
+			seed: Some(7),
+		};
+
+		let analysis = analyze(&dataset, &itemsets, 0.25, &constraints, &parameters);
+
+		for (stability, (itemset, support)) in analysis.itemsets.iter().zip(itemsets.iter()) {
+			let ratio = *support as f64 / 12.0;
+
+			assert_eq!(&stability.itemset, itemset);
+			assert_eq!(stability.frequency, 1.0);
+			assert_eq!(stability.interval, (ratio, ratio));
+		}
+	}
+}
@@ -0,0 +1,784 @@
+use std::{
+	borrow::Cow,
+	io::{self, Write},
+};
+
+use dci::DataSet;
+
+use serde::Serialize;
+
+use crate::{
+	data::{
+		distribution::{Attribute, Distribution, WeightedAttribute},
+		sample::Frame,
+		weight::Weights,
+	},
+	dataset::Dataset,
+	itemset::ItemSet,
+	labels,
+	schema::{self, Schema},
+	stability::Stability,
+};
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Format {
+	Text,
+	Latex,
+	Markdown,
+	/// A structured result file, for itemsets only, which can be compared by `diff`.
+	Json,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Column {
+	Support,
+	Percentage,
+	Lift,
+	/// The support extrapolated from a sample to the population, with its confidence
+	/// interval.
+	Estimate,
+}
+
+
+/// The layout of the output tables.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Table {
+	pub format: Format,
+	pub columns: Box<[Column]>,
+	/// The maximum number of rows. For distributions, this applies to each attribute.
+	pub limit: Option<usize>,
+	pub caption: Option<Box<str>>,
+}
+
+
+impl Default for Table {
+	fn default() -> Self {
+		Self {
+			format: Format::Text,
+			columns: Box::new([Column::Support, Column::Percentage]),
+			limit: None,
+			caption: None,
+		}
+	}
+}
+
+
+/// A single cell of a table.
+enum Cell<'a> {
+	Text(&'a str),
+	Count(usize),
+	Percentage(f64),
+	Ratio(f64),
+	/// An estimate and its interval.
+	Estimate(f64, f64, f64),
+	/// An interval of percentages.
+	Interval(f64, f64),
+	/// A total weight.
+	Weight(f64),
+}
+
+
+/// An itemset of a result file, labeled by the raw item labels.
+#[derive(Serialize)]
+struct JsonItemset<'a, S> {
+	items: Box<[Cow<'a, str>]>,
+	support: S,
+	percentage: f64,
+}
+
+
+/// A result file: the total support, i.e. the number of transactions or their total weight,
+/// and the itemsets.
+#[derive(Serialize)]
+struct JsonResults<'a, S> {
+	transactions: S,
+	itemsets: Box<[JsonItemset<'a, S>]>,
+}
+
+
+/// A support, either a number of transactions or their total weight.
+trait Support: Copy + Serialize {
+	fn value(self) -> f64;
+	fn cell(self) -> Cell<'static>;
+}
+
+
+impl Support for usize {
+	fn value(self) -> f64 {
+		self as f64
+	}
+
+	fn cell(self) -> Cell<'static> {
+		Cell::Count(self)
+	}
+}
+
+
+impl Support for f64 {
+	fn value(self) -> f64 {
+		self
+	}
+
+	fn cell(self) -> Cell<'static> {
+		Cell::Weight(self)
+	}
+}
+
+
+impl Format {
+	fn escape(self, text: &str) -> String {
+		match self {
+			Format::Text | Format::Json => text.to_owned(),
+
+			Format::Latex => {
+				let mut escaped = String::with_capacity(text.len());
+
+				for c in text.chars() {
+					match c {
+						'\\' => escaped.push_str("\\textbackslash{}"),
+						'~' => escaped.push_str("\\textasciitilde{}"),
+						'^' => escaped.push_str("\\textasciicircum{}"),
+						'_' | '%' | '&' | '#' | '$' | '{' | '}' => {
+							escaped.push('\\');
+							escaped.push(c);
+						},
+						c => escaped.push(c),
+					}
+				}
+
+				escaped
+			},
+
+			Format::Markdown => text.replace('|', "\\|"),
+		}
+	}
+
+
+	fn cell(self, cell: &Cell) -> String {
+		match (self, cell) {
+			(_, Cell::Text(text)) => self.escape(text),
+			(_, Cell::Count(count)) => count.to_string(),
+			(_, Cell::Weight(weight)) => format!("{:.1}", weight),
+			(Format::Latex, Cell::Percentage(percentage)) => format!("{:.1}\\%", percentage),
+			(_, Cell::Percentage(percentage)) => format!("{:.1}%", percentage),
+			(_, Cell::Ratio(ratio)) => format!("{:.2}", ratio),
+			(_, Cell::Estimate(estimate, low, high)) => format!("{:.0} [{:.0}, {:.0}]", estimate, low, high),
+			(_, Cell::Interval(low, high)) => format!(
+				"[{}, {}]",
+				self.cell(&Cell::Percentage(*low)),
+				self.cell(&Cell::Percentage(*high)),
+			),
+		}
+	}
+
+
+	/// Write the table header. Alignment is given by `l` for left and `r` for right.
+	fn header<W: Write>(
+		self,
+		writer: &mut W,
+		caption: Option<&str>,
+		headers: &[&str],
+		alignment: &str,
+	) -> io::Result<()> {
+		match self {
+			Format::Text | Format::Json => Ok(()),
+
+			Format::Latex => {
+				writeln!(writer, "\\begin{{table}}[h!]")?;
+
+				if let Some(caption) = caption {
+					writeln!(writer, "\\caption{{{}}}", self.escape(caption))?;
+				}
+
+				writeln!(writer, "\\centering")?;
+				writeln!(writer, "\\begin{{tabular}}{{{}}}", alignment)?;
+
+				let headers: Vec<String> = headers
+					.iter()
+					.map(|header| self.escape(header))
+					.collect();
+
+				writeln!(writer, "{}\\\\", headers.join(" & "))?;
+				writeln!(writer, "\\hline")
+			},
+
+			Format::Markdown => {
+				let headers: Vec<String> = headers
+					.iter()
+					.map(|header| self.escape(header))
+					.collect();
+
+				writeln!(writer, "| {} |", headers.join(" | "))?;
+
+				let alignment: Vec<&str> = alignment
+					.chars()
+					.map(
+						|c| if c == 'r' { "--:" } else { ":--" }
+					)
+					.collect();
+
+				writeln!(writer, "|{}|", alignment.join("|"))
+			},
+		}
+	}
+
+
+	fn row<W: Write>(self, writer: &mut W, cells: &[Cell]) -> io::Result<()> {
+		let cells: Vec<String> = cells
+			.iter()
+			.map(|cell| self.cell(cell))
+			.collect();
+
+		match self {
+			Format::Text | Format::Json => writeln!(writer, "{}", cells.join(" ")),
+			Format::Latex => writeln!(writer, "{}\\\\", cells.join(" & ")),
+			Format::Markdown => writeln!(writer, "| {} |", cells.join(" | ")),
+		}
+	}
+
+
+	/// Separate groups of rows.
+	fn separator<W: Write>(self, writer: &mut W) -> io::Result<()> {
+		match self {
+			Format::Text | Format::Json => Ok(()),
+			Format::Latex => writeln!(writer, "\\hline"),
+			Format::Markdown => Ok(()),
+		}
+	}
+
+
+	fn footer<W: Write>(self, writer: &mut W, caption: Option<&str>) -> io::Result<()> {
+		match self {
+			Format::Text | Format::Json => Ok(()),
+
+			Format::Latex => {
+				writeln!(writer, "\\end{{tabular}}")?;
+				writeln!(writer, "\\end{{table}}")
+			},
+
+			Format::Markdown => match caption {
+				Some(caption) => {
+					writeln!(writer)?;
+					writeln!(writer, "Table: {}", self.escape(caption))
+				},
+				None => Ok(()),
+			},
+		}
+	}
+}
+
+
+impl Table {
+	fn alignment(&self, left_columns: usize) -> String {
+		let mut alignment = "l".repeat(left_columns);
+
+		alignment.push_str(&"r".repeat(self.columns.len()));
+
+		alignment
+	}
+
+
+	/// Write the given itemsets as a table. If the dataset is a sample, the supports are
+	/// extrapolated through the sampling frame.
+	pub fn itemsets<W: Write>(
+		&self,
+		writer: W,
+		itemsets: &[(ItemSet, dci::Support)],
+		dataset: &Dataset,
+		frame: Option<&Frame>,
+	) -> io::Result<()> {
+		if self.format == Format::Json {
+			return self.write_json(writer, itemsets, dataset.transactions_count(), ItemSet::label);
+		}
+
+		self.write_itemsets(
+			writer,
+			itemsets,
+			dataset.transactions_count() as f64,
+			|item| dataset.item_support(item) as f64,
+			frame,
+			labels::item,
+		)
+	}
+
+
+	/// Write the given itemsets as a table, with their weighted supports.
+	pub fn weighted_itemsets<W: Write>(
+		&self,
+		writer: W,
+		itemsets: &[(ItemSet, f64)],
+		dataset: &Dataset,
+		weights: &Weights,
+	) -> io::Result<()> {
+		if self.format == Format::Json {
+			return self.write_json(writer, itemsets, weights.total(), ItemSet::label);
+		}
+
+		self.write_itemsets(
+			writer,
+			itemsets,
+			weights.total(),
+			|item| weights.support(dataset.row(item)),
+			None,
+			labels::item,
+		)
+	}
+
+
+	/// Write the given itemsets of a schema-described dataset as a table.
+	pub fn schema_itemsets<W: Write>(
+		&self,
+		writer: W,
+		itemsets: &[(schema::Items, dci::Support)],
+		dataset: &schema::Dataset,
+		schema: &Schema,
+	) -> io::Result<()> {
+		if self.format == Format::Json {
+			return self.write_json(writer, itemsets, dataset.transactions_count(), |item| schema.item_name(item));
+		}
+
+		self.write_itemsets(
+			writer,
+			itemsets,
+			dataset.transactions_count() as f64,
+			|item| dataset.item_support(item) as f64,
+			None,
+			|item| schema.item_name(item),
+		)
+	}
+
+
+	/// Write the itemsets as a result file, labeled by the given raw item labels so that
+	/// results can be matched regardless of the encoding and locale.
+	fn write_json<'a, W, I, S, F, L>(
+		&self,
+		mut writer: W,
+		itemsets: &[(I, S)],
+		transactions: S,
+		label: F,
+	) -> io::Result<()>
+	where
+		W: Write,
+		for<'b> &'b I: IntoIterator<Item = usize>,
+		S: Support,
+		F: Fn(usize) -> L,
+		L: Into<Cow<'a, str>>,
+	{
+		let limit = self.limit.unwrap_or(itemsets.len());
+
+		let results = JsonResults {
+			transactions,
+			itemsets: itemsets
+				.iter()
+				.take(limit)
+				.map(
+					|(itemset, support)| JsonItemset {
+						items: itemset
+							.into_iter()
+							.map(|item| label(item).into())
+							.collect(),
+						support: *support,
+						percentage: support.value() * 100.0 / transactions.value(),
+					}
+				)
+				.collect(),
+		};
+
+		serde_json::to_writer_pretty(&mut writer, &results)?;
+		writeln!(writer)?;
+
+		writer.flush()
+	}
+
+
+	/// Write the itemsets, given the total support of the dataset and the support of each
+	/// item.
+	fn write_itemsets<'a, W, I, S, F>(
+		&self,
+		mut writer: W,
+		itemsets: &[(I, S)],
+		transactions: f64,
+		item_support: impl Fn(usize) -> f64,
+		frame: Option<&Frame>,
+		name: F,
+	) -> io::Result<()>
+	where
+		W: Write,
+		for<'b> &'b I: IntoIterator<Item = usize>,
+		S: Support,
+		F: Fn(usize) -> Cow<'a, str>,
+	{
+		let mut headers = vec![labels::header("itemset", "Itemset")];
+		headers.extend(
+			self.columns
+				.iter()
+				.map(
+					|column| match column {
+						Column::Support => labels::header("support", "Support"),
+						Column::Percentage => "%",
+						Column::Lift => labels::header("lift", "Lift"),
+						Column::Estimate => labels::header("estimate", "Estimate (95% CI)"),
+					}
+				)
+		);
+
+		let caption = self.caption.as_deref();
+
+		self.format.header(&mut writer, caption, &headers, &self.alignment(1))?;
+
+		let limit = self.limit.unwrap_or(itemsets.len());
+
+		for (itemset, support) in itemsets.iter().take(limit) {
+			let value = support.value();
+
+			let lift = || {
+				let expected: f64 = itemset
+					.into_iter()
+					.map(|item| item_support(item) / transactions)
+					.product();
+
+				(value / transactions) / expected
+			};
+
+			let values = self.columns
+				.iter()
+				.map(
+					|column| match column {
+						Column::Support => support.cell(),
+						Column::Percentage => Cell::Percentage(value * 100.0 / transactions),
+						Column::Lift => Cell::Ratio(lift()),
+						Column::Estimate => match frame {
+							Some(frame) => {
+								let (estimate, low, high) = frame.extrapolate(value as usize);
+								Cell::Estimate(estimate, low, high)
+							},
+							None => Cell::Estimate(value, value, value),
+						},
+					}
+				);
+
+			let label = itemset
+				.into_iter()
+				.map(&name)
+				.collect::<Vec<_>>()
+				.join(", ");
+
+			if self.format == Format::Text {
+				// Keep the traditional output: `support (percentage): itemset`.
+				let values: Vec<String> = values
+					.map(
+						|cell| match cell {
+							Cell::Percentage(_) => format!("({})", self.format.cell(&cell)),
+							Cell::Ratio(_) => format!("[lift {}]", self.format.cell(&cell)),
+							Cell::Estimate(..) => format!("~{}", self.format.cell(&cell)),
+							_ => self.format.cell(&cell),
+						}
+					)
+					.collect();
+
+				writeln!(writer, "{}: {{{}}}", values.join(" "), label)?;
+			}
+			else {
+				let mut cells = vec![Cell::Text(&label)];
+				cells.extend(values);
+
+				self.format.row(&mut writer, &cells)?;
+			}
+		}
+
+		self.format.footer(&mut writer, caption)?;
+
+		writer.flush()
+	}
+
+
+	/// Write the stability of the given itemsets as a table.
+	pub fn stability<W: Write>(
+		&self,
+		mut writer: W,
+		itemsets: &[Stability],
+		dataset: &Dataset,
+	) -> io::Result<()> {
+		let transactions = dataset.transactions_count() as f64;
+
+		let headers = [
+			labels::header("itemset", "Itemset"),
+			labels::header("support", "Support"),
+			"%",
+			labels::header("frequency", "Frequency"),
+			labels::header("interval", "Support (95% CI)"),
+		];
+
+		let caption = self.caption.as_deref();
+
+		self.format.header(&mut writer, caption, &headers, "lrrrr")?;
+
+		let limit = self.limit.unwrap_or(itemsets.len());
+
+		for stability in itemsets.iter().take(limit) {
+			let percentage = Cell::Percentage(stability.support as f64 * 100.0 / transactions);
+			let frequency = Cell::Percentage(stability.frequency * 100.0);
+			let interval = Cell::Interval(stability.interval.0 * 100.0, stability.interval.1 * 100.0);
+
+			if self.format == Format::Text {
+				writeln!(
+					writer,
+					"{} ({}) [closed in {}, support {}]: {}",
+					stability.support,
+					self.format.cell(&percentage),
+					self.format.cell(&frequency),
+					self.format.cell(&interval),
+					stability.itemset,
+				)?;
+			}
+			else {
+				let label = stability.itemset
+					.into_iter()
+					.map(labels::item)
+					.collect::<Vec<_>>()
+					.join(", ");
+
+				self.format.row(
+					&mut writer,
+					&[
+						Cell::Text(&label),
+						Cell::Count(stability.support),
+						percentage,
+						frequency,
+						interval,
+					],
+				)?;
+			}
+		}
+
+		self.format.footer(&mut writer, caption)?;
+
+		writer.flush()
+	}
+
+
+	/// Write the given distribution as a table.
+	pub fn distribution<W: Write>(
+		&self,
+		writer: W,
+		distribution: &Distribution,
+	) -> io::Result<()> {
+		let weighted = distribution.weights
+			.as_ref()
+			.map(|_| (distribution.total_weight, distribution.weighted_attributes()));
+
+		let weighted = weighted.as_ref().map(|(total, attributes)| (*total, &attributes[..]));
+
+		if self.format == Format::Text {
+			return self.write_text_distribution(
+				writer,
+				distribution.total_records,
+				distribution.frame.as_ref(),
+				&distribution.attributes(),
+				weighted,
+			);
+		}
+
+		self.write_distribution(writer, distribution.total_records, &distribution.attributes(), weighted)
+	}
+
+
+	/// Write the given distribution of a schema-described dataset as a table.
+	pub fn schema_distribution<W: Write>(
+		&self,
+		writer: W,
+		distribution: &schema::Distribution,
+	) -> io::Result<()> {
+		if self.format == Format::Text {
+			return self.write_text_distribution(
+				writer,
+				distribution.total_records,
+				None,
+				&distribution.attributes(),
+				None,
+			);
+		}
+
+		self.write_distribution(writer, distribution.total_records, &distribution.attributes(), None)
+	}
+
+
+	/// Write the distribution as text: the caption, if any, the number of records, the
+	/// sampling frame and the total weight, if any, and the values of each attribute. With
+	/// the default columns, this is the `Display` of the distribution.
+	fn write_text_distribution<W: Write>(
+		&self,
+		mut writer: W,
+		total_records: usize,
+		frame: Option<&Frame>,
+		attributes: &[Attribute],
+		weighted: Option<(f64, &[WeightedAttribute])>,
+	) -> io::Result<()> {
+		if let Some(caption) = &self.caption {
+			writeln!(writer, "{}", caption)?;
+		}
+
+		writeln!(writer, "records: {}", total_records)?;
+
+		if let Some(frame) = frame {
+			write!(writer, "sample: {}", frame)?;
+		}
+
+		if let Some((total_weight, _)) = weighted {
+			writeln!(writer, "weight: {:.1}", total_weight)?;
+		}
+
+		for (ix, (attribute, values)) in attributes.iter().enumerate() {
+			writeln!(writer, "{}:", labels::attribute(attribute))?;
+
+			let limit = self.limit.unwrap_or(values.len());
+
+			for (row, (label, count)) in values.iter().take(limit).enumerate() {
+				write!(
+					writer,
+					"	{}: {}",
+					labels::value(label),
+					self.text_counts(Cell::Count(*count), *count as f64 * 100.0 / total_records as f64),
+				)?;
+
+				if let Some((total_weight, weighted)) = weighted {
+					let weight = weighted[ix].1[row].1;
+
+					write!(
+						writer,
+						", weighted {}",
+						self.text_counts(Cell::Weight(weight), weight * 100.0 / total_weight),
+					)?;
+				}
+
+				writeln!(writer)?;
+			}
+		}
+
+		writer.flush()
+	}
+
+
+	/// The support and percentage of a value as text, by the columns, e.g. `10 (2.0%)`.
+	fn text_counts(&self, support: Cell, percentage: f64) -> String {
+		let percentage = self.format.cell(&Cell::Percentage(percentage));
+
+		let cells: Vec<String> = self.columns
+			.iter()
+			.filter_map(
+				|column| match column {
+					Column::Support => Some(self.format.cell(&support)),
+					Column::Percentage if self.columns.contains(&Column::Support) => Some(format!("({})", percentage)),
+					Column::Percentage => Some(percentage.clone()),
+					Column::Lift | Column::Estimate => None,
+				}
+			)
+			.collect();
+
+		cells.join(" ")
+	}
+
+
+	/// Write the distribution, with the total weight and the weights of each attribute if
+	/// weighted.
+	fn write_distribution<W: Write>(
+		&self,
+		mut writer: W,
+		total_records: usize,
+		attributes: &[Attribute],
+		weighted: Option<(f64, &[WeightedAttribute])>,
+	) -> io::Result<()> {
+		let total_records = total_records as f64;
+
+		let mut headers = vec![
+			labels::header("attribute", "Attribute"),
+			labels::header("value", "Value"),
+		];
+		headers.extend(
+			self.columns
+				.iter()
+				.filter_map(
+					|column| match column {
+						Column::Support => Some(labels::header("records", "Records")),
+						Column::Percentage => Some("%"),
+						Column::Lift | Column::Estimate => None,
+					}
+				)
+		);
+
+		if weighted.is_some() {
+			headers.extend(
+				self.columns
+					.iter()
+					.filter_map(
+						|column| match column {
+							Column::Support => Some(labels::header("weight", "Weight")),
+							Column::Percentage => Some("%"),
+							Column::Lift | Column::Estimate => None,
+						}
+					)
+			);
+		}
+
+		let caption = self.caption.as_deref();
+
+		let alignment = {
+			let mut alignment = "ll".to_owned();
+			alignment.push_str(&"r".repeat(headers.len() - 2));
+			alignment
+		};
+
+		self.format.header(&mut writer, caption, &headers, &alignment)?;
+
+		for (ix, (attribute, values)) in attributes.iter().enumerate() {
+			if ix > 0 {
+				self.format.separator(&mut writer)?;
+			}
+
+			let limit = self.limit.unwrap_or(values.len());
+
+			for (row, (label, count)) in values.iter().take(limit).enumerate() {
+				let mut cells = vec![
+					Cell::Text(if row == 0 { labels::attribute(attribute) } else { "" }),
+					Cell::Text(labels::value(label)),
+				];
+
+				cells.extend(
+					self.columns
+						.iter()
+						.filter_map(
+							|column| match column {
+								Column::Support => Some(Cell::Count(*count)),
+								Column::Percentage => Some(Cell::Percentage(*count as f64 * 100.0 / total_records)),
+								Column::Lift | Column::Estimate => None,
+							}
+						)
+				);
+
+				if let Some((total_weight, weighted)) = weighted {
+					let weight = weighted[ix].1[row].1;
+
+					cells.extend(
+						self.columns
+							.iter()
+							.filter_map(
+								|column| match column {
+									Column::Support => Some(Cell::Weight(weight)),
+									Column::Percentage => Some(Cell::Percentage(weight * 100.0 / total_weight)),
+									Column::Lift | Column::Estimate => None,
+								}
+							)
+					);
+				}
+
+				self.format.row(&mut writer, &cells)?;
+			}
+		}
+
+		self.format.footer(&mut writer, caption)?;
+
+		writer.flush()
+	}
+}
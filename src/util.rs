@@ -17,7 +17,7 @@ where R: io::BufRead
 	}
 
 
-	pub fn read_line<'a>(&'a mut self) -> io::Result<Option<&'a [u8]>> {
+	pub fn read_line(&mut self) -> io::Result<Option<&[u8]>> {
 		self.buffer.clear();
 
 		let count = self.reader.read_until(b'\n', &mut self.buffer)?;
@@ -33,3 +33,21 @@ where R: io::BufRead
 		)
 	}
 }
+
+
+/// Log the peak resident memory of the process, where available (Linux).
+pub fn log_peak_memory() {
+	let status = match std::fs::read_to_string("/proc/self/status") {
+		Ok(status) => status,
+		Err(_) => return,
+	};
+
+	let peak = status
+		.lines()
+		.find_map(|line| line.strip_prefix("VmHWM:"))
+		.and_then(|value| value.trim().trim_end_matches("kB").trim().parse::<f64>().ok());
+
+	if let Some(peak) = peak {
+		log::info!("Peak memory: {:.1} MiB", peak / 1024.0);
+	}
+}